@@ -0,0 +1,123 @@
+// Códigos de barras GTIN (EAN-13, UPC-A, EAN-8, GTIN-14).
+//
+// El dígito verificador es el último: se ponderan los demás dígitos de
+// derecha a izquierda con 3, 1, 3, 1... y se completa la suma hasta la
+// decena siguiente. Un UPC-A es un EAN-13 con un cero adelante, así que la
+// búsqueda usa la clave GTIN-14 (rellenada con ceros a la izquierda) y un
+// mismo artículo se encuentra escaneado de cualquiera de las dos formas.
+// Otros códigos (Code 128 de proveedores, etiquetas propias) se guardan tal
+// cual, sin verificación.
+
+pub const KIND_EAN13: &str = "ean13";
+pub const KIND_UPCA: &str = "upca";
+pub const KIND_EAN8: &str = "ean8";
+pub const KIND_GTIN14: &str = "gtin14";
+pub const KIND_OTHER: &str = "otro";
+
+const MAX_LENGTH: usize = 48;
+
+// Lo que envía un lector o se tipea, sin espacios. Los guiones solo se
+// quitan si el resto son dígitos ("400-6381-33393-1")
+pub fn normalize(code: &str) -> String {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        code.replace('-', "")
+    } else {
+        code
+    }
+}
+
+// Dígito verificador para `digits` (el código sin su último dígito)
+pub fn check_digit(digits: &str) -> Option<u8> {
+    let mut sum = 0u32;
+    for (i, c) in digits.chars().rev().enumerate() {
+        let d = c.to_digit(10)?;
+        sum += if i % 2 == 0 { d * 3 } else { d };
+    }
+    Some(((10 - sum % 10) % 10) as u8)
+}
+
+fn gtin_kind(len: usize) -> Option<&'static str> {
+    match len {
+        8 => Some(KIND_EAN8),
+        12 => Some(KIND_UPCA),
+        13 => Some(KIND_EAN13),
+        14 => Some(KIND_GTIN14),
+        _ => None,
+    }
+}
+
+// Tipo de un código ya normalizado. Los numéricos con largo de GTIN deben
+// tener el dígito verificador correcto; None si el código no es válido.
+pub fn classify(code: &str) -> Option<&'static str> {
+    if code.is_empty() || code.len() > MAX_LENGTH || !code.chars().all(|c| c.is_ascii_graphic()) {
+        return None;
+    }
+    let numeric = code.chars().all(|c| c.is_ascii_digit());
+    match gtin_kind(code.len()).filter(|_| numeric) {
+        Some(kind) => {
+            let (body, last) = code.split_at(code.len() - 1);
+            (check_digit(body)? == last.parse::<u8>().ok()?).then_some(kind)
+        }
+        None => Some(KIND_OTHER),
+    }
+}
+
+// Clave única de búsqueda: GTIN-14 para los GTIN, el código para el resto
+pub fn lookup_key(code: &str) -> String {
+    match classify(code) {
+        Some(KIND_OTHER) | None => code.to_string(),
+        Some(_) => format!("{:0>14}", code),
+    }
+}
+
+// EAN-13 interno: prefijo + secuencia rellenada con ceros + verificador.
+// None si el prefijo no es numérico o la secuencia no entra en 12 dígitos.
+pub fn internal_ean13(prefix: &str, sequence: u64) -> Option<String> {
+    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_digit()) || prefix.len() >= 12 {
+        return None;
+    }
+    let width = 12 - prefix.len();
+    let sequence = sequence.to_string();
+    if sequence.len() > width {
+        return None;
+    }
+    let body = format!("{}{:0>width$}", prefix, sequence, width = width);
+    let check = check_digit(&body)?;
+    Some(format!("{}{}", body, check))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_check_digits() {
+        assert_eq!(check_digit("400638133393"), Some(1));
+        assert_eq!(classify("4006381333931"), Some(KIND_EAN13));
+        assert_eq!(classify("4006381333932"), None);
+        assert_eq!(classify("036000291452"), Some(KIND_UPCA));
+        assert_eq!(classify("96385074"), Some(KIND_EAN8));
+        assert_eq!(classify("ABC-123"), Some(KIND_OTHER));
+        assert_eq!(classify("12345"), Some(KIND_OTHER));
+        assert_eq!(classify(""), None);
+        assert_eq!(normalize(" 400 6381-333931 "), "4006381333931");
+        assert_eq!(normalize("CREA-300"), "CREA-300");
+    }
+
+    #[test]
+    fn upc_and_ean_share_the_lookup_key() {
+        assert_eq!(lookup_key("036000291452"), lookup_key("0036000291452"));
+        assert_eq!(lookup_key("036000291452"), "00036000291452");
+        assert_eq!(lookup_key("ABC123"), "ABC123");
+    }
+
+    #[test]
+    fn generates_internal_ean13() {
+        let code = internal_ean13("20", 42).unwrap();
+        assert_eq!(code, "2000000000428");
+        assert_eq!(classify(&code), Some(KIND_EAN13));
+        assert_eq!(internal_ean13("20", 10_000_000_000), None);
+        assert_eq!(internal_ean13("2A", 1), None);
+    }
+}
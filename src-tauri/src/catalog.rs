@@ -0,0 +1,1153 @@
+// Catálogo de productos: imágenes, importación desde CSV, productos padre
+// con sus variantes y códigos de barras.
+
+use crate::error::AppError;
+use crate::money::Money;
+use crate::{barcode, csv, media};
+use crate::{
+    get_setting, in_transaction, insert_product, product_from_row, receive_stock_cost, set_setting, update_product_row,
+    AppState, Product, PRODUCT_COLUMNS,
+};
+use rusqlite::{Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+// ============================================
+// PRODUCT IMAGE COMMANDS
+// ============================================
+//
+// Las imágenes de productos se copian a la carpeta de medios, junto a la
+// base de datos (ver media.rs). Un mismo archivo puede ser la imagen de
+// varios productos; se borra cuando ya ningún producto lo usa.
+
+// Carpeta de medios, relativa como vitasport.db
+pub const MEDIA_DIR: &str = "media";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductImage {
+    pub product_id: i32,
+    /// Valor guardado en `products.image_path`
+    pub image_path: String,
+    /// Rutas absolutas para mostrar la imagen y su miniatura
+    pub path: String,
+    pub thumbnail_path: String,
+}
+
+pub fn product_image_path(conn: &Connection, product_id: i64) -> Result<Option<String>, AppError> {
+    conn.query_row("SELECT image_path FROM products WHERE id=?1", [product_id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })
+}
+
+fn product_image(media_dir: &Path, product_id: i32, image_path: &str) -> ProductImage {
+    let absolute = |p: &str| {
+        if !media::is_managed(p) {
+            return p.to_string();
+        }
+        let joined = media_dir.join(p);
+        std::path::absolute(&joined).unwrap_or(joined).to_string_lossy().to_string()
+    };
+    // Sin miniatura (imagen que no se pudo decodificar) se usa la original
+    let thumbnail = media::thumbnail_path(image_path);
+    let thumbnail = if media_dir.join(&thumbnail).exists() { thumbnail } else { image_path.to_string() };
+    ProductImage {
+        product_id,
+        image_path: image_path.to_string(),
+        path: absolute(image_path),
+        thumbnail_path: absolute(&thumbnail),
+    }
+}
+
+// Borra el archivo si es de la carpeta de medios y ningún producto lo usa
+pub fn release_image(conn: &Connection, media_dir: &Path, image_path: Option<String>) -> Result<(), AppError> {
+    let path = match image_path {
+        Some(path) if media::is_managed(&path) => path,
+        _ => return Ok(()),
+    };
+    let used: i64 = conn.query_row("SELECT COUNT(*) FROM products WHERE image_path=?1", [&path], |row| row.get(0))?;
+    if used == 0 {
+        media::remove(media_dir, &path)?;
+    }
+    Ok(())
+}
+
+fn attach_image(conn: &Connection, media_dir: &Path, product_id: i32, source: &Path) -> Result<ProductImage, AppError> {
+    let old_path = product_image_path(conn, product_id as i64)?;
+    let bytes = fs::read(source)?;
+    if bytes.len() > media::MAX_BYTES {
+        return Err(AppError::validation("image"));
+    }
+    let image_path = media::store(media_dir, &bytes)?.ok_or(AppError::validation("image"))?;
+    conn.execute("UPDATE products SET image_path=?1 WHERE id=?2", rusqlite::params![image_path, product_id])?;
+    if old_path.as_deref() != Some(image_path.as_str()) {
+        release_image(conn, media_dir, old_path)?;
+    }
+    Ok(product_image(media_dir, product_id, &image_path))
+}
+
+// Imagen elegida con una ruta externa (productos anteriores o cargados sin
+// `attach_product_image`): si el archivo existe se copia a la carpeta de
+// medios. Si no existe o no es una imagen se deja la ruta como estaba.
+pub fn import_product_image(conn: &Connection, media_dir: &Path, product_id: i64) -> Result<(), AppError> {
+    let external = match product_image_path(conn, product_id)? {
+        Some(path) if !path.is_empty() && !media::is_managed(&path) => path,
+        _ => return Ok(()),
+    };
+    let bytes = match fs::read(&external) {
+        Ok(bytes) if bytes.len() <= media::MAX_BYTES => bytes,
+        _ => return Ok(()),
+    };
+    if let Some(image_path) = media::store(media_dir, &bytes)? {
+        conn.execute("UPDATE products SET image_path=?1 WHERE id=?2", rusqlite::params![image_path, product_id])?;
+    }
+    Ok(())
+}
+
+pub fn import_external_images(conn: &Connection, media_dir: &Path) -> Result<(), AppError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM products WHERE image_path IS NOT NULL AND image_path NOT LIKE '{}/%'",
+        media::PRODUCTS_DIR
+    ))?;
+    let ids = stmt.query_map([], |row| row.get::<_, i64>(0))?.collect::<Result<Vec<_>, _>>()?;
+    for id in ids {
+        import_product_image(conn, media_dir, id)?;
+    }
+    Ok(())
+}
+
+// Copia la imagen elegida a la carpeta de medios y la asigna al producto
+#[tauri::command]
+pub fn attach_product_image(state: State<AppState>, product_id: i32, source_path: String) -> Result<ProductImage, AppError> {
+    let conn = state.db.lock()?;
+    attach_image(&conn, Path::new(MEDIA_DIR), product_id, Path::new(&source_path))
+}
+
+#[tauri::command]
+pub fn get_product_image(state: State<AppState>, product_id: i32) -> Result<Option<ProductImage>, AppError> {
+    let conn = state.db.lock()?;
+    let image_path = product_image_path(&conn, product_id as i64)?;
+    Ok(image_path
+        .filter(|p| !p.is_empty())
+        .map(|p| product_image(Path::new(MEDIA_DIR), product_id, &p)))
+}
+
+#[tauri::command]
+pub fn remove_product_image(state: State<AppState>, product_id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let old_path = product_image_path(&conn, product_id as i64)?;
+    conn.execute("UPDATE products SET image_path=NULL WHERE id=?1", [product_id])?;
+    release_image(&conn, Path::new(MEDIA_DIR), old_path)
+}
+
+// ============================================
+// PRODUCT IMPORT COMMANDS
+// ============================================
+//
+// Carga masiva de productos desde un CSV con las columnas de
+// `export_inventory_report` (sobran columnas o faltan, salvo `name`). Cada
+// fila actualiza el producto del mismo SKU (o del mismo `id` si la fila no
+// tiene SKU) o crea uno nuevo. Primero se valida todo el archivo; solo si no
+// hay errores y se pide aplicar, los cambios se guardan en una única
+// transacción. En las actualizaciones, las columnas que no están en el
+// archivo conservan su valor y una celda vacía borra el dato.
+
+const IMPORT_COLUMNS: [&str; 16] = [
+    "id", "sku", "name", "sale_price", "brand", "category", "presentation", "flavor", "weight", "expiry_date",
+    "lot_number", "min_stock", "max_stock", "location", "status", "current_stock",
+];
+
+// Categorías del formulario de productos; también se aceptan las que ya
+// tienen los productos cargados
+const PRODUCT_CATEGORIES: [&str; 7] =
+    ["Suplementos", "Proteínas", "Aminoácidos", "Energéticos", "Vitaminas", "Snacks", "Accesorios"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRowError {
+    /// Línea del archivo (el encabezado es la 1)
+    pub line: usize,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRow {
+    pub line: usize,
+    /// "crear" o "actualizar"
+    pub action: String,
+    pub product_id: Option<i32>,
+    pub sku: Option<String>,
+    pub name: String,
+    /// Stock inicial de un producto nuevo (columna `current_stock`)
+    pub initial_stock: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductImportReport {
+    pub creates: usize,
+    pub updates: usize,
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<ImportRowError>,
+    /// false en la simulación o si hubo errores (no se guardó nada)
+    pub applied: bool,
+}
+
+// Filas válidas con el producto a guardar y errores de las demás
+pub struct ImportPlan {
+    pub rows: Vec<(ImportRow, Product)>,
+    pub errors: Vec<ImportRowError>,
+}
+
+fn import_error(line: usize, field: &str, message: impl Into<String>) -> ImportRowError {
+    ImportRowError { line, field: field.to_string(), message: message.into() }
+}
+
+// "19.99" o "19,99"
+fn parse_import_money(text: &str) -> Option<Money> {
+    let value: f64 = text.replace(',', ".").parse().ok()?;
+    Money::from_major(value).filter(|m| *m >= Money::ZERO)
+}
+
+fn parse_import_count(text: &str) -> Option<i64> {
+    text.parse().ok().filter(|n| *n >= 0)
+}
+
+// Valida el archivo y arma los productos a crear o actualizar
+pub fn plan_product_import(
+    conn: &Connection,
+    text: &str,
+    with_stock: bool,
+) -> Result<ImportPlan, AppError> {
+    let mut errors = Vec::new();
+    let records = match csv::parse(text, csv::detect_delimiter(text)) {
+        Ok(records) => records,
+        Err(e) => return Ok(ImportPlan { rows: Vec::new(), errors: vec![import_error(e.line, "csv", "Comillas sin cerrar")] }),
+    };
+    let (header, rows) = match records.split_first() {
+        Some(split) => split,
+        None => return Ok(ImportPlan { rows: Vec::new(), errors: vec![import_error(1, "csv", "El archivo está vacío")] }),
+    };
+    let columns: Vec<Option<&str>> = header
+        .fields
+        .iter()
+        .map(|name| IMPORT_COLUMNS.iter().copied().find(|c| c.eq_ignore_ascii_case(name.trim())))
+        .collect();
+    if !columns.contains(&Some("name")) {
+        return Ok(ImportPlan { rows: Vec::new(), errors: vec![import_error(header.line, "name", "Falta la columna name")] });
+    }
+
+    let mut categories: Vec<String> = PRODUCT_CATEGORIES.iter().map(|c| c.to_string()).collect();
+    let mut stmt = conn.prepare("SELECT DISTINCT category FROM products WHERE TRIM(COALESCE(category, '')) != ''")?;
+    categories.extend(stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?);
+
+    let mut planned = Vec::new();
+    let mut seen_skus: Vec<(String, usize)> = Vec::new();
+    for record in rows {
+        let line = record.line;
+        let mut row_errors = Vec::new();
+        // Some(None) = columna presente con la celda vacía
+        let cell = |column: &str| -> Option<Option<String>> {
+            let index = columns.iter().position(|c| *c == Some(column))?;
+            let value = record.fields.get(index).map(|v| v.trim().to_string()).unwrap_or_default();
+            Some((!value.is_empty()).then_some(value))
+        };
+
+        let sku = cell("sku").flatten();
+        if let Some(sku) = &sku {
+            match seen_skus.iter().find(|(s, _)| s == sku) {
+                Some((_, first)) => row_errors.push(import_error(line, "sku", format!("SKU repetido (línea {})", first))),
+                None => seen_skus.push((sku.clone(), line)),
+            }
+        }
+        let existing_id: Option<i32> = match (&sku, cell("id").flatten()) {
+            (Some(sku), _) => match conn.query_row("SELECT id FROM products WHERE sku=?1", [sku], |row| row.get(0)) {
+                Ok(id) => Some(id),
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(e) => return Err(e.into()),
+            },
+            (None, Some(id)) => match id.parse::<i32>() {
+                Ok(id) => match conn.query_row("SELECT id FROM products WHERE id=?1", [id], |row| row.get(0)) {
+                    Ok(id) => Some(id),
+                    Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                    Err(e) => return Err(e.into()),
+                },
+                Err(_) => {
+                    row_errors.push(import_error(line, "id", format!("Número no válido: {}", id)));
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+
+        let mut product = match existing_id {
+            Some(id) => conn.query_row(
+                &format!("SELECT {} FROM products WHERE id=?1", PRODUCT_COLUMNS),
+                [id],
+                product_from_row,
+            )?,
+            None => Product {
+                id: None,
+                sku: None,
+                name: String::new(),
+                sale_price: None,
+                brand: None,
+                category: None,
+                presentation: None,
+                flavor: None,
+                weight: None,
+                image_path: None,
+                expiry_date: None,
+                lot_number: None,
+                min_stock: None,
+                max_stock: None,
+                location: None,
+                status: None,
+                default_bin_id: None,
+                parent_id: None,
+            },
+        };
+
+        if let Some(sku) = cell("sku") {
+            product.sku = sku;
+        }
+        if let Some(name) = cell("name") {
+            product.name = name.unwrap_or_default();
+        }
+        if product.name.is_empty() {
+            row_errors.push(import_error(line, "name", "El nombre es obligatorio"));
+        }
+        if let Some(price) = cell("sale_price") {
+            product.sale_price = match price.as_deref().map(|p| (p, parse_import_money(p))) {
+                Some((_, Some(price))) => Some(price),
+                Some((text, None)) => {
+                    row_errors.push(import_error(line, "sale_price", format!("Precio no válido: {}", text)));
+                    None
+                }
+                None => None,
+            };
+        }
+        for (column, field) in [("min_stock", &mut product.min_stock), ("max_stock", &mut product.max_stock)] {
+            if let Some(value) = cell(column) {
+                *field = match value.as_deref().map(|v| (v, v.parse::<i32>().ok().filter(|n| *n >= 0))) {
+                    Some((_, Some(n))) => Some(n),
+                    Some((text, None)) => {
+                        row_errors.push(import_error(line, column, format!("Cantidad no válida: {}", text)));
+                        None
+                    }
+                    None => None,
+                };
+            }
+        }
+        if let Some(category) = cell("category") {
+            product.category = match category {
+                Some(text) => match categories.iter().find(|c| c.to_lowercase() == text.to_lowercase()) {
+                    Some(known) => Some(known.clone()),
+                    None => {
+                        row_errors.push(import_error(line, "category", format!("Categoría desconocida: {}", text)));
+                        None
+                    }
+                },
+                None => None,
+            };
+        }
+        if let Some(Some(date)) = cell("expiry_date") {
+            let valid = date.len() == 10
+                && date.char_indices().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+            if !valid {
+                row_errors.push(import_error(line, "expiry_date", format!("Fecha no válida (AAAA-MM-DD): {}", date)));
+            }
+        }
+        for (column, field) in [
+            ("brand", &mut product.brand),
+            ("presentation", &mut product.presentation),
+            ("flavor", &mut product.flavor),
+            ("weight", &mut product.weight),
+            ("expiry_date", &mut product.expiry_date),
+            ("lot_number", &mut product.lot_number),
+            ("status", &mut product.status),
+        ] {
+            if let Some(value) = cell(column) {
+                *field = value;
+            }
+        }
+        // El bin se busca o se crea por su código al guardar
+        if let Some(location) = cell("location") {
+            product.location = location;
+            product.default_bin_id = None;
+        }
+
+        let mut initial_stock = 0;
+        if let Some(Some(stock)) = cell("current_stock") {
+            match parse_import_count(&stock) {
+                Some(n) if with_stock && existing_id.is_none() => initial_stock = n,
+                Some(_) => {}
+                None => row_errors.push(import_error(line, "current_stock", format!("Cantidad no válida: {}", stock))),
+            }
+        }
+
+        if row_errors.is_empty() {
+            let row = ImportRow {
+                line,
+                action: if existing_id.is_some() { "actualizar" } else { "crear" }.to_string(),
+                product_id: existing_id,
+                sku: product.sku.clone(),
+                name: product.name.clone(),
+                initial_stock,
+            };
+            planned.push((row, product));
+        } else {
+            errors.extend(row_errors);
+        }
+    }
+    Ok(ImportPlan { rows: planned, errors })
+}
+
+pub fn import_products(conn: &Connection, text: &str, apply: bool, with_stock: bool) -> Result<ProductImportReport, AppError> {
+    let ImportPlan { rows: planned, errors } = plan_product_import(conn, text, with_stock)?;
+    let applied = apply && errors.is_empty() && !planned.is_empty();
+    if applied {
+        in_transaction(conn, || {
+            for (row, product) in &planned {
+                if product.id.is_some() {
+                    update_product_row(conn, product)?;
+                    continue;
+                }
+                let id = insert_product(conn, product)?;
+                if row.initial_stock > 0 {
+                    conn.execute(
+                        "INSERT INTO stock_movements (product_id, type, quantity, note, location_id)
+                         VALUES (?1, 'ingreso', ?2, 'Stock inicial (importación)', (SELECT id FROM locations WHERE is_default = 1))",
+                        rusqlite::params![id, row.initial_stock],
+                    )?;
+                    receive_stock_cost(conn, id as i32, conn.last_insert_rowid(), row.initial_stock, None)?;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    let rows: Vec<ImportRow> = planned.into_iter().map(|(row, _)| row).collect();
+    Ok(ProductImportReport {
+        creates: rows.iter().filter(|r| r.product_id.is_none()).count(),
+        updates: rows.iter().filter(|r| r.product_id.is_some()).count(),
+        rows,
+        errors,
+        applied,
+    })
+}
+
+// Con `apply = false` solo valida y devuelve lo que haría. `initial_stock`
+// carga la columna `current_stock` como stock de los productos nuevos.
+#[tauri::command]
+pub fn import_products_csv(
+    state: State<AppState>,
+    path: String,
+    apply: bool,
+    initial_stock: Option<bool>,
+) -> Result<ProductImportReport, AppError> {
+    // Excel en Windows guarda en Windows-1252 si no se elige UTF-8
+    let text = csv::decode(fs::read(&path)?);
+    let conn = state.db.lock()?;
+    import_products(&conn, &text, apply, initial_stock.unwrap_or(false))
+}
+
+// ============================================
+// PRODUCT VARIANT COMMANDS
+// ============================================
+//
+// Un producto padre ("Whey Gold", Optimum) agrupa variantes de sabor,
+// presentación o peso. Cada variante es una fila de `products` con su propio
+// SKU, precio y stock; el padre solo guarda los datos comunes y sirve para
+// agregar stock y reportes.
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParentProduct {
+    pub id: Option<i32>,
+    pub name: String,
+    pub brand: Option<String>,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParentProductSummary {
+    pub id: i32,
+    pub name: String,
+    pub brand: Option<String>,
+    pub category: Option<String>,
+    pub variant_count: i64,
+    /// Stock sumado de todas las variantes
+    pub total_stock: i64,
+    pub min_price: Option<Money>,
+    pub max_price: Option<Money>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductVariant {
+    pub id: i32,
+    pub sku: Option<String>,
+    pub name: String,
+    pub flavor: Option<String>,
+    pub presentation: Option<String>,
+    pub weight: Option<String>,
+    pub sale_price: Option<Money>,
+    pub status: Option<String>,
+    pub current_stock: i64,
+}
+
+// Misma clave de agrupación que la migración: nombre y marca sin importar
+// mayúsculas ni espacios
+const PARENT_KEY_SQL: &str = "LOWER(TRIM(pp.name)) = LOWER(TRIM(?1)) AND LOWER(TRIM(COALESCE(pp.brand, ''))) = LOWER(TRIM(COALESCE(?2, '')))";
+
+// Los productos anteriores a las variantes se agrupan por nombre y marca:
+// un padre por cada combinación, con los datos del producto más antiguo
+pub fn migrate_product_parents(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "INSERT INTO parent_products (name, brand, category)
+         SELECT TRIM(p.name), NULLIF(TRIM(p.brand), ''), NULLIF(TRIM(p.category), '')
+         FROM products p
+         WHERE p.id IN (SELECT MIN(id) FROM products WHERE parent_id IS NULL
+                        GROUP BY LOWER(TRIM(name)), LOWER(TRIM(COALESCE(brand, ''))))
+           AND NOT EXISTS (
+             SELECT 1 FROM parent_products pp
+             WHERE LOWER(TRIM(pp.name)) = LOWER(TRIM(p.name))
+               AND LOWER(TRIM(COALESCE(pp.brand, ''))) = LOWER(TRIM(COALESCE(p.brand, ''))))
+         ORDER BY p.id",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE products SET parent_id = (
+             SELECT pp.id FROM parent_products pp
+             WHERE LOWER(TRIM(pp.name)) = LOWER(TRIM(products.name))
+               AND LOWER(TRIM(COALESCE(pp.brand, ''))) = LOWER(TRIM(COALESCE(products.brand, '')))
+             ORDER BY pp.id LIMIT 1)
+         WHERE parent_id IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+// Padre de un producto a guardar: el indicado, el que ya tenía o, si es
+// nuevo, el de igual nombre y marca (que se crea si no existe)
+pub fn resolve_parent_product(conn: &Connection, product: &Product) -> Result<i64, AppError> {
+    if let Some(parent_id) = product.parent_id {
+        load_parent_product(conn, parent_id)?;
+        return Ok(parent_id as i64);
+    }
+    if let Some(id) = product.id {
+        let current: Option<i64> = conn
+            .query_row("SELECT parent_id FROM products WHERE id=?1", [id], |row| row.get(0))
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+                other => other.into(),
+            })?;
+        if let Some(parent_id) = current {
+            return Ok(parent_id);
+        }
+    }
+    let existing = conn.query_row(
+        &format!("SELECT pp.id FROM parent_products pp WHERE {} ORDER BY pp.id LIMIT 1", PARENT_KEY_SQL),
+        rusqlite::params![product.name, product.brand],
+        |row| row.get(0),
+    );
+    match existing {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            conn.execute(
+                "INSERT INTO parent_products (name, brand, category) VALUES (?1, ?2, ?3)",
+                rusqlite::params![product.name.trim(), product.brand, product.category],
+            )?;
+            Ok(conn.last_insert_rowid())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Borra el padre si se quedó sin variantes
+pub fn delete_orphan_parent(conn: &Connection, parent_id: Option<i64>) -> Result<(), AppError> {
+    conn.execute(
+        "DELETE FROM parent_products WHERE id=?1 AND NOT EXISTS (SELECT 1 FROM products WHERE parent_id=?1)",
+        [parent_id],
+    )?;
+    Ok(())
+}
+
+fn load_parent_product(conn: &Connection, id: i32) -> Result<ParentProduct, AppError> {
+    conn.query_row(
+        "SELECT id, name, brand, category FROM parent_products WHERE id=?1",
+        [id],
+        |row| {
+            Ok(ParentProduct {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                brand: row.get(2)?,
+                category: row.get(3)?,
+            })
+        },
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("parent_product"),
+        other => other.into(),
+    })
+}
+
+#[tauri::command]
+pub fn get_parent_products(state: State<AppState>) -> Result<Vec<ParentProductSummary>, AppError> {
+    let conn = state.db.lock()?;
+    parent_products(&conn)
+}
+
+fn parent_products(conn: &Connection) -> Result<Vec<ParentProductSummary>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT pp.id, pp.name, pp.brand, pp.category, COUNT(p.id),
+                COALESCE(SUM((SELECT SUM(CASE WHEN m.type='ingreso' THEN m.quantity WHEN m.type='egreso' THEN -m.quantity ELSE 0 END)
+                              FROM stock_movements m WHERE m.product_id = p.id)), 0),
+                MIN(p.sale_price), MAX(p.sale_price)
+         FROM parent_products pp LEFT JOIN products p ON p.parent_id = pp.id
+         GROUP BY pp.id
+         ORDER BY pp.name, pp.brand",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(ParentProductSummary {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                brand: row.get(2)?,
+                category: row.get(3)?,
+                variant_count: row.get(4)?,
+                total_stock: row.get(5)?,
+                min_price: row.get(6)?,
+                max_price: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+pub fn get_product_variants(state: State<AppState>, parent_id: i32) -> Result<Vec<ProductVariant>, AppError> {
+    let conn = state.db.lock()?;
+    product_variants(&conn, parent_id)
+}
+
+fn product_variants(conn: &Connection, parent_id: i32) -> Result<Vec<ProductVariant>, AppError> {
+    load_parent_product(conn, parent_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.sku, p.name, p.flavor, p.presentation, p.weight, p.sale_price, p.status,
+                COALESCE((SELECT SUM(CASE WHEN m.type='ingreso' THEN m.quantity WHEN m.type='egreso' THEN -m.quantity ELSE 0 END)
+                          FROM stock_movements m WHERE m.product_id = p.id), 0)
+         FROM products p
+         WHERE p.parent_id = ?1
+         ORDER BY p.flavor, p.presentation, p.weight, p.id",
+    )?;
+    let rows = stmt
+        .query_map([parent_id], |row| {
+            Ok(ProductVariant {
+                id: row.get(0)?,
+                sku: row.get(1)?,
+                name: row.get(2)?,
+                flavor: row.get(3)?,
+                presentation: row.get(4)?,
+                weight: row.get(5)?,
+                sale_price: row.get(6)?,
+                status: row.get(7)?,
+                current_stock: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+pub fn add_parent_product(state: State<AppState>, parent: ParentProduct) -> Result<i64, AppError> {
+    if parent.name.trim().is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    let conn = state.db.lock()?;
+    conn.execute(
+        "INSERT INTO parent_products (name, brand, category) VALUES (?1, ?2, ?3)",
+        rusqlite::params![parent.name.trim(), parent.brand, parent.category],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// La marca y la categoría se copian a todas las variantes; el nombre solo a
+// las que tenían el nombre anterior del padre
+#[tauri::command]
+pub fn update_parent_product(state: State<AppState>, parent: ParentProduct) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || update_parent_product_row(&conn, &parent))
+}
+
+fn update_parent_product_row(conn: &Connection, parent: &ParentProduct) -> Result<(), AppError> {
+    let id = parent.id.ok_or(AppError::validation("id"))?;
+    if parent.name.trim().is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    let current = load_parent_product(conn, id)?;
+    conn.execute(
+        "UPDATE products SET name=?1 WHERE parent_id=?2 AND name=?3",
+        rusqlite::params![parent.name.trim(), id, current.name],
+    )?;
+    conn.execute(
+        "UPDATE products SET brand=?1, category=?2 WHERE parent_id=?3",
+        rusqlite::params![parent.brand, parent.category, id],
+    )?;
+    conn.execute(
+        "UPDATE parent_products SET name=?1, brand=?2, category=?3 WHERE id=?4",
+        rusqlite::params![parent.name.trim(), parent.brand, parent.category, id],
+    )?;
+    Ok(())
+}
+
+// Solo padres sin variantes; las variantes se mueven a otro padre con
+// `update_product` (parent_id) o se borran antes
+#[tauri::command]
+pub fn delete_parent_product(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    load_parent_product(&conn, id)?;
+    let variants: i64 = conn.query_row("SELECT COUNT(*) FROM products WHERE parent_id=?1", [id], |row| row.get(0))?;
+    if variants > 0 {
+        return Err(AppError::validation("variants"));
+    }
+    conn.execute("DELETE FROM parent_products WHERE id=?1", [id])?;
+    Ok(())
+}
+
+// ============================================
+// BARCODE COMMANDS
+// ============================================
+//
+// Un producto puede tener varios códigos de barras (el del fabricante, el de
+// otro proveedor, uno interno). Los EAN/UPC se validan por dígito
+// verificador (ver barcode.rs). Los internos son EAN-13 con el prefijo del
+// ajuste `barcode_prefix` (por defecto "20", rango GS1 de uso interno) y una
+// secuencia correlativa.
+
+const DEFAULT_BARCODE_PREFIX: &str = "20";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductBarcode {
+    pub id: i32,
+    pub product_id: i32,
+    pub code: String,
+    /// "ean13", "upca", "ean8", "gtin14" u "otro"
+    pub kind: String,
+    /// Generado por el sistema
+    pub internal: bool,
+    pub created_at: String,
+}
+
+const BARCODE_COLUMNS: &str = "id, product_id, code, kind, internal, created_at";
+
+fn barcode_from_row(row: &rusqlite::Row) -> Result<ProductBarcode> {
+    Ok(ProductBarcode {
+        id: row.get(0)?,
+        product_id: row.get(1)?,
+        code: row.get(2)?,
+        kind: row.get(3)?,
+        internal: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+// Los SKU que ya eran un EAN/UPC válido pasan a ser el código de barras del
+// producto
+pub fn migrate_product_barcodes(conn: &Connection) -> Result<()> {
+    let skus: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, sku FROM products p
+             WHERE sku IS NOT NULL AND NOT EXISTS (SELECT 1 FROM product_barcodes b WHERE b.product_id = p.id)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    for (product_id, sku) in skus {
+        let code = barcode::normalize(&sku);
+        match barcode::classify(&code) {
+            Some(barcode::KIND_OTHER) | None => continue,
+            Some(kind) => {
+                conn.execute(
+                    "INSERT OR IGNORE INTO product_barcodes (product_id, code, kind, lookup_key) VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![product_id, code, kind, barcode::lookup_key(&code)],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn barcode_taken(conn: &Connection, key: &str) -> Result<bool> {
+    conn.query_row("SELECT COUNT(*) > 0 FROM product_barcodes WHERE lookup_key=?1", [key], |row| row.get(0))
+}
+
+pub fn insert_barcode(conn: &Connection, product_id: i32, code: &str, internal: bool) -> Result<i64, AppError> {
+    let code = barcode::normalize(code);
+    let kind = barcode::classify(&code).ok_or(AppError::validation("barcode"))?;
+    let exists: bool = conn.query_row("SELECT COUNT(*) > 0 FROM products WHERE id=?1", [product_id], |row| row.get(0))?;
+    if !exists {
+        return Err(AppError::not_found("product"));
+    }
+    let key = barcode::lookup_key(&code);
+    if barcode_taken(conn, &key)? {
+        return Err(AppError::conflict("barcode"));
+    }
+    conn.execute(
+        "INSERT INTO product_barcodes (product_id, code, kind, lookup_key, internal) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![product_id, code, kind, key, internal],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn load_barcode(conn: &Connection, id: i64) -> Result<ProductBarcode, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM product_barcodes WHERE id=?1", BARCODE_COLUMNS),
+        [id],
+        barcode_from_row,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("barcode"),
+        other => other.into(),
+    })
+}
+
+#[tauri::command]
+pub fn get_product_barcodes(state: State<AppState>, product_id: i32) -> Result<Vec<ProductBarcode>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM product_barcodes WHERE product_id=?1 ORDER BY id",
+        BARCODE_COLUMNS
+    ))?;
+    let rows = stmt
+        .query_map([product_id], barcode_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+pub fn add_product_barcode(state: State<AppState>, product_id: i32, code: String) -> Result<ProductBarcode, AppError> {
+    let conn = state.db.lock()?;
+    let id = insert_barcode(&conn, product_id, &code, false)?;
+    load_barcode(&conn, id)
+}
+
+#[tauri::command]
+pub fn delete_product_barcode(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let deleted = conn.execute("DELETE FROM product_barcodes WHERE id=?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("barcode"));
+    }
+    Ok(())
+}
+
+// Entrada del lector en caja. Un UPC-A se encuentra también escaneado como
+// EAN-13 y viceversa; si ningún código coincide se prueba con el SKU.
+#[tauri::command]
+pub fn find_product_by_barcode(state: State<AppState>, code: String) -> Result<Product, AppError> {
+    let conn = state.db.lock()?;
+    product_by_barcode(&conn, &code)
+}
+
+fn product_by_barcode(conn: &Connection, code: &str) -> Result<Product, AppError> {
+    let code = barcode::normalize(code);
+    let by_barcode = conn.query_row(
+        &format!(
+            "SELECT {} FROM products WHERE id = (SELECT product_id FROM product_barcodes WHERE lookup_key=?1)",
+            PRODUCT_COLUMNS
+        ),
+        [barcode::lookup_key(&code)],
+        product_from_row,
+    );
+    match by_barcode {
+        Ok(product) => return Ok(product),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {}
+        Err(e) => return Err(e.into()),
+    }
+    conn.query_row(
+        &format!("SELECT {} FROM products WHERE sku=?1 LIMIT 1", PRODUCT_COLUMNS),
+        [&code],
+        product_from_row,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+        other => other.into(),
+    })
+}
+
+fn barcode_prefix(conn: &Connection) -> Result<String> {
+    Ok(get_setting(conn, "barcode_prefix")?.unwrap_or_else(|| DEFAULT_BARCODE_PREFIX.to_string()))
+}
+
+#[tauri::command]
+pub fn get_barcode_prefix(state: State<AppState>) -> Result<String, AppError> {
+    let conn = state.db.lock()?;
+    Ok(barcode_prefix(&conn)?)
+}
+
+// De 2 a 9 dígitos; la secuencia ocupa el resto hasta 12
+#[tauri::command]
+pub fn set_barcode_prefix(state: State<AppState>, prefix: String) -> Result<(), AppError> {
+    let prefix = prefix.trim();
+    if !(2..=9).contains(&prefix.len()) || !prefix.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::validation("barcode_prefix"));
+    }
+    let conn = state.db.lock()?;
+    set_setting(&conn, "barcode_prefix", prefix)?;
+    Ok(())
+}
+
+// Asigna al producto el siguiente EAN-13 interno libre
+#[tauri::command]
+pub fn generate_product_barcode(state: State<AppState>, product_id: i32) -> Result<ProductBarcode, AppError> {
+    let conn = state.db.lock()?;
+    let id = in_transaction(&conn, || generate_internal_barcode(&conn, product_id))?;
+    load_barcode(&conn, id)
+}
+
+fn generate_internal_barcode(conn: &Connection, product_id: i32) -> Result<i64, AppError> {
+    let prefix = barcode_prefix(conn)?;
+    let mut sequence: u64 = get_setting(conn, "barcode_next_sequence")?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
+    loop {
+        let code = barcode::internal_ean13(&prefix, sequence).ok_or(AppError::validation("barcode_prefix"))?;
+        sequence += 1;
+        if !barcode_taken(conn, &barcode::lookup_key(&code))? {
+            let id = insert_barcode(conn, product_id, &code, true)?;
+            set_setting(conn, "barcode_next_sequence", &sequence.to_string())?;
+            return Ok(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{seed_stock, test_db};
+    use crate::{inventory_valuation, margin_report, product_stock, write_backup};
+
+    #[test]
+    fn variants_grouped_under_parent_products() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, name, brand, category, flavor, weight, sale_price, average_cost)
+             VALUES (1, 'Whey Gold', 'Optimum', 'Proteínas', 'Chocolate', '5lb', 30000, 20000),
+                    (2, 'whey gold ', 'OPTIMUM', 'Proteínas', 'Vainilla', '2lb', 15000, 10000),
+                    (3, 'Whey Gold', 'Otra', 'Proteínas', NULL, NULL, 12000, 8000);",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 4);
+        seed_stock(&conn, 2, 6);
+        seed_stock(&conn, 3, 1);
+        migrate_product_parents(&conn).unwrap();
+
+        let parents = parent_products(&conn).unwrap();
+        let summary: Vec<(Option<String>, i64, i64)> =
+            parents.iter().map(|p| (p.brand.clone(), p.variant_count, p.total_stock)).collect();
+        assert_eq!(summary, vec![(Some("Optimum".to_string()), 2, 10), (Some("Otra".to_string()), 1, 1)]);
+        assert_eq!(
+            (parents[0].min_price, parents[0].max_price),
+            (Some(Money::from_cents(15000)), Some(Money::from_cents(30000)))
+        );
+        let optimum = parents[0].id;
+        let variants: Vec<(i32, Option<String>, i64)> = product_variants(&conn, optimum)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.id, v.flavor, v.current_stock))
+            .collect();
+        assert_eq!(variants, vec![(1, Some("Chocolate".to_string()), 4), (2, Some("Vainilla".to_string()), 6)]);
+
+        // Reportes a nivel de padre
+        conn.execute_batch(
+            "INSERT INTO sales (product_id, quantity, sale_price, cost_amount, sale_date) VALUES
+                 (1, 1, 30000, 20000, '2024-03-01'), (2, 2, 30000, 20000, '2024-03-01'), (3, 1, 12000, 8000, '2024-03-01');",
+        )
+        .unwrap();
+        let margins: Vec<(String, i64, Money)> = margin_report(&conn, "padre", None, None)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.key, r.units, r.gross_margin))
+            .collect();
+        assert_eq!(
+            margins,
+            vec![(optimum.to_string(), 3, Money::from_cents(20000)), (parents[1].id.to_string(), 1, Money::from_cents(4000))]
+        );
+        let valuation = inventory_valuation(&conn, "padre", None).unwrap();
+        assert_eq!(valuation.rows.len(), 2);
+        assert_eq!(valuation.rows.iter().find(|r| r.key == optimum.to_string()).unwrap().units, 10);
+
+        // Mover la última variante a otro padre borra el que queda vacío
+        update_parent_product_row(
+            &conn,
+            &ParentProduct { id: Some(optimum), name: "Gold Standard".to_string(), brand: Some("Optimum".to_string()), category: None },
+        )
+        .unwrap();
+        let name: String = conn.query_row("SELECT name FROM products WHERE id=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(name, "Gold Standard");
+        conn.execute("UPDATE products SET parent_id=?1 WHERE id=3", [optimum]).unwrap();
+        delete_orphan_parent(&conn, Some(parents[1].id as i64)).unwrap();
+        assert!(load_parent_product(&conn, parents[1].id).is_err());
+        assert_eq!(product_variants(&conn, optimum).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn barcodes_lookup_and_internal_generation() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, sku, name) VALUES (1, '036000291452', 'Whey'), (2, 'CREA-300', 'Creatina'), (3, NULL, 'Shaker');",
+        )
+        .unwrap();
+        migrate_product_barcodes(&conn).unwrap();
+
+        // El UPC-A del SKU se encuentra también escaneado como EAN-13
+        assert_eq!(product_by_barcode(&conn, "0036000291452").unwrap().id, Some(1));
+        assert_eq!(product_by_barcode(&conn, "CREA-300").unwrap().id, Some(2));
+        assert!(matches!(product_by_barcode(&conn, "4006381333931"), Err(AppError::NotFound { .. })));
+
+        insert_barcode(&conn, 2, "4006381333931", false).unwrap();
+        assert_eq!(product_by_barcode(&conn, "400 6381 333931").unwrap().id, Some(2));
+        assert!(matches!(insert_barcode(&conn, 3, "4006381333932", false), Err(AppError::Validation { .. })));
+        assert!(matches!(insert_barcode(&conn, 3, "4006381333931", false), Err(AppError::Conflict { .. })));
+
+        // Los internos saltan los códigos ya usados
+        set_setting(&conn, "barcode_prefix", "29").unwrap();
+        let taken = barcode::internal_ean13("29", 1).unwrap();
+        insert_barcode(&conn, 1, &taken, false).unwrap();
+        let id = generate_internal_barcode(&conn, 3).unwrap();
+        let generated = load_barcode(&conn, id).unwrap();
+        assert_eq!(generated.code, barcode::internal_ean13("29", 2).unwrap());
+        assert!(generated.internal);
+        assert_eq!(product_by_barcode(&conn, &generated.code).unwrap().name, "Shaker");
+        let next = load_barcode(&conn, generate_internal_barcode(&conn, 3).unwrap()).unwrap();
+        assert_eq!(next.code, barcode::internal_ean13("29", 3).unwrap());
+    }
+
+    #[test]
+    fn product_images_are_deduplicated_and_backed_up() {
+        let dir = std::env::temp_dir().join(format!("vitasport_media_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let media_dir = dir.join(MEDIA_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let photo = dir.join("foto.jpg");
+        fs::write(&photo, [0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3]).unwrap();
+        let other = dir.join("otra.jpg");
+        fs::write(&other, [0xFF, 0xD8, 0xFF, 0xE0, 4, 5, 6]).unwrap();
+        fs::write(dir.join("notas.txt"), "hola").unwrap();
+
+        let conn = test_db();
+        conn.execute(
+            "INSERT INTO products (id, name, image_path) VALUES (1, 'Whey', NULL), (2, 'Creatina', NULL), (3, 'Shaker', ?1)",
+            [other.to_string_lossy()],
+        )
+        .unwrap();
+
+        // La misma foto para dos productos se guarda una vez
+        let first = attach_image(&conn, &media_dir, 1, &photo).unwrap();
+        let second = attach_image(&conn, &media_dir, 2, &photo).unwrap();
+        assert_eq!(first.image_path, second.image_path);
+        assert!(first.image_path.starts_with("products/"));
+        assert!(Path::new(&first.path).is_absolute());
+        assert_eq!(fs::read_dir(media_dir.join(media::PRODUCTS_DIR)).unwrap().count(), 1);
+        assert!(matches!(
+            attach_image(&conn, &media_dir, 1, &dir.join("notas.txt")),
+            Err(AppError::Validation { .. })
+        ));
+        assert!(matches!(attach_image(&conn, &media_dir, 9, &photo), Err(AppError::NotFound { .. })));
+
+        // La ruta externa se copia a la carpeta de medios
+        import_external_images(&conn, &media_dir).unwrap();
+        let imported = product_image_path(&conn, 3).unwrap().unwrap();
+        assert!(media_dir.join(&imported).exists());
+
+        let backup = dir.join("respaldo.tar.gz");
+        write_backup(&conn, &media_dir, &backup).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(&backup).unwrap()));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert!(names.contains(&"vitasport.db".to_string()));
+        assert!(names.contains(&format!("{}/{}", MEDIA_DIR, first.image_path)));
+        assert!(names.contains(&format!("{}/{}", MEDIA_DIR, imported)));
+
+        // El archivo se borra cuando ya ningún producto lo usa
+        let shared = media_dir.join(&first.image_path);
+        conn.execute("DELETE FROM products WHERE id=1", []).unwrap();
+        release_image(&conn, &media_dir, Some(first.image_path.clone())).unwrap();
+        assert!(shared.exists());
+        attach_image(&conn, &media_dir, 2, &other).unwrap();
+        assert!(!shared.exists());
+        assert!(media_dir.join(&imported).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn product_import_validates_then_applies_atomically() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, sku, name, sale_price, brand, category, min_stock)
+                 VALUES (1, 'WHEY-1', 'Whey', 10000, 'Optimum', 'Proteínas', 5);",
+        )
+        .unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        let bad = "sku;name;sale_price;category;current_stock\n\
+                   WHEY-1;Whey Gold;120,50;proteínas;\n\
+                   CREA-1;Creatina;abc;Suplementos;10\n\
+                   CREA-1;Creatina 2;10;Ropa;\n\
+                   ;;5;;\n\
+                   CARO-1;Caro;99999999999999999999;Suplementos;\n";
+        let report = import_products(&conn, bad, true, true).unwrap();
+        assert!(!report.applied);
+        let fields: Vec<(usize, &str)> = report.errors.iter().map(|e| (e.line, e.field.as_str())).collect();
+        assert_eq!(fields, vec![(3, "sale_price"), (4, "sku"), (4, "category"), (5, "name"), (6, "sale_price")]);
+        assert_eq!(report.updates, 1);
+        // Con errores no se guarda nada
+        assert_eq!(count("SELECT COUNT(*) FROM products"), 1);
+        assert_eq!(count("SELECT sale_price FROM products WHERE id=1"), 10000);
+
+        // Encabezado del export de inventario, con columnas de más
+        let good = "id,sku,name,sale_price,brand,category,min_stock,location,current_stock,extra\n\
+                    1,WHEY-1,Whey Gold,\"120.50\",,proteínas,,A-01,99,x\n\
+                    ,CREA-1,\"Creatina \"\"Pro\"\"\",45,MuscleTech,Suplementos,2,,12,x\n";
+        let dry = import_products(&conn, good, false, true).unwrap();
+        assert!(!dry.applied);
+        assert!(dry.errors.is_empty());
+        assert_eq!((dry.creates, dry.updates), (1, 1));
+        assert_eq!(dry.rows[1].initial_stock, 12);
+        assert_eq!(count("SELECT COUNT(*) FROM products"), 1);
+
+        let report = import_products(&conn, good, true, true).unwrap();
+        assert!(report.applied);
+        let whey = conn
+            .query_row(&format!("SELECT {} FROM products WHERE id=1", PRODUCT_COLUMNS), [], product_from_row)
+            .unwrap();
+        assert_eq!(whey.name, "Whey Gold");
+        assert_eq!(whey.sale_price, Some(Money::from_cents(12050)));
+        assert_eq!(whey.category.as_deref(), Some("Proteínas"));
+        assert_eq!(whey.brand, None);
+        assert_eq!(whey.min_stock, None);
+        assert_eq!(whey.location.as_deref(), Some("A-01"));
+        // El stock de los productos existentes no cambia
+        assert_eq!(product_stock(&conn, 1).unwrap(), 0);
+        let creatine: i32 = conn.query_row("SELECT id FROM products WHERE sku='CREA-1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(product_stock(&conn, creatine).unwrap(), 12);
+        assert_eq!(
+            conn.query_row("SELECT name FROM products WHERE id=?1", [creatine], |row| row.get::<_, String>(0)).unwrap(),
+            "Creatina \"Pro\""
+        );
+
+        // Una fila que falla al guardar deshace toda la importación
+        conn.execute_batch(
+            "CREATE TRIGGER import_fails BEFORE INSERT ON products WHEN NEW.sku = 'BOOM'
+             BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+        )
+        .unwrap();
+        let failing = "sku,name\nNEW-1,Nuevo\nBOOM,Falla\n";
+        assert!(import_products(&conn, failing, true, false).is_err());
+        assert_eq!(count("SELECT COUNT(*) FROM products WHERE sku='NEW-1'"), 0);
+    }
+}
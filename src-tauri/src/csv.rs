@@ -0,0 +1,235 @@
+// Lectura y escritura de archivos CSV (RFC 4180).
+//
+// Los campos pueden ir entre comillas dobles, con `""` para una comilla y
+// saltos de línea dentro. Se acepta `,` o `;` como separador (Excel en
+// español guarda con `;`), fin de línea CRLF o LF y la marca BOM de UTF-8
+// al principio. Los archivos que no son UTF-8 se leen como Windows-1252, la
+// codificación "ANSI" de Excel en Windows.
+//
+// Al escribir solo se ponen comillas en los campos que las necesitan y cada
+// registro termina en CRLF. La marca BOM es opcional: sin ella Excel en
+// Windows lee el archivo como ANSI y rompe los acentos.
+
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    /// Línea del archivo donde empieza el registro (desde 1)
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// Comillas sin cerrar en el registro que empieza en `line`
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+// Windows-1252 en 0x80-0x9F (comillas tipográficas, €, guiones...). Los
+// cinco códigos sin asignar quedan como el carácter de control equivalente.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+// Texto del archivo: UTF-8 si es válido y si no Windows-1252
+pub fn decode(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e
+            .into_bytes()
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            })
+            .collect(),
+    }
+}
+
+// Separador según el encabezado: `;` si aparece más veces que `,`
+pub fn detect_delimiter(text: &str) -> char {
+    let header = text.lines().next().unwrap_or("");
+    if header.matches(';').count() > header.matches(',').count() {
+        ';'
+    } else {
+        ','
+    }
+}
+
+pub fn parse(text: &str, delimiter: char) -> Result<Vec<Record>, ParseError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+
+    // Cierra el registro; las líneas vacías no cuentan
+    let mut end_record = |fields: &mut Vec<String>, field: &mut String, start: usize| {
+        fields.push(std::mem::take(field));
+        if fields.len() > 1 || !fields[0].is_empty() {
+            records.push(Record { line: start, fields: std::mem::take(fields) });
+        }
+        fields.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            // Una comilla en medio de un campo sin comillas se toma literal
+            '"' if field.is_empty() => in_quotes = true,
+            c if c == delimiter => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                end_record(&mut fields, &mut field, start);
+                line += 1;
+                start = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(ParseError { line: start });
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        end_record(&mut fields, &mut field, start);
+    }
+    Ok(records)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteOptions {
+    pub delimiter: char,
+    /// Marca BOM de UTF-8 al principio
+    pub bom: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions { delimiter: ',', bom: false }
+    }
+}
+
+// Entre comillas si tiene el separador, comillas o saltos de línea
+fn write_field(out: &mut String, field: &str, delimiter: char) {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+pub fn write<R: AsRef<[String]>>(records: &[R], options: WriteOptions) -> String {
+    let mut out = String::new();
+    if options.bom {
+        out.push('\u{feff}');
+    }
+    for record in records {
+        for (i, field) in record.as_ref().iter().enumerate() {
+            if i > 0 {
+                out.push(options.delimiter);
+            }
+            write_field(&mut out, field, options.delimiter);
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(records: &[Record]) -> Vec<Vec<&str>> {
+        records.iter().map(|r| r.fields.iter().map(String::as_str).collect()).collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields_and_line_numbers() {
+        let text = "\u{feff}sku,name,brand\r\nA1,\"Whey \"\"Gold\"\"\",Optimum\r\n\r\nA2,\"Creatina\n300 g\",\r\nA3,,\"a,b\"";
+        let records = parse(text, detect_delimiter(text)).unwrap();
+        assert_eq!(
+            fields(&records),
+            vec![
+                vec!["sku", "name", "brand"],
+                vec!["A1", "Whey \"Gold\"", "Optimum"],
+                vec!["A2", "Creatina\n300 g", ""],
+                vec!["A3", "", "a,b"],
+            ]
+        );
+        let lines: Vec<usize> = records.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn detects_semicolons_and_unclosed_quotes() {
+        let text = "sku;name;sale_price\nA1;Whey;19,99\n";
+        assert_eq!(detect_delimiter(text), ';');
+        assert_eq!(fields(&parse(text, ';').unwrap())[1], vec!["A1", "Whey", "19,99"]);
+        assert_eq!(parse("sku,name\nA1,\"Whey\n", ','), Err(ParseError { line: 2 }));
+    }
+
+    #[test]
+    fn decodes_windows_1252_when_not_utf8() {
+        assert_eq!(decode("Proteína “Gold” – 5€".as_bytes().to_vec()), "Proteína “Gold” – 5€");
+        // "Proteína “Gold” – 5€" guardado como ANSI por Excel
+        let ansi = b"Prote\xedna \x93Gold\x94 \x96 5\x80".to_vec();
+        assert_eq!(decode(ansi), "Proteína “Gold” – 5€");
+        assert_eq!(decode(vec![b'a', 0x81, 0xD1]), "a\u{81}Ñ");
+    }
+
+    #[test]
+    fn writes_quoted_fields_only_when_needed() {
+        let records = vec![
+            vec!["sku".to_string(), "name".to_string(), "price".to_string()],
+            vec!["A1".to_string(), "Whey, 5lb".to_string(), "19.99".to_string()],
+            vec!["A2".to_string(), "Barra \"Choco\"".to_string(), String::new()],
+        ];
+        assert_eq!(
+            write(&records, WriteOptions::default()),
+            "sku,name,price\r\nA1,\"Whey, 5lb\",19.99\r\nA2,\"Barra \"\"Choco\"\"\",\r\n"
+        );
+        // Con `;` la coma ya no necesita comillas
+        let semicolon = write(&records, WriteOptions { delimiter: ';', bom: true });
+        assert!(semicolon.starts_with("\u{feff}sku;name;price\r\nA1;Whey, 5lb;19.99\r\n"));
+    }
+
+    #[test]
+    fn written_records_parse_back_unchanged() {
+        let records: Vec<Vec<String>> = [
+            vec!["id", "name", "note"],
+            vec!["1", "Whey, 5lb", "línea 1\nlínea 2"],
+            vec!["2", "\"Gold\" Standard", "a;b"],
+            vec!["3", "", " con espacios "],
+            vec!["4", "\r\n", "\""],
+        ]
+        .iter()
+        .map(|r| r.iter().map(|f| f.to_string()).collect())
+        .collect();
+        for delimiter in [',', ';'] {
+            for bom in [false, true] {
+                let text = write(&records, WriteOptions { delimiter, bom });
+                assert_eq!(detect_delimiter(text.trim_start_matches('\u{feff}')), delimiter);
+                let parsed: Vec<Vec<String>> = parse(&text, delimiter).unwrap().into_iter().map(|r| r.fields).collect();
+                assert_eq!(parsed, records);
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// Error común para todos los comandos Tauri.
+//
+// Se serializa al frontend como un objeto con un `code` estable y un `message`
+// ya traducido, más los campos propios de cada variante, por ejemplo:
+// { "code": "INSUFFICIENT_STOCK", "message": "Stock insuficiente...", "available": 2, "requested": 5 }
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::PoisonError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Es,
+    En,
+}
+
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+/// Cambia el idioma usado en los mensajes de error ("es" o "en").
+/// Cualquier otro valor vuelve al español.
+pub fn set_lang(code: &str) {
+    let value = match code.to_ascii_lowercase().as_str() {
+        "en" => 1,
+        _ => 0,
+    };
+    LANG.store(value, Ordering::Relaxed);
+}
+
+pub fn current_lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::En,
+        _ => Lang::Es,
+    }
+}
+
+#[derive(Debug)]
+pub enum AppError {
+    /// El registro pedido no existe (`resource` es un identificador como "product" o "user")
+    NotFound { resource: &'static str },
+    /// Ya existe un registro con el mismo valor en un campo único
+    Conflict { field: String },
+    /// Un dato de entrada no es válido
+    Validation { field: String },
+    InsufficientStock { available: i64, requested: i64 },
+    Unauthorized,
+    Database(String),
+    Io(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn not_found(resource: &'static str) -> Self {
+        AppError::NotFound { resource }
+    }
+
+    pub fn conflict(field: impl Into<String>) -> Self {
+        AppError::Conflict { field: field.into() }
+    }
+
+    pub fn validation(field: impl Into<String>) -> Self {
+        AppError::Validation { field: field.into() }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::Conflict { .. } => "CONFLICT",
+            AppError::Validation { .. } => "VALIDATION",
+            AppError::InsufficientStock { .. } => "INSUFFICIENT_STOCK",
+            AppError::Unauthorized => "UNAUTHORIZED",
+            AppError::Database(_) => "DATABASE",
+            AppError::Io(_) => "IO",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    pub fn message(&self, lang: Lang) -> String {
+        match (self, lang) {
+            (AppError::NotFound { resource }, Lang::Es) => {
+                format!("No se encontró {}", resource_name(resource, lang))
+            }
+            (AppError::NotFound { resource }, Lang::En) => {
+                format!("{} not found", resource_name(resource, lang))
+            }
+            (AppError::Conflict { field }, Lang::Es) if field == "sku" => {
+                "El SKU ya existe. Usa otro SKU o edita el producto existente.".to_string()
+            }
+            (AppError::Conflict { field }, Lang::En) if field == "sku" => {
+                "The SKU already exists. Use another SKU or edit the existing product.".to_string()
+            }
+            (AppError::Conflict { field }, Lang::Es) => {
+                format!("Ya existe un registro con ese valor de {}", field_name(field, lang))
+            }
+            (AppError::Conflict { field }, Lang::En) => {
+                format!("A record with that {} already exists", field_name(field, lang))
+            }
+            (AppError::Validation { field }, Lang::Es) => {
+                format!("Valor inválido en el campo {}", field_name(field, lang))
+            }
+            (AppError::Validation { field }, Lang::En) => {
+                format!("Invalid value for field {}", field_name(field, lang))
+            }
+            (AppError::InsufficientStock { available, requested }, Lang::Es) => {
+                format!("Stock insuficiente. Disponible: {}, solicitado: {}", available, requested)
+            }
+            (AppError::InsufficientStock { available, requested }, Lang::En) => {
+                format!("Insufficient stock. Available: {}, requested: {}", available, requested)
+            }
+            (AppError::Unauthorized, Lang::Es) => "Usuario o contraseña incorrectos".to_string(),
+            (AppError::Unauthorized, Lang::En) => "Invalid username or password".to_string(),
+            (AppError::Database(detail), Lang::Es) => format!("Error de base de datos: {}", detail),
+            (AppError::Database(detail), Lang::En) => format!("Database error: {}", detail),
+            (AppError::Io(detail), Lang::Es) => format!("Error de archivo: {}", detail),
+            (AppError::Io(detail), Lang::En) => format!("File error: {}", detail),
+            (AppError::Internal(detail), Lang::Es) => format!("Error interno: {}", detail),
+            (AppError::Internal(detail), Lang::En) => format!("Internal error: {}", detail),
+        }
+    }
+}
+
+fn resource_name(resource: &str, lang: Lang) -> String {
+    let name = match (resource, lang) {
+        ("product", Lang::Es) => "el producto",
+        ("user", Lang::Es) => "el usuario",
+        ("sale", Lang::Es) => "la venta",
+        ("downloads_dir", Lang::Es) => "la carpeta Descargas",
+        ("scheduled_price", Lang::Es) => "el cambio de precio programado",
+        ("promotion", Lang::Es) => "la promoción",
+        ("customer", Lang::Es) => "el cliente",
+        ("loyalty_rule", Lang::Es) => "la regla de puntos",
+        ("cash_session", Lang::Es) => "el turno de caja",
+        ("expense_category", Lang::Es) => "la categoría de gasto",
+        ("recurring_expense", Lang::Es) => "el gasto recurrente",
+        ("pending_expense", Lang::Es) => "el gasto pendiente",
+        ("location", Lang::Es) => "la ubicación",
+        ("transfer", Lang::Es) => "la transferencia",
+        ("bin", Lang::Es) => "el bin",
+        ("parent_product", Lang::Es) => "el producto padre",
+        ("barcode", Lang::Es) => "el código de barras",
+        ("ticket", Lang::Es) => "el ticket",
+        ("product", Lang::En) => "Product",
+        ("user", Lang::En) => "User",
+        ("sale", Lang::En) => "Sale",
+        ("downloads_dir", Lang::En) => "Downloads folder",
+        ("scheduled_price", Lang::En) => "Scheduled price change",
+        ("promotion", Lang::En) => "Promotion",
+        ("customer", Lang::En) => "Customer",
+        ("loyalty_rule", Lang::En) => "Loyalty rule",
+        ("cash_session", Lang::En) => "Cash session",
+        ("expense_category", Lang::En) => "Expense category",
+        ("recurring_expense", Lang::En) => "Recurring expense",
+        ("pending_expense", Lang::En) => "Pending expense",
+        ("location", Lang::En) => "Location",
+        ("transfer", Lang::En) => "Transfer",
+        ("bin", Lang::En) => "Bin",
+        ("parent_product", Lang::En) => "Parent product",
+        ("barcode", Lang::En) => "Barcode",
+        ("ticket", Lang::En) => "Ticket",
+        (other, _) => other,
+    };
+    name.to_string()
+}
+
+fn field_name(field: &str, lang: Lang) -> String {
+    let name = match (field, lang) {
+        ("username", Lang::Es) => "usuario",
+        ("quantity", Lang::Es) => "cantidad",
+        ("sale_price", Lang::Es) => "precio de venta",
+        ("amount", Lang::Es) => "monto",
+        ("movement_type", Lang::Es) => "tipo de movimiento",
+        ("discount", Lang::Es) => "descuento",
+        ("price", Lang::Es) => "precio",
+        ("date", Lang::Es) => "fecha",
+        ("starts_at", Lang::Es) => "fecha de inicio",
+        ("ends_at", Lang::Es) => "fecha de fin",
+        ("lines", Lang::Es) => "líneas",
+        ("name", Lang::Es) => "nombre",
+        ("kind", Lang::Es) => "tipo",
+        ("percent", Lang::Es) => "porcentaje",
+        ("buy_qty", Lang::Es) => "cantidad lleva/paga",
+        ("bundle_items", Lang::Es) => "productos del combo",
+        ("bundle_price", Lang::Es) => "precio del combo",
+        ("document_id", Lang::Es) => "documento",
+        ("email", Lang::Es) => "email",
+        ("customer_id", Lang::Es) => "cliente",
+        ("redeem_points", Lang::Es) => "puntos a canjear",
+        ("points_per_unit", Lang::Es) => "puntos por unidad",
+        ("point_value", Lang::Es) => "valor del punto",
+        ("expiry_days", Lang::Es) => "días de vigencia",
+        ("ticket_id", Lang::Es) => "ticket",
+        ("payments", Lang::Es) => "pagos",
+        ("method", Lang::Es) => "forma de pago",
+        ("payment_method", Lang::Es) => "forma de pago",
+        ("register", Lang::Es) => "caja",
+        ("opening_float", Lang::Es) => "fondo inicial",
+        ("session_id", Lang::Es) => "turno de caja",
+        ("counts", Lang::Es) => "arqueo",
+        ("bucket", Lang::Es) => "agrupación",
+        ("start_date", Lang::Es) => "fecha desde",
+        ("end_date", Lang::Es) => "fecha hasta",
+        ("parent_id", Lang::Es) => "categoría padre",
+        ("category_id", Lang::Es) => "categoría",
+        ("frequency", Lang::Es) => "frecuencia",
+        ("every", Lang::Es) => "intervalo",
+        ("costing_method", Lang::Es) => "método de costeo",
+        ("purchase_price", Lang::Es) => "precio de compra",
+        ("group_by", Lang::Es) => "agrupación",
+        ("as_of", Lang::Es) => "fecha de corte",
+        ("location_id", Lang::Es) => "ubicación",
+        ("from_location_id", Lang::Es) => "ubicación de origen",
+        ("to_location_id", Lang::Es) => "ubicación de destino",
+        ("branch", Lang::Es) => "sucursal",
+        ("is_default", Lang::Es) => "ubicación por defecto",
+        ("status", Lang::Es) => "estado",
+        ("code", Lang::Es) => "código",
+        ("level", Lang::Es) => "nivel",
+        ("active", Lang::Es) => "activo",
+        ("bin_id", Lang::Es) => "bin",
+        ("default_bin_id", Lang::Es) => "bin por defecto",
+        ("to_bin_id", Lang::Es) => "bin de destino",
+        ("variants", Lang::Es) => "variantes",
+        ("barcode", Lang::Es) => "código de barras",
+        ("barcode_prefix", Lang::Es) => "prefijo de códigos de barras",
+        ("template", Lang::Es) => "plantilla",
+        ("style", Lang::Es) => "estilo",
+        ("format", Lang::Es) => "formato",
+        ("delimiter", Lang::Es) => "separador",
+        ("product_ids", Lang::Es) => "productos",
+        ("copies", Lang::Es) => "copias",
+        ("store_name", Lang::Es) => "nombre del comercio",
+        ("paper_width", Lang::Es) => "ancho del papel",
+        ("image", Lang::Es) => "imagen",
+        (other, _) => other,
+    };
+    name.to_string()
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(current_lang()))
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.message(current_lang()))?;
+        match self {
+            AppError::NotFound { resource } => map.serialize_entry("resource", resource)?,
+            AppError::Conflict { field } | AppError::Validation { field } => {
+                map.serialize_entry("field", field)?
+            }
+            AppError::InsufficientStock { available, requested } => {
+                map.serialize_entry("available", available)?;
+                map.serialize_entry("requested", requested)?;
+            }
+            AppError::Unauthorized
+            | AppError::Database(_)
+            | AppError::Io(_)
+            | AppError::Internal(_) => {}
+        }
+        map.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        match &e {
+            rusqlite::Error::SqliteFailure(err, Some(msg))
+                if err.code == rusqlite::ErrorCode::ConstraintViolation
+                    && msg.starts_with("UNIQUE constraint failed: ") =>
+            {
+                // "UNIQUE constraint failed: products.sku" -> "sku"
+                let column = msg
+                    .trim_start_matches("UNIQUE constraint failed: ")
+                    .split(',')
+                    .next()
+                    .and_then(|c| c.trim().rsplit('.').next())
+                    .unwrap_or("")
+                    .to_string();
+                AppError::Conflict { field: column }
+            }
+            _ => AppError::Database(e.to_string()),
+        }
+    }
+}
+
+impl<T> From<PoisonError<T>> for AppError {
+    fn from(e: PoisonError<T>) -> Self {
+        AppError::Database(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e.to_string())
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<printpdf::Error> for AppError {
+    fn from(e: printpdf::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<rust_xlsxwriter::XlsxError> for AppError {
+    fn from(e: rust_xlsxwriter::XlsxError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<std::time::SystemTimeError> for AppError {
+    fn from(e: std::time::SystemTimeError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
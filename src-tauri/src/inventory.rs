@@ -0,0 +1,1172 @@
+// Inventario por ubicación: ubicaciones, transferencias entre ubicaciones
+// y sucursales, y bins del depósito.
+
+use crate::error::AppError;
+use crate::{in_transaction, AppState, Product, TicketLine};
+use rusqlite::{Connection, Result};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+// ============================================
+// LOCATION COMMANDS
+// ============================================
+//
+// Ubicaciones de inventario (salón, depósito, otra sucursal). Cada
+// movimiento de stock pertenece a una ubicación; los que no la indican van
+// a la ubicación por defecto. Una transferencia registra en la misma
+// transacción la salida del origen y la entrada al destino. Entre
+// sucursales distintas la mercadería pasa por la ubicación de sistema
+// "En tránsito" hasta que el destino la recibe con `receive_transfer`.
+
+const LOCATION_KINDS: [&str; 2] = ["tienda", "almacen"];
+// Ubicación de sistema para transferencias entre sucursales
+const TRANSIT_KIND: &str = "transito";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub id: Option<i32>,
+    pub name: String,
+    /// "tienda" o "almacen" ("transito" solo la de sistema)
+    pub kind: String,
+    /// Sucursal a la que pertenece; las transferencias entre sucursales
+    /// distintas quedan en tránsito
+    pub branch: String,
+    #[serde(default)]
+    pub is_default: bool,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocationBalance {
+    pub product_id: i32,
+    pub location_id: i32,
+    pub location_name: String,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewTransfer {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub from_location_id: i32,
+    pub to_location_id: i32,
+    pub note: Option<String>,
+    pub created_by: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockTransfer {
+    pub id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+    pub from_location_id: i32,
+    pub to_location_id: i32,
+    /// "en_transito", "recibida" o "cancelada"
+    pub status: String,
+    pub note: Option<String>,
+    pub created_by: Option<i32>,
+    pub created_at: String,
+    pub received_by: Option<i32>,
+    pub received_at: Option<String>,
+}
+
+const LOCATION_COLUMNS: &str = "id, name, kind, branch, is_default, active";
+
+fn location_from_row(row: &rusqlite::Row) -> Result<Location> {
+    Ok(Location {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        kind: row.get(2)?,
+        branch: row.get(3)?,
+        is_default: row.get(4)?,
+        active: row.get(5)?,
+    })
+}
+
+fn load_location(conn: &Connection, id: i32) -> Result<Location, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM locations WHERE id=?1", LOCATION_COLUMNS),
+        [id],
+        location_from_row,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("location"),
+        other => other.into(),
+    })
+}
+
+// Ubicación de un movimiento: la indicada (debe estar activa) o la por defecto
+pub fn resolve_location(conn: &Connection, explicit: Option<i32>) -> Result<i64, AppError> {
+    if let Some(id) = explicit {
+        let location = load_location(conn, id)?;
+        if !location.active || location.kind == TRANSIT_KIND {
+            return Err(AppError::validation("location_id"));
+        }
+        return Ok(id as i64);
+    }
+    Ok(conn.query_row("SELECT id FROM locations WHERE is_default=1", [], |row| row.get(0))?)
+}
+
+pub fn location_stock(conn: &Connection, product_id: i32, location_id: i64) -> Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END),0)
+         FROM stock_movements WHERE product_id=?1 AND location_id=?2",
+        rusqlite::params![product_id, location_id],
+        |row| row.get(0),
+    )
+}
+
+#[tauri::command]
+pub fn get_locations(state: State<AppState>) -> Result<Vec<Location>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM locations ORDER BY kind = '{}', branch, name",
+        LOCATION_COLUMNS, TRANSIT_KIND
+    ))?;
+    let rows = stmt
+        .query_map([], location_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+fn validate_location(location: &Location) -> Result<(), AppError> {
+    if location.name.trim().is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    if !LOCATION_KINDS.contains(&location.kind.as_str()) {
+        return Err(AppError::validation("kind"));
+    }
+    if location.branch.trim().is_empty() {
+        return Err(AppError::validation("branch"));
+    }
+    if location.is_default && !location.active {
+        return Err(AppError::validation("is_default"));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn add_location(state: State<AppState>, location: Location) -> Result<i64, AppError> {
+    validate_location(&location)?;
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        if location.is_default {
+            conn.execute("UPDATE locations SET is_default=0", [])?;
+        }
+        conn.execute(
+            "INSERT INTO locations (name, kind, branch, is_default, active) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                location.name.trim(),
+                location.kind,
+                location.branch.trim(),
+                location.is_default,
+                location.active,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+// Marcar otra ubicación como por defecto desmarca la anterior; la por
+// defecto no se puede desmarcar ni desactivar directamente
+#[tauri::command]
+pub fn update_location(state: State<AppState>, location: Location) -> Result<(), AppError> {
+    let id = location.id.ok_or(AppError::validation("id"))?;
+    validate_location(&location)?;
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        let current = load_location(&conn, id)?;
+        if current.kind == TRANSIT_KIND {
+            return Err(AppError::validation("kind"));
+        }
+        if current.is_default && !location.is_default {
+            return Err(AppError::validation("is_default"));
+        }
+        if location.is_default {
+            conn.execute("UPDATE locations SET is_default=0", [])?;
+        }
+        conn.execute(
+            "UPDATE locations SET name=?1, kind=?2, branch=?3, is_default=?4, active=?5 WHERE id=?6",
+            rusqlite::params![
+                location.name.trim(),
+                location.kind,
+                location.branch.trim(),
+                location.is_default,
+                location.active,
+                id,
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+// Solo se borran ubicaciones sin movimientos; las usadas se desactivan
+#[tauri::command]
+pub fn delete_location(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let location = load_location(&conn, id)?;
+    let used: i64 = conn.query_row("SELECT COUNT(*) FROM stock_movements WHERE location_id=?1", [id], |row| row.get(0))?;
+    if location.is_default || location.kind == TRANSIT_KIND || used > 0 {
+        return Err(AppError::validation("location_id"));
+    }
+    conn.execute("DELETE FROM locations WHERE id=?1", [id])?;
+    Ok(())
+}
+
+// Stock por producto y ubicación (solo combinaciones con saldo distinto de cero)
+#[tauri::command]
+pub fn get_location_balances(
+    state: State<AppState>,
+    product_id: Option<i32>,
+    location_id: Option<i32>,
+) -> Result<Vec<LocationBalance>, AppError> {
+    let conn = state.db.lock()?;
+    location_balances(&conn, product_id, location_id)
+}
+
+fn location_balances(
+    conn: &Connection,
+    product_id: Option<i32>,
+    location_id: Option<i32>,
+) -> Result<Vec<LocationBalance>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT m.product_id, l.id, l.name,
+                SUM(CASE WHEN m.type='ingreso' THEN m.quantity WHEN m.type='egreso' THEN -m.quantity ELSE 0 END) AS qty
+         FROM stock_movements m JOIN locations l ON l.id = m.location_id
+         WHERE (?1 IS NULL OR m.product_id = ?1) AND (?2 IS NULL OR l.id = ?2)
+         GROUP BY m.product_id, l.id
+         HAVING qty != 0
+         ORDER BY m.product_id, l.name",
+    )?;
+    let rows = stmt
+        .query_map(rusqlite::params![product_id, location_id], |row| {
+            Ok(LocationBalance {
+                product_id: row.get(0)?,
+                location_id: row.get(1)?,
+                location_name: row.get(2)?,
+                quantity: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+const TRANSFER_COLUMNS: &str =
+    "id, product_id, quantity, from_location_id, to_location_id, status, note, created_by, created_at, received_by, received_at";
+
+fn transfer_from_row(row: &rusqlite::Row) -> Result<StockTransfer> {
+    Ok(StockTransfer {
+        id: row.get(0)?,
+        product_id: row.get(1)?,
+        quantity: row.get(2)?,
+        from_location_id: row.get(3)?,
+        to_location_id: row.get(4)?,
+        status: row.get(5)?,
+        note: row.get(6)?,
+        created_by: row.get(7)?,
+        created_at: row.get(8)?,
+        received_by: row.get(9)?,
+        received_at: row.get(10)?,
+    })
+}
+
+fn load_transfer(conn: &Connection, id: i32) -> Result<StockTransfer, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM stock_transfers WHERE id=?1", TRANSFER_COLUMNS),
+        [id],
+        transfer_from_row,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("transfer"),
+        other => other.into(),
+    })
+}
+
+#[tauri::command]
+pub fn get_transfers(state: State<AppState>, status: Option<String>) -> Result<Vec<StockTransfer>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stock_transfers WHERE ?1 IS NULL OR status = ?1 ORDER BY created_at DESC, id DESC LIMIT 200",
+        TRANSFER_COLUMNS
+    ))?;
+    let rows = stmt
+        .query_map([status], transfer_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// Una pierna de transferencia. No toca las capas de costo: la mercadería
+// sigue siendo la misma, solo cambia de lugar.
+fn insert_transfer_leg(
+    conn: &Connection,
+    transfer_id: i64,
+    product_id: i32,
+    movement_type: &str,
+    quantity: i32,
+    location_id: i64,
+    created_by: Option<i32>,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id, transfer_id, cost_amount)
+         SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?3 * COALESCE(average_cost, 0) FROM products WHERE id = ?1",
+        rusqlite::params![
+            product_id,
+            movement_type,
+            quantity,
+            format!("Transferencia #{}", transfer_id),
+            created_by,
+            location_id,
+            transfer_id,
+        ],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn transfer_stock(state: State<AppState>, transfer: NewTransfer) -> Result<StockTransfer, AppError> {
+    let conn = state.db.lock()?;
+    let id = in_transaction(&conn, || record_transfer(&conn, &transfer))?;
+    load_transfer(&conn, id as i32)
+}
+
+pub fn record_transfer(conn: &Connection, transfer: &NewTransfer) -> Result<i64, AppError> {
+    if transfer.quantity <= 0 {
+        return Err(AppError::validation("quantity"));
+    }
+    if transfer.from_location_id == transfer.to_location_id {
+        return Err(AppError::validation("to_location_id"));
+    }
+    let from = load_location(conn, transfer.from_location_id)?;
+    let to = load_location(conn, transfer.to_location_id)?;
+    if from.kind == TRANSIT_KIND || !from.active {
+        return Err(AppError::validation("from_location_id"));
+    }
+    if to.kind == TRANSIT_KIND || !to.active {
+        return Err(AppError::validation("to_location_id"));
+    }
+    let available = location_stock(conn, transfer.product_id, transfer.from_location_id as i64)?;
+    if (transfer.quantity as i64) > available {
+        return Err(AppError::InsufficientStock {
+            available,
+            requested: transfer.quantity as i64,
+        });
+    }
+
+    let in_transit = from.branch != to.branch;
+    conn.execute(
+        "INSERT INTO stock_transfers (product_id, quantity, from_location_id, to_location_id, status, note, created_by, received_by, received_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, CASE WHEN ?5 = 'recibida' THEN CURRENT_TIMESTAMP END)",
+        rusqlite::params![
+            transfer.product_id,
+            transfer.quantity,
+            transfer.from_location_id,
+            transfer.to_location_id,
+            if in_transit { "en_transito" } else { "recibida" },
+            transfer.note,
+            transfer.created_by,
+            if in_transit { None } else { transfer.created_by },
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    let destination = if in_transit {
+        conn.query_row("SELECT id FROM locations WHERE kind=?1", [TRANSIT_KIND], |row| row.get(0))?
+    } else {
+        transfer.to_location_id as i64
+    };
+    insert_transfer_leg(conn, id, transfer.product_id, "egreso", transfer.quantity, transfer.from_location_id as i64, transfer.created_by)?;
+    insert_transfer_leg(conn, id, transfer.product_id, "ingreso", transfer.quantity, destination, transfer.created_by)?;
+    Ok(id)
+}
+
+// Recibe en destino una transferencia en tránsito
+#[tauri::command]
+pub fn receive_transfer(state: State<AppState>, transfer_id: i32, received_by: Option<i32>) -> Result<StockTransfer, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || close_transfer(&conn, transfer_id, received_by, true))?;
+    load_transfer(&conn, transfer_id)
+}
+
+// Anula una transferencia en tránsito: la mercadería vuelve al origen
+#[tauri::command]
+pub fn cancel_transfer(state: State<AppState>, transfer_id: i32, cancelled_by: Option<i32>) -> Result<StockTransfer, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || close_transfer(&conn, transfer_id, cancelled_by, false))?;
+    load_transfer(&conn, transfer_id)
+}
+
+fn close_transfer(conn: &Connection, transfer_id: i32, user_id: Option<i32>, received: bool) -> Result<(), AppError> {
+    let transfer = load_transfer(conn, transfer_id)?;
+    if transfer.status != "en_transito" {
+        return Err(AppError::validation("status"));
+    }
+    let transit: i64 = conn.query_row("SELECT id FROM locations WHERE kind=?1", [TRANSIT_KIND], |row| row.get(0))?;
+    let target = if received { transfer.to_location_id } else { transfer.from_location_id };
+    let id = transfer_id as i64;
+    insert_transfer_leg(conn, id, transfer.product_id, "egreso", transfer.quantity, transit, user_id)?;
+    insert_transfer_leg(conn, id, transfer.product_id, "ingreso", transfer.quantity, target as i64, user_id)?;
+    conn.execute(
+        "UPDATE stock_transfers SET status=?1, received_by=?2, received_at=CURRENT_TIMESTAMP WHERE id=?3",
+        rusqlite::params![if received { "recibida" } else { "cancelada" }, user_id, transfer_id],
+    )?;
+    Ok(())
+}
+
+// ============================================
+// BIN COMMANDS
+// ============================================
+//
+// Bins del depósito: cada ubicación se divide en zonas, las zonas en
+// pasillos y los pasillos en estantes. El código completo de un bin es el de
+// sus ancestros unidos por guiones ("A-03-2"). Los movimientos sin bin
+// explícito cuentan para el bin por defecto del producto si está en la misma
+// ubicación; si no, quedan "sin ubicar".
+
+const BIN_LEVELS: [&str; 3] = ["zona", "pasillo", "estante"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bin {
+    pub id: Option<i32>,
+    pub location_id: i32,
+    /// Zona o pasillo que lo contiene; None = zona
+    pub parent_id: Option<i32>,
+    /// "zona", "pasillo" o "estante"; se deduce del padre
+    #[serde(default)]
+    pub level: String,
+    pub code: String,
+    /// Código completo, calculado al guardar
+    #[serde(default)]
+    pub path: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BinBalance {
+    pub product_id: i32,
+    pub location_id: i32,
+    /// None = stock sin ubicar en la ubicación
+    pub bin_id: Option<i32>,
+    pub bin_path: Option<String>,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BinMove {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub from_bin_id: i32,
+    pub to_bin_id: i32,
+    pub created_by: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PickingLine {
+    pub bin_id: Option<i32>,
+    pub bin_path: Option<String>,
+    pub product_id: i32,
+    pub sku: Option<String>,
+    pub name: String,
+    pub quantity: i64,
+}
+
+const BIN_COLUMNS: &str = "id, location_id, parent_id, level, code, path, active";
+
+// Bin al que cuenta un movimiento `m` de un producto cuyo bin por defecto
+// es `d` (LEFT JOIN)
+const MOVEMENT_BIN_SQL: &str = "COALESCE(m.bin_id, CASE WHEN d.location_id = m.location_id THEN d.id END)";
+
+fn bin_from_row(row: &rusqlite::Row) -> Result<Bin> {
+    Ok(Bin {
+        id: row.get(0)?,
+        location_id: row.get(1)?,
+        parent_id: row.get(2)?,
+        level: row.get(3)?,
+        code: row.get(4)?,
+        path: row.get(5)?,
+        active: row.get(6)?,
+    })
+}
+
+pub fn load_bin(conn: &Connection, id: i32) -> Result<Bin, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM bins WHERE id=?1", BIN_COLUMNS),
+        [id],
+        bin_from_row,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("bin"),
+        other => other.into(),
+    })
+}
+
+// Orden natural de códigos: "A-9" va antes que "A-10"
+fn compare_bin_paths(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut left = a.split('-');
+    let mut right = b.split('-');
+    loop {
+        let (x, y) = match (left.next(), right.next()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(n), Ok(m)) => n.cmp(&m),
+            _ => x.cmp(y),
+        };
+        if ord != std::cmp::Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+// Sin bin al final
+fn compare_optional_paths(a: Option<&str>, b: Option<&str>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare_bin_paths(a, b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+// Busca o crea el bin de un código de texto libre ("A-03-2", "A/03/2"): el
+// primer tramo es la zona, el segundo el pasillo y el resto el estante.
+// None si el texto no tiene ningún tramo.
+fn bin_for_code(conn: &Connection, location_id: i64, text: &str) -> Result<Option<i64>> {
+    let parts: Vec<&str> = text.split(['-', '/']).map(str::trim).filter(|p| !p.is_empty()).collect();
+    let mut codes: Vec<String> = parts.iter().take(2).map(|p| p.to_string()).collect();
+    if parts.len() > 2 {
+        codes.push(parts[2..].join("."));
+    }
+    let mut parent: Option<i64> = None;
+    let mut path = String::new();
+    for (code, level) in codes.iter().zip(BIN_LEVELS) {
+        if !path.is_empty() {
+            path.push('-');
+        }
+        path.push_str(code);
+        let existing = conn.query_row(
+            "SELECT id FROM bins WHERE location_id=?1 AND path=?2",
+            rusqlite::params![location_id, path],
+            |row| row.get(0),
+        );
+        parent = Some(match existing {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                conn.execute(
+                    "INSERT INTO bins (location_id, parent_id, level, code, path) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![location_id, parent, level, code, path],
+                )?;
+                conn.last_insert_rowid()
+            }
+            Err(e) => return Err(e),
+        });
+    }
+    Ok(parent)
+}
+
+// La columna de texto libre `products.location` pasa a ser el código del bin
+// por defecto; los textos existentes se convierten en bins de la ubicación
+// por defecto
+pub fn migrate_product_bins(conn: &Connection) -> Result<()> {
+    let location_id: i64 = conn.query_row("SELECT id FROM locations WHERE is_default=1", [], |row| row.get(0))?;
+    let products: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, location FROM products WHERE default_bin_id IS NULL AND TRIM(COALESCE(location, '')) != ''",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    for (product_id, text) in products {
+        let bin_id = bin_for_code(conn, location_id, &text)?;
+        conn.execute(
+            "UPDATE products SET default_bin_id=?1, location=(SELECT path FROM bins WHERE id=?1) WHERE id=?2",
+            rusqlite::params![bin_id, product_id],
+        )?;
+    }
+    Ok(())
+}
+
+// Bin por defecto de un producto a guardar: el indicado, el actual si
+// `location` no cambió, o el del texto de `location` en la ubicación por
+// defecto
+pub fn resolve_product_bin(conn: &Connection, product: &Product) -> Result<Option<i64>, AppError> {
+    if let Some(bin_id) = product.default_bin_id {
+        if !load_bin(conn, bin_id)?.active {
+            return Err(AppError::validation("default_bin_id"));
+        }
+        return Ok(Some(bin_id as i64));
+    }
+    let text = match product.location.as_deref().map(str::trim) {
+        Some(text) if !text.is_empty() => text,
+        _ => return Ok(None),
+    };
+    if let Some(id) = product.id {
+        let current = conn.query_row(
+            "SELECT b.id FROM products p JOIN bins b ON b.id = p.default_bin_id WHERE p.id=?1 AND b.path=?2",
+            rusqlite::params![id, text],
+            |row| row.get(0),
+        );
+        match current {
+            Ok(bin_id) => return Ok(Some(bin_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let location_id = resolve_location(conn, None)?;
+    Ok(bin_for_code(conn, location_id, text)?)
+}
+
+// Cambia el bin por defecto. El stock que contaba para el bin anterior queda
+// fijado en ese bin: la mercadería no se mueve sola.
+pub fn set_product_bin(conn: &Connection, product_id: i64, bin_id: Option<i64>) -> Result<(), AppError> {
+    let current: Option<i64> = conn
+        .query_row("SELECT default_bin_id FROM products WHERE id=?1", [product_id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })?;
+    if let Some(previous) = current.filter(|previous| Some(*previous) != bin_id) {
+        conn.execute(
+            "UPDATE stock_movements SET bin_id=?1
+             WHERE product_id=?2 AND bin_id IS NULL AND location_id = (SELECT location_id FROM bins WHERE id=?1)",
+            rusqlite::params![previous, product_id],
+        )?;
+    }
+    conn.execute(
+        "UPDATE products SET default_bin_id=?1, location=(SELECT path FROM bins WHERE id=?1) WHERE id=?2",
+        rusqlite::params![bin_id, product_id],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_bins(state: State<AppState>, location_id: Option<i32>) -> Result<Vec<Bin>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM bins WHERE ?1 IS NULL OR location_id = ?1",
+        BIN_COLUMNS
+    ))?;
+    let mut rows = stmt
+        .query_map([location_id], bin_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.sort_by(|a, b| a.location_id.cmp(&b.location_id).then_with(|| compare_bin_paths(&a.path, &b.path)));
+    Ok(rows)
+}
+
+fn validate_bin_code(code: &str) -> Result<(), AppError> {
+    let code = code.trim();
+    if code.is_empty() || code.contains(['-', '/']) {
+        return Err(AppError::validation("code"));
+    }
+    Ok(())
+}
+
+fn bin_path_taken(conn: &Connection, location_id: i32, path: &str, except: Option<i32>) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM bins WHERE location_id=?1 AND path=?2 AND id IS NOT ?3",
+        rusqlite::params![location_id, path, except],
+        |row| row.get(0),
+    )
+}
+
+#[tauri::command]
+pub fn add_bin(state: State<AppState>, bin: Bin) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    insert_bin(&conn, &bin)
+}
+
+fn insert_bin(conn: &Connection, bin: &Bin) -> Result<i64, AppError> {
+    validate_bin_code(&bin.code)?;
+    let location = load_location(conn, bin.location_id)?;
+    if location.kind == TRANSIT_KIND {
+        return Err(AppError::validation("location_id"));
+    }
+    let code = bin.code.trim();
+    let (level, path) = match bin.parent_id {
+        None => (BIN_LEVELS[0], code.to_string()),
+        Some(parent_id) => {
+            let parent = load_bin(conn, parent_id)?;
+            let depth = BIN_LEVELS.iter().position(|l| *l == parent.level).unwrap_or(BIN_LEVELS.len());
+            if parent.location_id != bin.location_id || depth + 1 >= BIN_LEVELS.len() {
+                return Err(AppError::validation("level"));
+            }
+            (BIN_LEVELS[depth + 1], format!("{}-{}", parent.path, code))
+        }
+    };
+    if bin_path_taken(conn, bin.location_id, &path, None)? {
+        return Err(AppError::conflict("code"));
+    }
+    conn.execute(
+        "INSERT INTO bins (location_id, parent_id, level, code, path, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![bin.location_id, bin.parent_id, level, code, path, bin.active],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Solo cambian el código y si está activo. El nuevo código se propaga a
+// los bins hijos y al texto `location` de los productos.
+#[tauri::command]
+pub fn update_bin(state: State<AppState>, bin: Bin) -> Result<(), AppError> {
+    let id = bin.id.ok_or(AppError::validation("id"))?;
+    validate_bin_code(&bin.code)?;
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        let current = load_bin(&conn, id)?;
+        if !bin.active && current.active {
+            let stocked: i64 = conn.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM (
+                         SELECT m.product_id, SUM(CASE WHEN m.type='ingreso' THEN m.quantity WHEN m.type='egreso' THEN -m.quantity ELSE 0 END) AS qty
+                         FROM stock_movements m JOIN products p ON p.id = m.product_id LEFT JOIN bins d ON d.id = p.default_bin_id
+                         WHERE {} = ?1 GROUP BY m.product_id HAVING qty != 0)",
+                    MOVEMENT_BIN_SQL
+                ),
+                [id],
+                |row| row.get(0),
+            )?;
+            if stocked > 0 {
+                return Err(AppError::validation("active"));
+            }
+        }
+        let code = bin.code.trim();
+        let path = match current.path.rfind('-') {
+            Some(pos) => format!("{}-{}", &current.path[..pos], code),
+            None => code.to_string(),
+        };
+        if bin_path_taken(&conn, current.location_id, &path, Some(id))? {
+            return Err(AppError::conflict("code"));
+        }
+        conn.execute(
+            "UPDATE bins SET path = ?1 || substr(path, length(?2) + 1)
+             WHERE location_id=?3 AND (path = ?2 OR substr(path, 1, length(?2) + 1) = ?2 || '-')",
+            rusqlite::params![path, current.path, current.location_id],
+        )?;
+        conn.execute(
+            "UPDATE bins SET code=?1, active=?2 WHERE id=?3",
+            rusqlite::params![code, bin.active, id],
+        )?;
+        conn.execute(
+            "UPDATE products SET location = (SELECT path FROM bins WHERE id = products.default_bin_id)
+             WHERE default_bin_id IS NOT NULL",
+            [],
+        )?;
+        Ok(())
+    })
+}
+
+// Solo se borran bins sin hijos, sin movimientos y que no son el bin por
+// defecto de ningún producto; los usados se desactivan
+#[tauri::command]
+pub fn delete_bin(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    load_bin(&conn, id)?;
+    let used: i64 = conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM bins WHERE parent_id=?1)
+              + (SELECT COUNT(*) FROM stock_movements WHERE bin_id=?1)
+              + (SELECT COUNT(*) FROM products WHERE default_bin_id=?1)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if used > 0 {
+        return Err(AppError::validation("bin_id"));
+    }
+    conn.execute("DELETE FROM bins WHERE id=?1", [id])?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn assign_product_bin(state: State<AppState>, product_id: i32, bin_id: Option<i32>) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        if let Some(bin_id) = bin_id {
+            if !load_bin(&conn, bin_id)?.active {
+                return Err(AppError::validation("bin_id"));
+            }
+        }
+        set_product_bin(&conn, product_id as i64, bin_id.map(i64::from))
+    })
+}
+
+// Stock por producto y bin (solo combinaciones con saldo distinto de cero)
+#[tauri::command]
+pub fn get_bin_balances(
+    state: State<AppState>,
+    product_id: Option<i32>,
+    location_id: Option<i32>,
+) -> Result<Vec<BinBalance>, AppError> {
+    let conn = state.db.lock()?;
+    bin_balances(&conn, product_id, location_id)
+}
+
+fn bin_balances(
+    conn: &Connection,
+    product_id: Option<i32>,
+    location_id: Option<i32>,
+) -> Result<Vec<BinBalance>, AppError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT m.product_id, m.location_id, b.id, b.path,
+                SUM(CASE WHEN m.type='ingreso' THEN m.quantity WHEN m.type='egreso' THEN -m.quantity ELSE 0 END) AS qty
+         FROM stock_movements m
+         JOIN products p ON p.id = m.product_id
+         LEFT JOIN bins d ON d.id = p.default_bin_id
+         LEFT JOIN bins b ON b.id = {}
+         WHERE (?1 IS NULL OR m.product_id = ?1) AND (?2 IS NULL OR m.location_id = ?2)
+         GROUP BY m.product_id, m.location_id, b.id
+         HAVING qty != 0",
+        MOVEMENT_BIN_SQL
+    ))?;
+    let mut rows = stmt
+        .query_map(rusqlite::params![product_id, location_id], |row| {
+            Ok(BinBalance {
+                product_id: row.get(0)?,
+                location_id: row.get(1)?,
+                bin_id: row.get(2)?,
+                bin_path: row.get(3)?,
+                quantity: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.sort_by(|a, b| {
+        a.product_id
+            .cmp(&b.product_id)
+            .then(a.location_id.cmp(&b.location_id))
+            .then_with(|| compare_optional_paths(a.bin_path.as_deref(), b.bin_path.as_deref()))
+    });
+    Ok(rows)
+}
+
+// De qué bins sale `quantity` de un producto en una ubicación: primero el
+// bin por defecto y después el resto en orden de código. Lo que no alcanza
+// a cubrirse con stock ubicado sale sin bin.
+pub fn pick_bins(conn: &Connection, product_id: i32, location_id: i64, quantity: i64) -> Result<Vec<BinBalance>, AppError> {
+    let default_bin: Option<i32> =
+        conn.query_row("SELECT default_bin_id FROM products WHERE id=?1", [product_id], |row| row.get(0))?;
+    let mut stocked: Vec<BinBalance> = bin_balances(conn, Some(product_id), Some(location_id as i32))?
+        .into_iter()
+        .filter(|b| b.bin_id.is_some() && b.quantity > 0)
+        .collect();
+    stocked.sort_by_key(|b| b.bin_id != default_bin);
+
+    let mut pending = quantity;
+    let mut picks = Vec::new();
+    for mut bin in stocked {
+        if pending == 0 {
+            break;
+        }
+        bin.quantity = bin.quantity.min(pending);
+        pending -= bin.quantity;
+        picks.push(bin);
+    }
+    if pending > 0 {
+        picks.push(BinBalance {
+            product_id,
+            location_id: location_id as i32,
+            bin_id: None,
+            bin_path: None,
+            quantity: pending,
+        });
+    }
+    Ok(picks)
+}
+
+// Lista de picking de una venta antes de cobrarla: una fila por producto y
+// bin, ordenada por código de bin para recorrer el depósito una sola vez.
+// `add_ticket` descuenta el stock de los mismos bins.
+#[tauri::command]
+pub fn get_picking_list(
+    state: State<AppState>,
+    lines: Vec<TicketLine>,
+    location_id: Option<i32>,
+) -> Result<Vec<PickingLine>, AppError> {
+    let conn = state.db.lock()?;
+    picking_list(&conn, &lines, location_id)
+}
+
+fn picking_list(conn: &Connection, lines: &[TicketLine], location_id: Option<i32>) -> Result<Vec<PickingLine>, AppError> {
+    let location_id = resolve_location(conn, location_id)?;
+    let mut requested: Vec<(i32, i64)> = Vec::new();
+    for line in lines {
+        if line.quantity <= 0 {
+            return Err(AppError::validation("quantity"));
+        }
+        match requested.iter_mut().find(|(id, _)| *id == line.product_id) {
+            Some((_, qty)) => *qty += line.quantity as i64,
+            None => requested.push((line.product_id, line.quantity as i64)),
+        }
+    }
+    let mut list = Vec::new();
+    for (product_id, quantity) in requested {
+        let (sku, name): (Option<String>, String) = conn
+            .query_row("SELECT sku, name FROM products WHERE id=?1", [product_id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+                other => other.into(),
+            })?;
+        for pick in pick_bins(conn, product_id, location_id, quantity)? {
+            list.push(PickingLine {
+                bin_id: pick.bin_id,
+                bin_path: pick.bin_path,
+                product_id,
+                sku: sku.clone(),
+                name: name.clone(),
+                quantity: pick.quantity,
+            });
+        }
+    }
+    list.sort_by(|a, b| {
+        compare_optional_paths(a.bin_path.as_deref(), b.bin_path.as_deref()).then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(list)
+}
+
+// Mueve stock entre dos bins de la misma ubicación; entre ubicaciones se
+// usa `transfer_stock`
+#[tauri::command]
+pub fn move_stock_between_bins(state: State<AppState>, movement: BinMove) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || record_bin_move(&conn, &movement))
+}
+
+fn record_bin_move(conn: &Connection, movement: &BinMove) -> Result<(), AppError> {
+    if movement.quantity <= 0 {
+        return Err(AppError::validation("quantity"));
+    }
+    let from = load_bin(conn, movement.from_bin_id)?;
+    let to = load_bin(conn, movement.to_bin_id)?;
+    if from.id == to.id || from.location_id != to.location_id || !to.active {
+        return Err(AppError::validation("to_bin_id"));
+    }
+    let available = bin_balances(conn, Some(movement.product_id), Some(from.location_id))?
+        .iter()
+        .find(|b| b.bin_id == from.id)
+        .map(|b| b.quantity)
+        .unwrap_or(0);
+    if movement.quantity as i64 > available {
+        return Err(AppError::InsufficientStock {
+            available,
+            requested: movement.quantity as i64,
+        });
+    }
+    let note = format!("Reubicación {} → {}", from.path, to.path);
+    for (movement_type, bin_id) in [("egreso", movement.from_bin_id), ("ingreso", movement.to_bin_id)] {
+        conn.execute(
+            "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id, bin_id, cost_amount)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?3 * COALESCE(average_cost, 0) FROM products WHERE id = ?1",
+            rusqlite::params![
+                movement.product_id,
+                movement_type,
+                movement.quantity,
+                note,
+                movement.created_by,
+                from.location_id,
+                bin_id,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::test_util::{seed_product, seed_session, test_db};
+    use crate::{inventory_valuation, product_stock, record_ticket, NewTicket};
+
+    #[test]
+    fn transfers_between_locations_and_branches() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        conn.execute("INSERT INTO products (id, name, sale_price, average_cost) VALUES (1, 'Whey', 2000, 1000)", [])
+            .unwrap();
+        let shop: i32 = conn.query_row("SELECT id FROM locations WHERE is_default=1", [], |row| row.get(0)).unwrap();
+        conn.execute_batch(
+            "INSERT INTO locations (id, name, kind, branch) VALUES (20, 'Depósito', 'almacen', 'Principal'), (30, 'Sucursal Norte', 'tienda', 'Norte');
+             INSERT INTO stock_movements (product_id, type, quantity, cost_amount, location_id) VALUES (1, 'ingreso', 10, 10000, 20);",
+        )
+        .unwrap();
+        let balances = |conn: &Connection| -> Vec<(String, i64)> {
+            location_balances(conn, Some(1), None)
+                .unwrap()
+                .into_iter()
+                .map(|b| (b.location_name, b.quantity))
+                .collect()
+        };
+        let transfer = |to: i32, quantity: i32| NewTransfer {
+            product_id: 1,
+            quantity,
+            from_location_id: 20,
+            to_location_id: to,
+            note: None,
+            created_by: None,
+        };
+
+        // Misma sucursal: sale del depósito y entra al salón en un paso
+        let id = record_transfer(&conn, &transfer(shop, 4)).unwrap();
+        assert_eq!(load_transfer(&conn, id as i32).unwrap().status, "recibida");
+        assert_eq!(balances(&conn), vec![("Depósito".to_string(), 6), ("Tienda".to_string(), 4)]);
+
+        // Otra sucursal: queda en tránsito hasta que se recibe
+        let id = record_transfer(&conn, &transfer(30, 5)).unwrap() as i32;
+        assert_eq!(load_transfer(&conn, id).unwrap().status, "en_transito");
+        assert_eq!(
+            balances(&conn),
+            vec![("Depósito".to_string(), 1), ("En tránsito".to_string(), 5), ("Tienda".to_string(), 4)]
+        );
+        // El total y el costo no cambian por mover mercadería
+        assert_eq!(product_stock(&conn, 1).unwrap(), 10);
+        assert_eq!(inventory_valuation(&conn, "producto", None).unwrap().total_cost, Money::from_cents(10000));
+
+        close_transfer(&conn, id, None, true).unwrap();
+        assert_eq!(
+            balances(&conn),
+            vec![("Depósito".to_string(), 1), ("Sucursal Norte".to_string(), 5), ("Tienda".to_string(), 4)]
+        );
+        assert!(close_transfer(&conn, id, None, false).is_err());
+
+        assert!(matches!(
+            record_transfer(&conn, &transfer(shop, 2)),
+            Err(AppError::InsufficientStock { available: 1, requested: 2 })
+        ));
+
+        // Una venta desde una ubicación controla el stock de esa ubicación
+        let mut ticket = NewTicket {
+            lines: vec![TicketLine { product_id: 1, quantity: 5, discount: None }],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: Some(shop),
+            channel: None,
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        assert!(matches!(
+            record_ticket(&conn, &ticket),
+            Err(AppError::InsufficientStock { available: 4, requested: 5 })
+        ));
+        ticket.location_id = Some(30);
+        record_ticket(&conn, &ticket).unwrap();
+        assert_eq!(location_stock(&conn, 1, 30).unwrap(), 0);
+    }
+
+    #[test]
+    fn bins_moves_and_picking_list() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, location) VALUES (1, 'Whey', 2000, 'A / 03 / 2');",
+        )
+        .unwrap();
+        seed_product(&conn, 2, "Creatina", 1500);
+        migrate_product_bins(&conn).unwrap();
+        let (bin_a, location): (i32, String) = conn
+            .query_row("SELECT default_bin_id, location FROM products WHERE id=1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(location, "A-03-2");
+        assert_eq!(load_bin(&conn, bin_a).unwrap().level, "estante");
+
+        let shop: i32 = conn.query_row("SELECT id FROM locations WHERE is_default=1", [], |row| row.get(0)).unwrap();
+        let bin = |parent_id: Option<i64>, code: &str| Bin {
+            id: None,
+            location_id: shop,
+            parent_id: parent_id.map(|id| id as i32),
+            level: String::new(),
+            code: code.to_string(),
+            path: String::new(),
+            active: true,
+        };
+        let zone = insert_bin(&conn, &bin(None, "B")).unwrap();
+        let aisle = insert_bin(&conn, &bin(Some(zone), "1")).unwrap();
+        let shelf_9 = insert_bin(&conn, &bin(Some(aisle), "9")).unwrap() as i32;
+        let shelf_10 = insert_bin(&conn, &bin(Some(aisle), "10")).unwrap();
+        assert!(matches!(insert_bin(&conn, &bin(Some(aisle), "9")), Err(AppError::Conflict { .. })));
+        assert!(matches!(insert_bin(&conn, &bin(None, "C-1")), Err(AppError::Validation { .. })));
+        assert!(matches!(insert_bin(&conn, &bin(Some(shelf_10), "x")), Err(AppError::Validation { .. })));
+
+        // Sin bin explícito el stock cuenta para el bin por defecto
+        conn.execute_batch(&format!(
+            "INSERT INTO stock_movements (product_id, type, quantity, location_id) VALUES (1, 'ingreso', 10, {shop});
+             INSERT INTO stock_movements (product_id, type, quantity, location_id, bin_id) VALUES (2, 'ingreso', 3, {shop}, {shelf_10});"
+        ))
+        .unwrap();
+        let mv = |quantity: i32| BinMove {
+            product_id: 1,
+            quantity,
+            from_bin_id: bin_a,
+            to_bin_id: shelf_9,
+            created_by: None,
+        };
+        record_bin_move(&conn, &mv(4)).unwrap();
+        assert!(matches!(
+            record_bin_move(&conn, &mv(7)),
+            Err(AppError::InsufficientStock { available: 6, requested: 7 })
+        ));
+        let balances = |conn: &Connection| -> Vec<(i32, Option<String>, i64)> {
+            bin_balances(conn, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|b| (b.product_id, b.bin_path, b.quantity))
+                .collect()
+        };
+        assert_eq!(
+            balances(&conn),
+            vec![
+                (1, Some("A-03-2".to_string()), 6),
+                (1, Some("B-1-9".to_string()), 4),
+                (2, Some("B-1-10".to_string()), 3),
+            ]
+        );
+
+        // Primero el bin por defecto, después el resto; ordenado por código
+        let lines = vec![
+            TicketLine { product_id: 2, quantity: 3, discount: None },
+            TicketLine { product_id: 1, quantity: 5, discount: None },
+            TicketLine { product_id: 1, quantity: 3, discount: None },
+        ];
+        let picks: Vec<(Option<String>, i32, i64)> = picking_list(&conn, &lines, None)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.bin_path, p.product_id, p.quantity))
+            .collect();
+        assert_eq!(
+            picks,
+            vec![
+                (Some("A-03-2".to_string()), 1, 6),
+                (Some("B-1-9".to_string()), 1, 2),
+                (Some("B-1-10".to_string()), 2, 3),
+            ]
+        );
+
+        // La venta descuenta de los mismos bins
+        let ticket = NewTicket {
+            lines,
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        record_ticket(&conn, &ticket).unwrap();
+        assert_eq!(balances(&conn), vec![(1, Some("B-1-9".to_string()), 2)]);
+
+        // Al cambiar el bin por defecto el stock ya ubicado no se mueve
+        conn.execute(&format!("INSERT INTO stock_movements (product_id, type, quantity, location_id) VALUES (1, 'ingreso', 5, {shop})"), [])
+            .unwrap();
+        set_product_bin(&conn, 1, Some(shelf_9 as i64)).unwrap();
+        assert_eq!(
+            balances(&conn),
+            vec![(1, Some("A-03-2".to_string()), 5), (1, Some("B-1-9".to_string()), 2)]
+        );
+        let location: String = conn.query_row("SELECT location FROM products WHERE id=1", [], |row| row.get(0)).unwrap();
+        assert_eq!(location, "B-1-9");
+    }
+}
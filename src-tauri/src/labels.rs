@@ -0,0 +1,368 @@
+// Etiquetas de góndola y de precio para hojas A4 y rollos de etiquetas.
+//
+// El diseño se arma como una lista de figuras (rectángulos rellenos y
+// textos) en milímetros, con origen arriba a la izquierda, y después se
+// dibuja en SVG o en PDF. Los EAN-13, UPC-A y EAN-8 se dibujan como barras;
+// cualquier otro código se imprime solo como texto.
+
+use crate::barcode;
+use printpdf::{BuiltinFont, Mm, PdfDocument, Rect};
+
+pub const STYLE_SHELF: &str = "estante";
+pub const STYLE_PRICE: &str = "precio";
+
+// 1 punto tipográfico en milímetros
+const PT: f32 = 0.3528;
+const PADDING: f32 = 1.5;
+
+#[derive(Debug)]
+pub struct Template {
+    pub key: &'static str,
+    pub page_width: f32,
+    pub page_height: f32,
+    pub columns: u32,
+    pub rows: u32,
+    pub label_width: f32,
+    pub label_height: f32,
+    pub margin_left: f32,
+    pub margin_top: f32,
+    pub gap_x: f32,
+    pub gap_y: f32,
+}
+
+pub const TEMPLATES: [Template; 3] = [
+    // 24 etiquetas de 70 x 37 mm
+    Template {
+        key: "a4_3x8",
+        page_width: 210.0,
+        page_height: 297.0,
+        columns: 3,
+        rows: 8,
+        label_width: 70.0,
+        label_height: 37.0,
+        margin_left: 0.0,
+        margin_top: 0.5,
+        gap_x: 0.0,
+        gap_y: 0.0,
+    },
+    // 65 etiquetas de 38,1 x 21,2 mm
+    Template {
+        key: "a4_5x13",
+        page_width: 210.0,
+        page_height: 297.0,
+        columns: 5,
+        rows: 13,
+        label_width: 38.1,
+        label_height: 21.2,
+        margin_left: 4.75,
+        margin_top: 10.7,
+        gap_x: 2.5,
+        gap_y: 0.0,
+    },
+    // Rollo de impresora térmica: una etiqueta por página
+    Template {
+        key: "rollo_50x25",
+        page_width: 50.0,
+        page_height: 25.0,
+        columns: 1,
+        rows: 1,
+        label_width: 50.0,
+        label_height: 25.0,
+        margin_left: 0.0,
+        margin_top: 0.0,
+        gap_x: 0.0,
+        gap_y: 0.0,
+    },
+];
+
+pub fn template(key: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.key == key)
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    /// Sabor, presentación, peso
+    pub detail: Option<String>,
+    pub price: String,
+    pub barcode: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Shape {
+    Rect { x: f32, y: f32, w: f32, h: f32 },
+    /// `y` es la línea base; `size` en puntos
+    Text { x: f32, y: f32, size: f32, text: String, bold: bool },
+}
+
+pub type Page = Vec<Shape>;
+
+const L_CODES: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011", "0110111", "0001011",
+];
+// Paridad de los seis dígitos de la izquierda según el primer dígito
+const EAN13_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL", "LGGLGL",
+];
+
+fn digit_modules(digit: usize, set: char) -> Vec<bool> {
+    let l = L_CODES[digit].chars().map(|c| c == '1');
+    match set {
+        'L' => l.collect(),
+        // R es el complemento de L y G es R invertido
+        'R' => l.map(|b| !b).collect(),
+        _ => l.rev().map(|b| !b).collect(),
+    }
+}
+
+// Barras (true) y espacios de un EAN-13, UPC-A o EAN-8 válido
+pub fn ean_modules(code: &str) -> Option<Vec<bool>> {
+    let code = match barcode::classify(code)? {
+        barcode::KIND_UPCA => format!("0{}", code),
+        barcode::KIND_EAN13 | barcode::KIND_EAN8 => code.to_string(),
+        _ => return None,
+    };
+    let digits: Vec<usize> = code.chars().filter_map(|c| c.to_digit(10)).map(|d| d as usize).collect();
+    let (parity, left, right) = if digits.len() == 13 {
+        (EAN13_PARITY[digits[0]], &digits[1..7], &digits[7..])
+    } else {
+        ("LLLL", &digits[..4], &digits[4..])
+    };
+    let guard = [true, false, true];
+    let mut modules = guard.to_vec();
+    for (d, set) in left.iter().zip(parity.chars()) {
+        modules.extend(digit_modules(*d, set));
+    }
+    modules.extend([false, true, false, true, false]);
+    for d in right {
+        modules.extend(digit_modules(*d, 'R'));
+    }
+    modules.extend(guard);
+    Some(modules)
+}
+
+// Ancho aproximado de un texto en Helvetica
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * PT * 0.55
+}
+
+// Recorta el texto para que entre en `width`
+fn fit_text(text: &str, size: f32, width: f32) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+    let max = (width / (size * PT * 0.55)) as usize;
+    let kept: String = text.chars().take(max.saturating_sub(3)).collect();
+    format!("{}...", kept.trim_end())
+}
+
+fn text(shapes: &mut Page, x: f32, y: f32, size: f32, text: String, bold: bool) {
+    shapes.push(Shape::Text { x, y, size, text, bold });
+}
+
+fn draw_barcode(shapes: &mut Page, code: &str, x: f32, y: f32, w: f32, h: f32) {
+    let digits_size = (h * 0.8).clamp(5.0, 8.0);
+    let digits_height = digits_size * PT + 0.5;
+    if let Some(modules) = ean_modules(code) {
+        // Zona de silencio de 7 módulos a cada lado
+        let module = (w / (modules.len() as f32 + 14.0)).min(0.5);
+        let start = x + (w - module * modules.len() as f32) / 2.0;
+        let bar_height = h - digits_height;
+        let mut i = 0;
+        while i < modules.len() {
+            if !modules[i] {
+                i += 1;
+                continue;
+            }
+            let run = modules[i..].iter().take_while(|b| **b).count();
+            shapes.push(Shape::Rect { x: start + i as f32 * module, y, w: run as f32 * module, h: bar_height });
+            i += run;
+        }
+    }
+    let code_x = x + (w - text_width(code, digits_size)) / 2.0;
+    text(shapes, code_x.max(x), y + h - 0.3, digits_size, fit_text(code, digits_size, w), false);
+}
+
+// Una etiqueta en el recuadro (x, y, w, h)
+fn draw_label(shapes: &mut Page, label: &Label, style: &str, x: f32, y: f32, w: f32, h: f32) {
+    let scale = (h / 25.0).min(1.4);
+    let inner = w - 2.0 * PADDING;
+    let left = x + PADDING;
+    let mut line = y + PADDING;
+
+    let name_size = 8.0 * scale;
+    line += name_size * PT;
+    text(shapes, left, line, name_size, fit_text(&label.name, name_size, inner), true);
+    if let Some(detail) = label.detail.as_deref().filter(|d| !d.is_empty()) {
+        let size = 6.5 * scale;
+        line += size * PT + 0.8;
+        text(shapes, left, line, size, fit_text(detail, size, inner), false);
+    }
+
+    if style == STYLE_PRICE {
+        // Precio grande abajo, sin código de barras
+        let size = (h * 0.32 / PT).min(28.0);
+        let price_x = x + w - PADDING - text_width(&label.price, size);
+        text(shapes, price_x.max(left), y + h - PADDING - 0.5, size, label.price.clone(), true);
+        return;
+    }
+
+    let price_size = 12.0 * scale;
+    line += price_size * PT + 1.0;
+    text(shapes, left, line, price_size, label.price.clone(), true);
+    if let Some(code) = &label.barcode {
+        let top = line + 1.0;
+        let height = y + h - PADDING - top;
+        if height > 4.0 {
+            draw_barcode(shapes, code, left, top, inner, height);
+        }
+    }
+}
+
+// Reparte las etiquetas en páginas, de izquierda a derecha y de arriba abajo
+pub fn layout(template: &Template, labels: &[Label], style: &str) -> Vec<Page> {
+    let per_page = (template.columns * template.rows) as usize;
+    labels
+        .chunks(per_page)
+        .map(|chunk| {
+            let mut shapes = Page::new();
+            for (i, label) in chunk.iter().enumerate() {
+                let column = (i as u32 % template.columns) as f32;
+                let row = (i as u32 / template.columns) as f32;
+                let x = template.margin_left + column * (template.label_width + template.gap_x);
+                let y = template.margin_top + row * (template.label_height + template.gap_y);
+                draw_label(&mut shapes, label, style, x, y, template.label_width, template.label_height);
+            }
+            shapes
+        })
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Una página como SVG, en milímetros
+pub fn to_svg(template: &Template, page: &Page) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n",
+        w = template.page_width,
+        h = template.page_height
+    );
+    for shape in page {
+        match shape {
+            Shape::Rect { x, y, w, h } => {
+                svg.push_str(&format!("<rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\"/>\n", x, y, w, h));
+            }
+            Shape::Text { x, y, size, text, bold } => {
+                svg.push_str(&format!(
+                    "<text x=\"{:.2}\" y=\"{:.2}\" font-family=\"Helvetica, Arial, sans-serif\" font-size=\"{:.2}\"{}>{}</text>\n",
+                    x,
+                    y,
+                    size * PT,
+                    if *bold { " font-weight=\"bold\"" } else { "" },
+                    escape_xml(text)
+                ));
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Todas las páginas en un PDF
+pub fn to_pdf(template: &Template, pages: &[Page], title: &str) -> Result<Vec<u8>, printpdf::Error> {
+    let (width, height) = (Mm(template.page_width), Mm(template.page_height));
+    let (doc, first_page, first_layer) = PdfDocument::new(title, width, height, "Etiquetas");
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    for (i, page) in pages.iter().enumerate() {
+        let layer = if i == 0 {
+            doc.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page_index, layer_index) = doc.add_page(width, height, "Etiquetas");
+            doc.get_page(page_index).get_layer(layer_index)
+        };
+        // PDF mide desde abajo a la izquierda
+        let flip = |y: f32| Mm(template.page_height - y);
+        for shape in page {
+            match shape {
+                Shape::Rect { x, y, w, h } => layer.add_rect(Rect::new(Mm(*x), flip(y + h), Mm(x + w), flip(*y))),
+                Shape::Text { x, y, size, text, bold: is_bold } => {
+                    layer.use_text(text.as_str(), *size, Mm(*x), flip(*y), if *is_bold { &bold } else { &regular })
+                }
+            }
+        }
+    }
+    doc.save_to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str) -> Label {
+        Label {
+            name: name.to_string(),
+            detail: Some("Chocolate 5lb".to_string()),
+            price: "$199.90".to_string(),
+            barcode: Some("4006381333931".to_string()),
+        }
+    }
+
+    #[test]
+    fn encodes_ean13_and_ean8() {
+        let modules = ean_modules("4006381333931").unwrap();
+        assert_eq!(modules.len(), 95);
+        let bits: String = modules.iter().map(|b| if *b { '1' } else { '0' }).collect();
+        // Guarda, "0" con paridad L (primer dígito 4 = LGLLGG) y "0" con G
+        assert_eq!(&bits[..17], "10100011010100111");
+        assert!(bits.ends_with("101"));
+        assert_eq!(ean_modules("96385074").unwrap().len(), 67);
+        assert_eq!(ean_modules("036000291452").unwrap().len(), 95);
+        assert_eq!(ean_modules("4006381333932"), None);
+        assert_eq!(ean_modules("ABC-1"), None);
+    }
+
+    #[test]
+    fn lays_out_labels_in_pages() {
+        let labels: Vec<Label> = (0..30).map(|i| label(&format!("Producto {}", i))).collect();
+        let pages = layout(template("a4_3x8").unwrap(), &labels, STYLE_SHELF);
+        assert_eq!(pages.len(), 2);
+        // La etiqueta 25 (primera de la segunda hoja) arranca arriba a la izquierda
+        let first = pages[1].iter().find_map(|s| match s {
+            Shape::Text { x, y, text, .. } if text == "Producto 24" => Some((*x, *y)),
+            _ => None,
+        });
+        assert_eq!(first, Some((PADDING, 0.5 + PADDING + 8.0 * 1.4 * PT)));
+        let bars = pages[0].iter().filter(|s| matches!(s, Shape::Rect { .. })).count();
+        // 30 barras por EAN-13 (6 de las guardas y 2 por dígito) en 24 etiquetas
+        assert_eq!(bars, 30 * 24);
+    }
+
+    #[test]
+    fn renders_svg_and_pdf() {
+        let tpl = template("rollo_50x25").unwrap();
+        let mut long = label("Proteína Whey Gold Standard <Edición> & más");
+        long.barcode = Some("CREA-300".to_string());
+        let pages = layout(tpl, &[long, label("Creatina")], STYLE_PRICE);
+        assert_eq!(pages.len(), 2);
+        let svg = to_svg(tpl, &pages[0]);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"50mm\" height=\"25mm\""));
+        assert!(svg.contains(">Proteína Whey Gold Standard...</text>"));
+        assert!(!svg.contains("<rect"));
+        assert!(svg.contains(">$199.90</text>"));
+
+        let shelf = layout(tpl, &[label("Creatina")], STYLE_SHELF);
+        let svg = to_svg(tpl, &shelf[0]);
+        assert!(svg.contains("<rect"));
+        assert!(svg.contains(">4006381333931</text>"));
+        assert!(to_svg(tpl, &layout(tpl, &[label("A & B")], STYLE_SHELF)[0]).contains("A &amp; B"));
+
+        let pdf = to_pdf(tpl, &pages, "Etiquetas").unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -1,13 +1,38 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod barcode;
+mod catalog;
+mod csv;
+mod error;
+mod inventory;
+mod labels;
+mod media;
+mod money;
+mod promotions;
+mod receipt;
+mod report;
+#[cfg(test)]
+mod test_util;
+
+use catalog::{
+    delete_orphan_parent, import_external_images, import_product_image, migrate_product_barcodes, migrate_product_parents,
+    product_image_path, release_image, resolve_parent_product, MEDIA_DIR,
+};
+use error::AppError;
+use inventory::{
+    load_bin, location_stock, migrate_product_bins, pick_bins, resolve_location, resolve_product_bin, set_product_bin,
+};
+use money::Money;
+use promotions::Promotion;
 use rusqlite::{Connection, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 use tauri::State;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::api::path::download_dir;
 
@@ -21,6 +46,8 @@ struct User {
     fullname: Option<String>,
 }
 
+// Con `by_parent` las variantes se suman en su producto padre y
+// `product_id` es el id del padre
 #[tauri::command]
 fn get_sales_by_product(
     state: State<AppState>,
@@ -29,30 +56,37 @@ fn get_sales_by_product(
     order_by: Option<String>,
     category: Option<String>,
     limit: Option<i32>,
-) -> Result<Vec<SalesByProduct>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    by_parent: Option<bool>,
+) -> Result<Vec<SalesByProduct>, AppError> {
+    let conn = state.db.lock()?;
     let lim = limit.unwrap_or(5);
     let order_col = match order_by.as_deref() {
         Some("qty") => "total_qty",
         _ => "total_revenue",
     };
+    let (key, label) = if by_parent.unwrap_or(false) {
+        ("COALESCE(pp.id, s.product_id)", "COALESCE(pp.name, p.name, '')")
+    } else {
+        ("s.product_id", "COALESCE(p.name, '')")
+    };
     let sql = format!(
-        "SELECT s.product_id, COALESCE(p.name, '') as name,
+        "SELECT {key} AS key, {label} as name,
                 COALESCE(SUM(s.quantity),0) as total_qty,
-                COALESCE(SUM(s.sale_price),0.0) as total_revenue
+                COALESCE(SUM(s.sale_price),0) as total_revenue,
+                COALESCE(SUM(s.gross_amount),0) as gross_revenue,
+                COALESCE(SUM(s.discount_amount),0) as total_discount
          FROM sales s
          LEFT JOIN products p ON p.id = s.product_id
+         LEFT JOIN parent_products pp ON pp.id = p.parent_id
          WHERE (?1 IS NULL OR substr(s.sale_date,1,10) >= ?1)
            AND (?2 IS NULL OR substr(s.sale_date,1,10) <= ?2)
            AND (?3 IS NULL OR p.category = ?3)
-         GROUP BY s.product_id, name
-         ORDER BY {} DESC
+         GROUP BY key, name
+         ORDER BY {order_col} DESC
          LIMIT ?4",
-        order_col
     );
     let mut stmt = conn
-        .prepare(&sql)
-        .map_err(|e| e.to_string())?;
+        .prepare(&sql)?;
     let rows = stmt
         .query_map(rusqlite::params![start_date, end_date, category, lim], |row| {
             Ok(SalesByProduct {
@@ -60,18 +94,22 @@ fn get_sales_by_product(
                 name: row.get(1)?,
                 total_qty: row.get(2)?,
                 total_revenue: row.get(3)?,
+                gross_revenue: row.get(4)?,
+                total_discount: row.get(5)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(rows)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SalesTotals {
     total_units: i64,
-    total_revenue: f64,
+    /// Ingresos netos (después de descuentos)
+    total_revenue: Money,
+    /// Ingresos a precio de lista, antes de descuentos
+    gross_revenue: Money,
+    total_discount: Money,
 }
 
 #[tauri::command]
@@ -80,57 +118,60 @@ fn get_sales_totals(
     start_date: Option<String>,
     end_date: Option<String>,
     category: Option<String>,
-) -> Result<SalesTotals, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+) -> Result<SalesTotals, AppError> {
+    let conn = state.db.lock()?;
     let mut stmt = conn
         .prepare(
             "SELECT COALESCE(SUM(s.quantity),0) as total_units,
-                    COALESCE(SUM(s.sale_price),0.0) as total_revenue
+                    COALESCE(SUM(s.sale_price),0) as total_revenue,
+                    COALESCE(SUM(s.gross_amount),0) as gross_revenue,
+                    COALESCE(SUM(s.discount_amount),0) as total_discount
              FROM sales s
              LEFT JOIN products p ON p.id = s.product_id
              WHERE (?1 IS NULL OR substr(s.sale_date,1,10) >= ?1)
                AND (?2 IS NULL OR substr(s.sale_date,1,10) <= ?2)
                AND (?3 IS NULL OR p.category = ?3)",
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     let totals = stmt
         .query_row(rusqlite::params![start_date, end_date, category], |row| {
             Ok(SalesTotals {
                 total_units: row.get(0)?,
                 total_revenue: row.get(1)?,
+                gross_revenue: row.get(2)?,
+                total_discount: row.get(3)?,
             })
-        })
-        .map_err(|e| e.to_string())?;
+        })?;
     Ok(totals)
 }
 
 #[tauri::command]
-fn get_sales_trend(state: State<AppState>, days: Option<i32>) -> Result<Vec<SalesTrendPoint>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn get_sales_trend(state: State<AppState>, days: Option<i32>) -> Result<Vec<SalesTrendPoint>, AppError> {
+    let conn = state.db.lock()?;
     let d = days.unwrap_or(7);
     let modifier = format!("-{} day", d.max(0));
     let mut stmt = conn
         .prepare(
             "SELECT substr(sale_date,1,10) as day,
                     COUNT(*) as sales_count,
-                    COALESCE(SUM(sale_price),0.0) as total_revenue
+                    COALESCE(SUM(sale_price),0) as total_revenue,
+                    COALESCE(SUM(gross_amount),0) as gross_revenue,
+                    COALESCE(SUM(discount_amount),0) as total_discount
              FROM sales
              WHERE substr(sale_date,1,10) >= date('now', ?1)
              GROUP BY day
              ORDER BY day ASC",
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
     let rows = stmt
         .query_map(rusqlite::params![modifier], |row| {
             Ok(SalesTrendPoint {
                 date: row.get(0)?,
                 sales_count: row.get(1)?,
                 total_revenue: row.get(2)?,
+                gross_revenue: row.get(3)?,
+                total_discount: row.get(4)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(rows)
 }
 #[derive(Debug, Serialize, Deserialize)]
@@ -139,172 +180,41 @@ struct StockBalance {
     current_stock: i64,
 }
 
+// Stock por producto; con `as_of` (fecha u hora) el que había en ese momento
 #[tauri::command]
-fn get_stock_balances(state: State<AppState>) -> Result<Vec<StockBalance>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT product_id, COALESCE(SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END),0) as balance FROM stock_movements GROUP BY product_id")
-        .map_err(|e| e.to_string())?;
+fn get_stock_balances(state: State<AppState>, as_of: Option<String>) -> Result<Vec<StockBalance>, AppError> {
+    let conn = state.db.lock()?;
+    stock_balances(&conn, as_of.as_deref())
+}
+
+fn stock_balances(conn: &Connection, as_of: Option<&str>) -> Result<Vec<StockBalance>, AppError> {
+    validate_timestamp(conn, as_of, "as_of")?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT product_id, COALESCE(SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END),0) as balance
+         FROM stock_movements WHERE ?1 IS NULL OR {}
+         GROUP BY product_id
+         ORDER BY product_id",
+        at_or_before_sql("created_at", "?1")
+    ))?;
 
     let rows = stmt
-        .query_map([], |row| {
+        .query_map([as_of], |row| {
             Ok(StockBalance {
                 product_id: row.get(0)?,
                 current_stock: row.get(1)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(rows)
 }
 
-#[tauri::command]
-fn export_sales_report(state: State<AppState>, start_date: Option<String>, end_date: Option<String>) -> Result<String, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-
-    let mut rows: Vec<(i32, i32, i32, f64, Option<f64>, Option<String>, String, Option<i32>)> = Vec::new();
-    if start_date.is_some() && end_date.is_some() {
-        let mut stmt = conn
-            .prepare("SELECT id, product_id, quantity, sale_price, discount, channel, sale_date, created_by FROM sales WHERE substr(sale_date,1,10) BETWEEN ?1 AND ?2 ORDER BY sale_date DESC")
-            .map_err(|e| e.to_string())?;
-        let iter = stmt
-            .query_map(rusqlite::params![start_date.as_ref().unwrap(), end_date.as_ref().unwrap()], |row| {
-                Ok((
-                    row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?,
-                ))
-            })
-            .map_err(|e| e.to_string())?;
-        for r in iter { rows.push(r.map_err(|e| e.to_string())?); }
-    } else {
-        let mut stmt = conn
-            .prepare("SELECT id, product_id, quantity, sale_price, discount, channel, sale_date, created_by FROM sales ORDER BY sale_date DESC")
-            .map_err(|e| e.to_string())?;
-        let iter = stmt
-            .query_map([], |row| {
-                Ok((
-                    row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?,
-                ))
-            })
-            .map_err(|e| e.to_string())?;
-        for r in iter { rows.push(r.map_err(|e| e.to_string())?); }
-    }
-
-    let mut csv = String::from("id,product_id,quantity,sale_price,discount,channel,sale_date,created_by\n");
-    for (id, pid, qty, price, disc, channel, date, created_by) in rows {
-        csv.push_str(&format!(
-            "{},{},{},{:.2},{},{},{},{}\n",
-            id,
-            pid,
-            qty,
-            price,
-            disc.map(|d| d.to_string()).unwrap_or_default(),
-            channel.unwrap_or_default(),
-            date,
-            created_by.map(|c| c.to_string()).unwrap_or_default()
-        ));
-    }
-
-    let base: PathBuf = download_dir().ok_or("No se pudo obtener carpeta Descargas")?;
-    let out_dir = base.join("VitaSport");
-    fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
-    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs();
-    let path = out_dir.join(format!("sales_report_{}.csv", ts));
-    fs::write(&path, csv).map_err(|e| e.to_string())?;
-    Ok(path.to_string_lossy().to_string())
-}
-
-#[tauri::command]
-fn export_inventory_report(state: State<AppState>) -> Result<String, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare("SELECT id, sku, name, sale_price, brand, category, presentation, flavor, weight, expiry_date, lot_number, min_stock, max_stock, location, status FROM products")
-        .map_err(|e| e.to_string())?;
-
-    let mut csv = String::from("id,sku,name,sale_price,brand,category,presentation,flavor,weight,expiry_date,lot_number,min_stock,max_stock,location,status,current_stock\n");
-    let rows = stmt.query_map([], |row| {
-        Ok((
-            row.get::<_, i32>(0)?,
-            row.get::<_, Option<String>>(1)?,
-            row.get::<_, String>(2)?,
-            row.get::<_, Option<f64>>(3)?,
-            row.get::<_, Option<String>>(4)?,
-            row.get::<_, Option<String>>(5)?,
-            row.get::<_, Option<String>>(6)?,
-            row.get::<_, Option<String>>(7)?,
-            row.get::<_, Option<String>>(8)?,
-            row.get::<_, Option<String>>(9)?,
-            row.get::<_, Option<String>>(10)?,
-            row.get::<_, Option<i32>>(11)?,
-            row.get::<_, Option<i32>>(12)?,
-            row.get::<_, Option<String>>(13)?,
-            row.get::<_, Option<String>>(14)?,
-        ))
-    }).map_err(|e| e.to_string())?;
-
-    for r in rows {
-        let (id, sku, name, sale_price, brand, category, presentation, flavor, weight, expiry_date, lot_number, min_stock, max_stock, location, status) = r.map_err(|e| e.to_string())?;
-
-        let ingreso: i64 = conn.query_row(
-            "SELECT COALESCE(SUM(quantity),0) FROM stock_movements WHERE product_id=?1 AND type='ingreso'",
-            rusqlite::params![id],
-            |row| row.get(0),
-        ).unwrap_or(0);
-        let egreso: i64 = conn.query_row(
-            "SELECT COALESCE(SUM(quantity),0) FROM stock_movements WHERE product_id=?1 AND type='egreso'",
-            rusqlite::params![id],
-            |row| row.get(0),
-        ).unwrap_or(0);
-        let current_stock = ingreso - egreso;
-
-        csv.push_str(&format!(
-            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
-            id,
-            sku.unwrap_or_default(),
-            name,
-            sale_price.map(|v| format!("{:.2}", v)).unwrap_or_default(),
-            brand.unwrap_or_default(),
-            category.unwrap_or_default(),
-            presentation.unwrap_or_default(),
-            flavor.unwrap_or_default(),
-            weight.unwrap_or_default(),
-            expiry_date.unwrap_or_default(),
-            lot_number.unwrap_or_default(),
-            min_stock.map(|v| v.to_string()).unwrap_or_default(),
-            max_stock.map(|v| v.to_string()).unwrap_or_default(),
-            location.unwrap_or_default(),
-            status.unwrap_or_default(),
-            current_stock,
-        ));
-    }
-
-    let base: PathBuf = download_dir().ok_or("No se pudo obtener carpeta Descargas")?;
-    let out_dir = base.join("VitaSport");
-    fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
-    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs();
-    let path = out_dir.join(format!("inventory_report_{}.csv", ts));
-    fs::write(&path, csv).map_err(|e| e.to_string())?;
-    Ok(path.to_string_lossy().to_string())
-}
-
-#[tauri::command]
-fn export_all_reports(state: State<AppState>) -> Result<Vec<String>, String> {
-    let mut paths = Vec::new();
-    let inv = export_inventory_report(state.clone())?;
-    paths.push(inv);
-    let sales = export_sales_report(state, None, None)?;
-    paths.push(sales);
-    Ok(paths)
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct Product {
     id: Option<i32>,
     sku: Option<String>,
     name: String,
-    sale_price: Option<f64>,
+    sale_price: Option<Money>,
     brand: Option<String>,
     category: Option<String>,
     presentation: Option<String>,
@@ -315,8 +225,18 @@ struct Product {
     lot_number: Option<String>,
     min_stock: Option<i32>,
     max_stock: Option<i32>,
+    /// Código del bin por defecto ("A-03-2"). Al guardar, si no se indica
+    /// `default_bin_id`, el bin se busca o se crea a partir de este texto en
+    /// la ubicación por defecto
     location: Option<String>,
     status: Option<String>,
+    #[serde(default)]
+    default_bin_id: Option<i32>,
+    /// Producto padre; si no se indica se mantiene el actual o, en un alta,
+    /// se usa (o se crea) el de igual nombre y marca. `flavor`,
+    /// `presentation` y `weight` son los atributos de la variante
+    #[serde(default)]
+    parent_id: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -327,6 +247,12 @@ struct StockMovement {
     quantity: i32,
     note: Option<String>,
     created_by: Option<i32>,
+    /// Ubicación; None = la ubicación por defecto
+    #[serde(default)]
+    location_id: Option<i32>,
+    /// Bin dentro de la ubicación; None = el bin por defecto del producto
+    #[serde(default)]
+    bin_id: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -334,14 +260,20 @@ struct SalesByProduct {
     product_id: i32,
     name: String,
     total_qty: i64,
-    total_revenue: f64,
+    /// Ingresos netos (después de descuentos)
+    total_revenue: Money,
+    gross_revenue: Money,
+    total_discount: Money,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SalesTrendPoint {
     date: String,
     sales_count: i64,
-    total_revenue: f64,
+    /// Ingresos netos (después de descuentos)
+    total_revenue: Money,
+    gross_revenue: Money,
+    total_discount: Money,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -349,39 +281,285 @@ struct Purchase {
     id: Option<i32>,
     product_id: i32,
     supplier: Option<String>,
-    purchase_price: Option<f64>,
+    purchase_price: Option<Money>,
     purchase_date: Option<String>,
-    discount: Option<f64>,
+    /// Descuento sobre el total de la compra (no por unidad)
+    discount: Option<Money>,
     expected_replenish_days: Option<i32>,
+    /// Unidades recibidas
+    #[serde(default)]
+    quantity: Option<i32>,
+    #[serde(default)]
+    created_by: Option<i32>,
+    /// Ubicación donde se recibe; None = la por defecto
+    #[serde(default)]
+    location_id: Option<i32>,
 }
 
+// Los importes de una venta los calcula siempre el backend en `add_sale` a
+// partir del precio de lista del producto; lo que envíe el frontend en esos
+// campos se ignora.
 #[derive(Debug, Serialize, Deserialize)]
 struct Sale {
     id: Option<i32>,
     product_id: i32,
     quantity: i32,
-    sale_price: f64,
+    /// Precio de lista unitario al momento de la venta
+    #[serde(default)]
+    unit_price: Money,
+    /// unit_price x quantity, antes del descuento
+    #[serde(default)]
+    gross_amount: Money,
+    /// Porcentaje de descuento aplicado (0-100), no un importe
     discount: Option<f64>,
+    /// Descuento total de la línea: promoción + descuento manual
+    #[serde(default)]
+    discount_amount: Money,
+    /// Total neto cobrado: gross_amount - discount_amount
+    #[serde(default)]
+    sale_price: Money,
+    #[serde(default)]
+    ticket_id: Option<i32>,
+    /// Promoción aplicada por el backend, si hubo alguna
+    #[serde(default)]
+    promotion_id: Option<i32>,
+    #[serde(default)]
+    promotion_amount: Money,
+    /// Cliente registrado; None = venta de mostrador
+    #[serde(default)]
+    customer_id: Option<i32>,
+    /// Parte del descuento pagada con puntos canjeados
+    #[serde(default)]
+    points_amount: Money,
+    /// Venta al crédito (fiado): queda pendiente de cobro al cliente
+    #[serde(default)]
+    on_account: bool,
+    /// Formas de pago al registrar la venta; no se devuelven en las consultas
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    payments: Vec<Tender>,
     channel: Option<String>,
     sale_date: String,
     created_by: Option<i32>,
 }
+
+// Venta de varias líneas. Cada línea se guarda como una fila de `sales`.
+#[derive(Debug, Serialize, Deserialize)]
+struct TicketLine {
+    product_id: i32,
+    quantity: i32,
+    /// Descuento manual en porcentaje, aplicado después de las promociones
+    discount: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewTicket {
+    lines: Vec<TicketLine>,
+    #[serde(default)]
+    customer_id: Option<i32>,
+    /// Puntos del cliente a canjear como descuento sobre el total. Para
+    /// pagar con puntos se usa la forma de pago "puntos".
+    #[serde(default)]
+    redeem_points: Option<i64>,
+    /// Venta al crédito; requiere cliente
+    #[serde(default)]
+    on_account: bool,
+    /// Formas de pago. Vacío = un solo pago por el total, en efectivo (o al
+    /// crédito si `on_account`)
+    #[serde(default)]
+    payments: Vec<Tender>,
+    /// Turno de caja; si no se indica, el turno abierto del usuario
+    #[serde(default)]
+    session_id: Option<i32>,
+    /// Ubicación de la que sale la mercadería y en la que se controla el
+    /// stock; si no se indica, la por defecto.
+    #[serde(default)]
+    location_id: Option<i32>,
+    channel: Option<String>,
+    sale_date: String,
+    created_by: Option<i32>,
+}
+
+// Formas de pago aceptadas. "billetera" agrupa Yape, Plin y similares;
+// "puntos" paga con puntos del cliente al valor de canje.
+const PAYMENT_METHODS: [&str; 6] = ["efectivo", "tarjeta", "transferencia", "billetera", "credito", "puntos"];
+// Formas de pago que solo valen en un ticket: no son dinero que entra o sale
+// de la caja (gastos, cobros, movimientos)
+const TICKET_ONLY_METHODS: [&str; 2] = ["credito", "puntos"];
+
+/// Pago entregado por el cliente. Solo el efectivo puede superar lo que
+/// falta por pagar; la diferencia es el vuelto.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tender {
+    method: String,
+    amount: Money,
+    /// Nº de operación, últimos dígitos de la tarjeta, etc.
+    #[serde(default)]
+    reference: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TicketPayment {
+    id: Option<i32>,
+    ticket_id: i32,
+    method: String,
+    /// Importe aplicado al ticket
+    amount: Money,
+    /// Importe entregado por el cliente
+    tendered: Money,
+    change_given: Money,
+    reference: Option<String>,
+}
+
+const SALE_COLUMNS: &str = "id, product_id, quantity, unit_price, gross_amount, discount, discount_amount, sale_price, ticket_id, promotion_id, promotion_amount, customer_id, points_amount, on_account, channel, sale_date, created_by";
+
+fn sale_from_row(row: &rusqlite::Row) -> rusqlite::Result<Sale> {
+    Ok(Sale {
+        id: row.get(0)?,
+        product_id: row.get(1)?,
+        quantity: row.get(2)?,
+        unit_price: row.get::<_, Option<Money>>(3)?.unwrap_or_default(),
+        gross_amount: row.get::<_, Option<Money>>(4)?.unwrap_or_default(),
+        discount: row.get(5)?,
+        discount_amount: row.get::<_, Option<Money>>(6)?.unwrap_or_default(),
+        sale_price: row.get(7)?,
+        ticket_id: row.get(8)?,
+        promotion_id: row.get(9)?,
+        promotion_amount: row.get(10)?,
+        customer_id: row.get(11)?,
+        points_amount: row.get(12)?,
+        on_account: row.get(13)?,
+        payments: Vec::new(),
+        channel: row.get(14)?,
+        sale_date: row.get(15)?,
+        created_by: row.get(16)?,
+    })
+}
+
+/// Importes de una línea de venta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SaleAmounts {
+    unit_price: Money,
+    gross: Money,
+    promotion: Money,
+    /// Promoción + descuento manual
+    discount: Money,
+    net: Money,
+}
+
+impl SaleAmounts {
+    // La promoción se descuenta primero y el porcentaje manual se aplica
+    // sobre lo que queda
+    fn compute(unit_price: Money, quantity: i32, discount_pct: f64, promotion: Money) -> Self {
+        let gross = unit_price.times(quantity as i64);
+        let promotion = promotion.min(gross);
+        let manual = (gross - promotion).percent(discount_pct);
+        let discount = promotion + manual;
+        SaleAmounts { unit_price, gross, promotion, discount, net: gross - discount }
+    }
+}
 #[derive(Debug, Serialize, Deserialize)]
 struct CashMovement {
     id: Option<i32>,
     movement_type: String,
-    amount: f64,
+    amount: Money,
     category: Option<String>,
     description: Option<String>,
     movement_date: String,
+    /// Forma de pago del movimiento; None = efectivo
+    #[serde(default)]
+    payment_method: Option<String>,
+    /// Turno de caja; si no se indica, el turno abierto del usuario
+    #[serde(default)]
+    session_id: Option<i32>,
     created_by: Option<i32>,
+    /// Categoría de gasto (ver expense_categories); `category` lleva su nombre
+    #[serde(default)]
+    category_id: Option<i32>,
+    /// "confirmado" o "pendiente" (egresos recurrentes aún no pagados)
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    recurring_expense_id: Option<i32>,
+}
+
+/// Importe neto que entró por una forma de pago
+#[derive(Debug, Serialize, Deserialize)]
+struct PaymentMethodTotal {
+    method: String,
+    /// Ventas cobradas con esta forma de pago (ya descontado el vuelto)
+    sales: Money,
+    /// Otros ingresos de caja (incluye cobros de crédito)
+    other_income: Money,
+    expense: Money,
+    /// sales + other_income - expense
+    net: Money,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CashSummary {
-    total_income: f64,
-    total_expense: f64,
-    balance: f64,
+    total_income: Money,
+    total_expense: Money,
+    balance: Money,
+    /// Ventas a precio de lista, descuentos y neto
+    sales_gross: Money,
+    sales_discount: Money,
+    sales_net: Money,
+    /// Parte del neto vendida al crédito. No entra en total_income: los
+    /// cobros llegan después como `ingreso` en cash_movements
+    #[serde(default)]
+    sales_on_account: Money,
+    /// Parte del neto pagada con puntos del cliente. Tampoco entra en
+    /// total_income: no es dinero de caja
+    #[serde(default)]
+    sales_in_points: Money,
+    /// Desglose por forma de pago (sin "credito" ni "puntos")
+    #[serde(default)]
+    by_method: Vec<PaymentMethodTotal>,
+    /// Efectivo que debería haber en caja: neto de la forma "efectivo"
+    #[serde(default)]
+    cash_in_drawer: Money,
+    /// Ingresos y egresos por día, semana o mes (si se pidió `bucket`)
+    #[serde(default)]
+    buckets: Vec<CashBucket>,
+    /// Mismo resumen del período anterior equivalente (si hay fechas)
+    #[serde(default)]
+    previous: Option<PeriodComparison>,
+}
+
+/// Filtros del resumen de caja. Las fechas son "YYYY-MM-DD" inclusivas.
+/// `categories` filtra los movimientos de caja por categoría; las ventas se
+/// incluyen solo si la lista contiene "Ventas".
+#[derive(Debug, Default, Clone)]
+struct CashFilter {
+    session_id: Option<i64>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    categories: Option<Vec<String>>,
+}
+
+// Categoría con la que aparecen las ventas al filtrar por categorías
+const SALES_CATEGORY: &str = "Ventas";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CashBucket {
+    /// Primer día del período ("YYYY-MM-DD"; la semana empieza el lunes)
+    period_start: String,
+    total_income: Money,
+    total_expense: Money,
+    balance: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PeriodComparison {
+    start_date: String,
+    end_date: String,
+    total_income: Money,
+    total_expense: Money,
+    balance: Money,
+    /// balance actual - balance anterior
+    balance_change: Money,
+    /// Variación porcentual del balance; None si el anterior es cero
+    balance_change_pct: Option<f64>,
 }
 
 // Database state
@@ -392,7 +570,15 @@ struct AppState {
 // Initialize database
 fn init_database() -> Result<Connection> {
     let conn = Connection::open("vitasport.db")?;
+    init_schema(&conn)?;
+    apply_due_price_changes(&conn)?;
+    expire_loyalty_points(&conn, "now")?;
+    generate_recurring_expenses(&conn, "now")?;
+    Ok(conn)
+}
 
+// Crea las tablas y aplica las migraciones pendientes
+fn init_schema(conn: &Connection) -> Result<()> {
     // Create users table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS users (
@@ -413,7 +599,7 @@ fn init_database() -> Result<Connection> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             sku TEXT UNIQUE,
             name TEXT NOT NULL,
-            sale_price REAL,
+            sale_price INTEGER,
             brand TEXT,
             category TEXT,
             presentation TEXT,
@@ -425,7 +611,10 @@ fn init_database() -> Result<Connection> {
             min_stock INTEGER,
             max_stock INTEGER,
             location TEXT,
-            status TEXT
+            status TEXT,
+            average_cost INTEGER,
+            default_bin_id INTEGER REFERENCES bins(id),
+            parent_id INTEGER REFERENCES parent_products(id)
         )",
         [],
     )?;
@@ -438,7 +627,7 @@ fn init_database() -> Result<Connection> {
             if let Ok(name) = r { col_names.push(name); }
         }
         if !col_names.iter().any(|c| c == "sale_price") {
-            let _ = conn.execute("ALTER TABLE products ADD COLUMN sale_price REAL", []);
+            let _ = conn.execute("ALTER TABLE products ADD COLUMN sale_price INTEGER", []);
         }
         if !col_names.iter().any(|c| c == "max_stock") {
             let _ = conn.execute("ALTER TABLE products ADD COLUMN max_stock INTEGER", []);
@@ -456,22 +645,128 @@ fn init_database() -> Result<Connection> {
             created_by INTEGER,
             created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
             updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            cost_amount INTEGER,
+            location_id INTEGER REFERENCES locations(id),
+            transfer_id INTEGER REFERENCES stock_transfers(id),
+            bin_id INTEGER REFERENCES bins(id),
             FOREIGN KEY (product_id) REFERENCES products(id),
             FOREIGN KEY (created_by) REFERENCES users(id)
         )",
         [],
     )?;
 
+    // Ubicaciones de inventario y transferencias entre ellas
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS locations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            kind TEXT NOT NULL,
+            branch TEXT NOT NULL,
+            is_default INTEGER NOT NULL DEFAULT 0,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS stock_transfers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            from_location_id INTEGER NOT NULL,
+            to_location_id INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            note TEXT,
+            created_by INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            received_by INTEGER,
+            received_at TEXT,
+            FOREIGN KEY (product_id) REFERENCES products(id),
+            FOREIGN KEY (from_location_id) REFERENCES locations(id),
+            FOREIGN KEY (to_location_id) REFERENCES locations(id),
+            FOREIGN KEY (created_by) REFERENCES users(id),
+            FOREIGN KEY (received_by) REFERENCES users(id)
+        )",
+        [],
+    )?;
+
+    // Producto padre de las variantes (sabor, presentación, peso). Cada fila
+    // de `products` es una variante con su SKU, precio y stock
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS parent_products (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            brand TEXT,
+            category TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // Códigos de barras por producto. `lookup_key` es la clave de búsqueda
+    // (GTIN-14 para EAN/UPC) y no puede repetirse
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS product_barcodes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            code TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            lookup_key TEXT NOT NULL UNIQUE,
+            internal INTEGER NOT NULL DEFAULT 0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (product_id) REFERENCES products(id)
+        )",
+        [],
+    )?;
+
+    // Bins del depósito: zona > pasillo > estante dentro de una ubicación.
+    // `path` es el código completo ("A-03-2") y se mantiene al renombrar
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bins (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            location_id INTEGER NOT NULL,
+            parent_id INTEGER,
+            level TEXT NOT NULL,
+            code TEXT NOT NULL,
+            path TEXT NOT NULL,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (location_id, path),
+            FOREIGN KEY (location_id) REFERENCES locations(id),
+            FOREIGN KEY (parent_id) REFERENCES bins(id)
+        )",
+        [],
+    )?;
+
+    // Capas de costo por ingreso de stock (FIFO). `remaining_cost` es el
+    // costo de las unidades que quedan en la capa
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cost_layers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            stock_movement_id INTEGER,
+            quantity INTEGER NOT NULL,
+            remaining INTEGER NOT NULL,
+            remaining_cost INTEGER NOT NULL,
+            FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE,
+            FOREIGN KEY (stock_movement_id) REFERENCES stock_movements(id)
+        )",
+        [],
+    )?;
+
     // Create purchases table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS purchases (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             product_id INTEGER NOT NULL,
             supplier TEXT,
-            purchase_price REAL,
+            purchase_price INTEGER,
             purchase_date TEXT,
-            discount REAL,
+            discount INTEGER,
             expected_replenish_days INTEGER,
+            quantity INTEGER,
+            stock_movement_id INTEGER REFERENCES stock_movements(id),
+            created_by INTEGER REFERENCES users(id),
             FOREIGN KEY (product_id) REFERENCES products(id)
         )",
         [],
@@ -483,8 +778,18 @@ fn init_database() -> Result<Connection> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             product_id INTEGER NOT NULL,
             quantity INTEGER NOT NULL,
-            sale_price REAL NOT NULL,
+            unit_price INTEGER,
+            gross_amount INTEGER,
             discount REAL,
+            discount_amount INTEGER,
+            sale_price INTEGER NOT NULL,
+            ticket_id INTEGER REFERENCES tickets(id),
+            promotion_id INTEGER REFERENCES promotions(id),
+            promotion_amount INTEGER NOT NULL DEFAULT 0,
+            customer_id INTEGER REFERENCES customers(id),
+            points_amount INTEGER NOT NULL DEFAULT 0,
+            cost_amount INTEGER,
+            on_account INTEGER NOT NULL DEFAULT 0,
             channel TEXT,
             sale_date TEXT NOT NULL,
             created_by INTEGER,
@@ -497,93 +802,663 @@ fn init_database() -> Result<Connection> {
         "CREATE TABLE IF NOT EXISTS cash_movements (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             movement_type TEXT NOT NULL,
-            amount REAL NOT NULL,
+            amount INTEGER NOT NULL,
             category TEXT,
             description TEXT,
             movement_date TEXT NOT NULL,
+            payment_method TEXT NOT NULL DEFAULT 'efectivo',
+            session_id INTEGER REFERENCES cash_sessions(id),
             created_by INTEGER,
+            category_id INTEGER REFERENCES expense_categories(id),
+            status TEXT NOT NULL DEFAULT 'confirmado',
+            recurring_expense_id INTEGER REFERENCES recurring_expenses(id),
             FOREIGN KEY (created_by) REFERENCES users(id)
         )",
         [],
     )?;
 
-    // Insertar usuario admin por defecto si no existe
-    let user_count: i32 = conn.query_row(
-        "SELECT COUNT(*) FROM users",
+    // Categorías de gasto en árbol y plantillas de gastos recurrentes
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS expense_categories (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            parent_id INTEGER REFERENCES expense_categories(id),
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
         [],
-        |row| row.get(0)
-    ).unwrap_or(0);
-    
-    if user_count == 0 {
-        // Hash seguro de la contraseña "admin" con bcrypt
-        let admin_password_hash = hash("admin", DEFAULT_COST).expect("Failed to hash password");
-        
-        conn.execute(
-            "INSERT INTO users (username, password_hash, role, fullname) VALUES (?1, ?2, ?3, ?4)",
-            rusqlite::params!["admin", admin_password_hash, "Administrador", "Administrador del Sistema"],
-        )?;
-        println!("✅ Usuario admin por defecto creado con contraseña encriptada");
-    }
-
-    Ok(conn)
-}
-
-// Tauri commands
-#[tauri::command]
-fn get_products(state: State<AppState>) -> Result<Vec<Product>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, sku, name, sale_price, brand, category, presentation, flavor, weight, image_path, expiry_date, lot_number, min_stock, max_stock, location, status FROM products")
-        .map_err(|e| e.to_string())?;
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recurring_expenses (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            amount INTEGER NOT NULL,
+            category_id INTEGER REFERENCES expense_categories(id),
+            description TEXT,
+            frequency TEXT NOT NULL,
+            every INTEGER NOT NULL DEFAULT 1,
+            start_date TEXT NOT NULL,
+            end_date TEXT,
+            next_date TEXT NOT NULL,
+            occurrences INTEGER NOT NULL DEFAULT 0,
+            payment_method TEXT NOT NULL DEFAULT 'efectivo',
+            active INTEGER NOT NULL DEFAULT 1,
+            created_by INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )",
+        [],
+    )?;
 
-    let products = stmt
-        .query_map([], |row| {
-            Ok(Product {
-                id: row.get(0)?,
-                sku: row.get(1)?,
-                name: row.get(2)?,
-                sale_price: row.get(3)?,
-                brand: row.get(4)?,
-                category: row.get(5)?,
-                presentation: row.get(6)?,
-                flavor: row.get(7)?,
-                weight: row.get(8)?,
-                image_path: row.get(9)?,
-                expiry_date: row.get(10)?,
-                lot_number: row.get(11)?,
-                min_stock: row.get(12)?,
-                max_stock: row.get(13)?,
-                location: row.get(14)?,
-                status: row.get(15)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+    // Historial de precios de venta: una fila por cada cambio de sale_price
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS price_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            old_price INTEGER,
+            new_price INTEGER,
+            source TEXT NOT NULL,
+            changed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
 
-    Ok(products)
-}
+    // Cambios de precio programados (promociones con fecha de fin o cambios definitivos sin ella)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scheduled_prices (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            product_id INTEGER NOT NULL,
+            price INTEGER NOT NULL,
+            starts_at TEXT NOT NULL,
+            ends_at TEXT,
+            note TEXT,
+            applied_at TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
 
-#[tauri::command]
-fn add_product(state: State<AppState>, product: Product) -> Result<i64, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+    // Cabecera de cada venta; las filas de `sales` son sus líneas
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tickets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            customer_id INTEGER REFERENCES customers(id),
+            on_account INTEGER NOT NULL DEFAULT 0,
+            session_id INTEGER REFERENCES cash_sessions(id),
+            location_id INTEGER REFERENCES locations(id),
+            channel TEXT,
+            sale_date TEXT NOT NULL,
+            created_by INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )",
+        [],
+    )?;
 
-    if let Some(ref sku_val) = product.sku {
-        let existing = conn.query_row(
-            "SELECT id FROM products WHERE sku = ?1 LIMIT 1",
-            rusqlite::params![sku_val],
-            |row| row.get::<_, i32>(0),
-        );
-        match existing {
-            Ok(_id) => {
-                return Err("El SKU ya existe. Usa otro SKU o edita el producto existente.".to_string());
-            }
-            Err(rusqlite::Error::QueryReturnedNoRows) => {}
-            Err(e) => return Err(e.to_string()),
-        }
-    }
+    // Clientes. El documento es opcional pero no puede repetirse.
     conn.execute(
-        "INSERT INTO products (sku, name, sale_price, brand, category, presentation, flavor, weight, image_path, expiry_date, lot_number, min_stock, max_stock, location, status) 
+        "CREATE TABLE IF NOT EXISTS customers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            document_id TEXT,
+            phone TEXT,
+            email TEXT,
+            notes TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_customers_document ON customers(document_id) WHERE document_id IS NOT NULL",
+        [],
+    )?;
+
+    // Ajustes generales clave/valor
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Programa de puntos: reglas de acumulación y libro de movimientos.
+    // Una regla sin categoría es la general; la de la categoría del producto
+    // tiene prioridad sobre ella.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS loyalty_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            category TEXT UNIQUE,
+            points_per_unit REAL NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO loyalty_rules (category, points_per_unit)
+         SELECT NULL, 1 WHERE NOT EXISTS (SELECT 1 FROM loyalty_rules)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS loyalty_ledger (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            customer_id INTEGER NOT NULL,
+            ticket_id INTEGER,
+            entry_type TEXT NOT NULL,
+            points INTEGER NOT NULL,
+            remaining INTEGER NOT NULL DEFAULT 0,
+            expires_at TEXT,
+            note TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (customer_id) REFERENCES customers(id) ON DELETE CASCADE,
+            FOREIGN KEY (ticket_id) REFERENCES tickets(id)
+        )",
+        [],
+    )?;
+
+    // Cobros de ventas al crédito (fiado) y su reparto entre tickets
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS customer_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            customer_id INTEGER NOT NULL,
+            amount INTEGER NOT NULL,
+            method TEXT NOT NULL DEFAULT 'efectivo',
+            payment_date TEXT NOT NULL,
+            note TEXT,
+            cash_movement_id INTEGER,
+            created_by INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (customer_id) REFERENCES customers(id),
+            FOREIGN KEY (cash_movement_id) REFERENCES cash_movements(id),
+            FOREIGN KEY (created_by) REFERENCES users(id)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payment_allocations (
+            payment_id INTEGER NOT NULL,
+            ticket_id INTEGER NOT NULL,
+            amount INTEGER NOT NULL,
+            PRIMARY KEY (payment_id, ticket_id),
+            FOREIGN KEY (payment_id) REFERENCES customer_payments(id) ON DELETE CASCADE,
+            FOREIGN KEY (ticket_id) REFERENCES tickets(id)
+        )",
+        [],
+    )?;
+
+    // Turnos de caja: fondo inicial al abrir y arqueo por denominación al cerrar
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cash_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            register TEXT NOT NULL,
+            opened_by INTEGER,
+            opened_at TEXT NOT NULL,
+            opening_float INTEGER NOT NULL,
+            closed_by INTEGER,
+            closed_at TEXT,
+            expected_cash INTEGER,
+            counted_cash INTEGER,
+            variance INTEGER,
+            notes TEXT,
+            FOREIGN KEY (opened_by) REFERENCES users(id),
+            FOREIGN KEY (closed_by) REFERENCES users(id)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS cash_session_counts (
+            session_id INTEGER NOT NULL,
+            denomination INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            PRIMARY KEY (session_id, denomination),
+            FOREIGN KEY (session_id) REFERENCES cash_sessions(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Formas de pago de cada ticket. `tendered` es lo entregado por el
+    // cliente y `amount` lo aplicado al ticket (tendered - change_given)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ticket_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            ticket_id INTEGER NOT NULL,
+            method TEXT NOT NULL,
+            amount INTEGER NOT NULL,
+            tendered INTEGER NOT NULL,
+            change_given INTEGER NOT NULL DEFAULT 0,
+            reference TEXT,
+            FOREIGN KEY (ticket_id) REFERENCES tickets(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Reglas de promoción (ver src/promotions.rs)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS promotions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            percent REAL,
+            buy_qty INTEGER,
+            pay_qty INTEGER,
+            bundle_price INTEGER,
+            product_id INTEGER,
+            category TEXT,
+            brand TEXT,
+            flavor TEXT,
+            channels TEXT,
+            starts_at TEXT,
+            ends_at TEXT,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (product_id) REFERENCES products(id)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS promotion_bundle_items (
+            promotion_id INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            PRIMARY KEY (promotion_id, product_id),
+            FOREIGN KEY (promotion_id) REFERENCES promotions(id) ON DELETE CASCADE,
+            FOREIGN KEY (product_id) REFERENCES products(id)
+        )",
+        [],
+    )?;
+
+    migrate_money_columns(conn)?;
+    migrate_sale_amounts(conn)?;
+    ensure_columns(
+        conn,
+        "sales",
+        &[
+            ("ticket_id", "INTEGER REFERENCES tickets(id)"),
+            ("promotion_id", "INTEGER REFERENCES promotions(id)"),
+            ("promotion_amount", "INTEGER NOT NULL DEFAULT 0"),
+            ("customer_id", "INTEGER REFERENCES customers(id)"),
+            ("points_amount", "INTEGER NOT NULL DEFAULT 0"),
+            ("on_account", "INTEGER NOT NULL DEFAULT 0"),
+            ("cost_amount", "INTEGER"),
+        ],
+    )?;
+    ensure_columns(
+        conn,
+        "products",
+        &[
+            ("average_cost", "INTEGER"),
+            ("default_bin_id", "INTEGER REFERENCES bins(id)"),
+            ("parent_id", "INTEGER REFERENCES parent_products(id)"),
+        ],
+    )?;
+    migrate_product_parents(conn)?;
+    migrate_product_barcodes(conn)?;
+    ensure_columns(
+        conn,
+        "stock_movements",
+        &[
+            ("cost_amount", "INTEGER"),
+            ("location_id", "INTEGER REFERENCES locations(id)"),
+            ("transfer_id", "INTEGER REFERENCES stock_transfers(id)"),
+            ("bin_id", "INTEGER REFERENCES bins(id)"),
+        ],
+    )?;
+    // Ubicación por defecto y la de tránsito; los movimientos anteriores a
+    // las ubicaciones quedan en la por defecto
+    let locations: i64 = conn.query_row("SELECT COUNT(*) FROM locations WHERE kind != 'transito'", [], |row| row.get(0))?;
+    if locations == 0 {
+        conn.execute(
+            "INSERT INTO locations (name, kind, branch, is_default) VALUES ('Tienda', 'tienda', 'Principal', 1)",
+            [],
+        )?;
+    }
+    conn.execute(
+        "INSERT INTO locations (name, kind, branch, active)
+         SELECT 'En tránsito', 'transito', '', 1 WHERE NOT EXISTS (SELECT 1 FROM locations WHERE kind = 'transito')",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE stock_movements SET location_id = (SELECT id FROM locations WHERE is_default = 1) WHERE location_id IS NULL",
+        [],
+    )?;
+    migrate_product_bins(conn)?;
+    ensure_columns(
+        conn,
+        "purchases",
+        &[
+            ("quantity", "INTEGER"),
+            ("stock_movement_id", "INTEGER REFERENCES stock_movements(id)"),
+            ("created_by", "INTEGER REFERENCES users(id)"),
+        ],
+    )?;
+    // Costo inicial: el precio de la última compra registrada, y una capa de
+    // apertura con el stock que ya había antes del costeo
+    conn.execute(
+        "UPDATE products SET average_cost = (
+             SELECT purchase_price FROM purchases WHERE product_id = products.id AND purchase_price IS NOT NULL
+             ORDER BY purchase_date DESC, id DESC LIMIT 1)
+         WHERE average_cost IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO cost_layers (product_id, quantity, remaining, remaining_cost)
+         SELECT p.id, st.qty, st.qty, COALESCE(p.average_cost, 0) * st.qty
+         FROM products p
+         JOIN (SELECT product_id, SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END) AS qty
+               FROM stock_movements GROUP BY product_id) st ON st.product_id = p.id
+         WHERE st.qty > 0 AND NOT EXISTS (SELECT 1 FROM cost_layers l WHERE l.product_id = p.id)",
+        [],
+    )?;
+    ensure_columns(
+        conn,
+        "cash_movements",
+        &[
+            ("payment_method", "TEXT NOT NULL DEFAULT 'efectivo'"),
+            ("session_id", "INTEGER REFERENCES cash_sessions(id)"),
+            ("category_id", "INTEGER REFERENCES expense_categories(id)"),
+            ("status", "TEXT NOT NULL DEFAULT 'confirmado'"),
+            ("recurring_expense_id", "INTEGER REFERENCES recurring_expenses(id)"),
+        ],
+    )?;
+    // Categorías por defecto la primera vez, y una categoría por cada
+    // categoría de texto libre ya usada en egresos
+    let categories: i64 = conn.query_row("SELECT COUNT(*) FROM expense_categories", [], |row| row.get(0))?;
+    if categories == 0 {
+        for name in ["Alquiler", "Sueldos", "Servicios", "Limpieza", "Mantenimiento", "Otros"] {
+            conn.execute("INSERT INTO expense_categories (name) VALUES (?1)", [name])?;
+        }
+        for name in ["Luz", "Agua", "Internet"] {
+            conn.execute(
+                "INSERT INTO expense_categories (name, parent_id) SELECT ?1, id FROM expense_categories WHERE name='Servicios'",
+                [name],
+            )?;
+        }
+    }
+    conn.execute(
+        "INSERT OR IGNORE INTO expense_categories (name)
+         SELECT DISTINCT TRIM(category) FROM cash_movements
+         WHERE movement_type='egreso' AND category_id IS NULL AND TRIM(COALESCE(category, '')) != ''",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE cash_movements SET category_id = (SELECT id FROM expense_categories WHERE name = TRIM(cash_movements.category))
+         WHERE movement_type='egreso' AND category_id IS NULL AND category IS NOT NULL",
+        [],
+    )?;
+    ensure_columns(conn, "customer_payments", &[("method", "TEXT NOT NULL DEFAULT 'efectivo'")])?;
+    ensure_columns(
+        conn,
+        "tickets",
+        &[
+            ("customer_id", "INTEGER REFERENCES customers(id)"),
+            ("on_account", "INTEGER NOT NULL DEFAULT 0"),
+            ("session_id", "INTEGER REFERENCES cash_sessions(id)"),
+            ("location_id", "INTEGER REFERENCES locations(id)"),
+        ],
+    )?;
+    // Tickets registrados antes de guardar formas de pago: un solo pago por
+    // el total, en efectivo o al crédito
+    conn.execute(
+        "INSERT INTO ticket_payments (ticket_id, method, amount, tendered)
+         SELECT t.id, CASE WHEN t.on_account = 1 THEN 'credito' ELSE 'efectivo' END,
+                SUM(s.sale_price), SUM(s.sale_price)
+         FROM tickets t JOIN sales s ON s.ticket_id = t.id
+         WHERE NOT EXISTS (SELECT 1 FROM ticket_payments p WHERE p.ticket_id = t.id)
+         GROUP BY t.id",
+        [],
+    )?;
+
+    // Insertar usuario admin por defecto si no existe
+    let user_count: i32 = conn.query_row(
+        "SELECT COUNT(*) FROM users",
+        [],
+        |row| row.get(0)
+    ).unwrap_or(0);
+    
+    if user_count == 0 {
+        // Hash seguro de la contraseña "admin" con bcrypt
+        let admin_password_hash = hash("admin", DEFAULT_COST).expect("Failed to hash password");
+        
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role, fullname) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params!["admin", admin_password_hash, "Administrador", "Administrador del Sistema"],
+        )?;
+        println!("✅ Usuario admin por defecto creado con contraseña encriptada");
+    }
+
+    Ok(())
+}
+
+// Agrega a `table` las columnas que falten (bases creadas con versiones anteriores)
+fn ensure_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let mut col_names: Vec<String> = Vec::new();
+    {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        for r in rows {
+            col_names.push(r?);
+        }
+    }
+    for (name, decl) in columns {
+        if !col_names.iter().any(|c| c == name) {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, name, decl), [])?;
+        }
+    }
+    Ok(())
+}
+
+// Las ventas antiguas solo guardaban el total neto (`sale_price`) y el
+// porcentaje de descuento. Se agregan las columnas de importes y se
+// reconstruye el bruto a partir de ambos.
+fn migrate_sale_amounts(conn: &Connection) -> Result<()> {
+    ensure_columns(
+        conn,
+        "sales",
+        &[
+            ("unit_price", "INTEGER"),
+            ("gross_amount", "INTEGER"),
+            ("discount_amount", "INTEGER"),
+        ],
+    )?;
+    conn.execute(
+        "UPDATE sales
+         SET gross_amount = CASE
+                 WHEN COALESCE(discount,0) > 0 AND discount < 100
+                 THEN CAST(ROUND(sale_price * 100.0 / (100 - discount)) AS INTEGER)
+                 ELSE sale_price
+             END
+         WHERE gross_amount IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE sales SET discount_amount = gross_amount - sale_price WHERE discount_amount IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE sales SET unit_price = CAST(ROUND(gross_amount * 1.0 / quantity) AS INTEGER)
+         WHERE unit_price IS NULL AND quantity > 0",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Importes monetarios que antes se guardaban como REAL y ahora son INTEGER en céntimos
+const MONEY_COLUMNS: &[(&str, &[&str])] = &[
+    ("products", &["sale_price"]),
+    ("purchases", &["purchase_price", "discount"]),
+    ("sales", &["sale_price"]),
+    ("cash_movements", &["amount"]),
+];
+
+// Las bases creadas antes de usar céntimos tienen esas columnas como REAL.
+// SQLite no permite cambiar el tipo de una columna, así que se reconstruye la
+// tabla: se crea una copia con el esquema nuevo, se copian los datos
+// convirtiendo a céntimos y se reemplaza la original.
+fn migrate_money_columns(conn: &Connection) -> Result<()> {
+    // Al terminar se deja la configuración de claves foráneas como estaba
+    let foreign_keys: i64 = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    let restore_foreign_keys = format!("PRAGMA foreign_keys = {}", if foreign_keys != 0 { "ON" } else { "OFF" });
+    for (table, money_cols) in MONEY_COLUMNS {
+        let mut columns: Vec<(String, String)> = Vec::new();
+        {
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+            for r in rows {
+                columns.push(r?);
+            }
+        }
+        let needs_migration = columns
+            .iter()
+            .any(|(name, ty)| money_cols.contains(&name.as_str()) && ty.eq_ignore_ascii_case("REAL"));
+        if !needs_migration {
+            continue;
+        }
+
+        let create_sql: String = conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type='table' AND name=?1",
+            [table],
+            |row| row.get(0),
+        )?;
+        let tmp_table = format!("{}_money_migration", table);
+        let mut new_sql = create_sql.replacen(table, &tmp_table, 1);
+        for col in money_cols.iter() {
+            new_sql = new_sql.replace(&format!("{} REAL", col), &format!("{} INTEGER", col));
+        }
+        let column_list = columns.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", ");
+        let placeholders = (1..=columns.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+
+        // Las claves foráneas se desactivan mientras se reemplaza la tabla
+        // (no se puede cambiar dentro de una transacción)
+        conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+        conn.execute("BEGIN IMMEDIATE TRANSACTION", [])?;
+        let result: Result<()> = (|| {
+            conn.execute(&new_sql, [])?;
+            let mut select = conn.prepare(&format!("SELECT {} FROM {}", column_list, table))?;
+            let mut insert = conn.prepare(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                tmp_table, column_list, placeholders
+            ))?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(columns.len());
+                for (i, (name, _)) in columns.iter().enumerate() {
+                    let value: rusqlite::types::Value = row.get(i)?;
+                    let value = match value {
+                        rusqlite::types::Value::Real(v) if money_cols.contains(&name.as_str()) => {
+                            let money = Money::from_major(v).ok_or(rusqlite::Error::IntegralValueOutOfRange(i, v as i64))?;
+                            rusqlite::types::Value::Integer(money.cents())
+                        }
+                        rusqlite::types::Value::Integer(v) if money_cols.contains(&name.as_str()) => {
+                            let cents = v.checked_mul(money::CENTS_PER_UNIT).ok_or(rusqlite::Error::IntegralValueOutOfRange(i, v))?;
+                            rusqlite::types::Value::Integer(cents)
+                        }
+                        other => other,
+                    };
+                    values.push(value);
+                }
+                insert.execute(rusqlite::params_from_iter(values))?;
+            }
+            conn.execute(&format!("DROP TABLE {}", table), [])?;
+            conn.execute(&format!("ALTER TABLE {} RENAME TO {}", tmp_table, table), [])?;
+            Ok(())
+        })();
+        match result {
+            Ok(()) => conn.execute("COMMIT", []).map(|_| ())?,
+            Err(e) => {
+                let _ = conn.execute("ROLLBACK", []);
+                let _ = conn.execute_batch(&restore_foreign_keys);
+                return Err(e);
+            }
+        }
+        conn.execute_batch(&restore_foreign_keys)?;
+        println!("✅ Columnas monetarias de {} migradas a céntimos", table);
+    }
+    Ok(())
+}
+
+// Tauri commands
+#[tauri::command]
+fn get_products(state: State<AppState>) -> Result<Vec<Product>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM products", PRODUCT_COLUMNS))?;
+
+    let products = stmt
+        .query_map([], product_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(products)
+}
+
+const PRODUCT_COLUMNS: &str = "id, sku, name, sale_price, brand, category, presentation, flavor, weight, image_path, expiry_date, lot_number, min_stock, max_stock, location, status, default_bin_id, parent_id";
+
+fn product_from_row(row: &rusqlite::Row) -> Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        sku: row.get(1)?,
+        name: row.get(2)?,
+        sale_price: row.get(3)?,
+        brand: row.get(4)?,
+        category: row.get(5)?,
+        presentation: row.get(6)?,
+        flavor: row.get(7)?,
+        weight: row.get(8)?,
+        image_path: row.get(9)?,
+        expiry_date: row.get(10)?,
+        lot_number: row.get(11)?,
+        min_stock: row.get(12)?,
+        max_stock: row.get(13)?,
+        location: row.get(14)?,
+        status: row.get(15)?,
+        default_bin_id: row.get(16)?,
+        parent_id: row.get(17)?,
+    })
+}
+
+#[tauri::command]
+fn add_product(state: State<AppState>, product: Product) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    let new_id = in_transaction(&conn, || {
+        let new_id = insert_product(&conn, &product)?;
+        if let Some(max_qty) = product.max_stock {
+            if max_qty > 0 {
+                conn.execute(
+                    "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id)
+                     VALUES (?1, 'ingreso', ?2, ?3, ?4, (SELECT id FROM locations WHERE is_default = 1))",
+                    rusqlite::params![new_id as i32, max_qty, Option::<String>::None, Option::<i32>::None],
+                )?;
+                receive_stock_cost(&conn, new_id as i32, conn.last_insert_rowid(), max_qty as i64, None)?;
+            }
+        }
+        Ok(new_id)
+    })?;
+    // La copia de la imagen va después del commit
+    import_product_image(&conn, Path::new(MEDIA_DIR), new_id)?;
+
+    Ok(new_id)
+}
+
+// Alta de un producto: SKU único, bin por defecto, producto padre e
+// historial de precios
+fn insert_product(conn: &Connection, product: &Product) -> Result<i64, AppError> {
+    if let Some(ref sku_val) = product.sku {
+        let existing = conn.query_row(
+            "SELECT id FROM products WHERE sku = ?1 LIMIT 1",
+            rusqlite::params![sku_val],
+            |row| row.get::<_, i32>(0),
+        );
+        match existing {
+            Ok(_id) => {
+                return Err(AppError::conflict("sku"));
+            }
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let bin_id = resolve_product_bin(conn, product)?;
+    let parent_id = resolve_parent_product(conn, product)?;
+    conn.execute(
+        "INSERT INTO products (sku, name, sale_price, brand, category, presentation, flavor, weight, image_path, expiry_date, lot_number, min_stock, max_stock, status, parent_id) 
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
         rusqlite::params![
             product.sku,
@@ -599,38 +1474,50 @@ fn add_product(state: State<AppState>, product: Product) -> Result<i64, String>
             product.lot_number,
             product.min_stock,
             product.max_stock,
-            product.location,
             product.status,
+            parent_id,
         ],
-    )
-    .map_err(|e| {
-        let msg = e.to_string();
-        if msg.contains("UNIQUE constraint failed: products.sku") {
-            "El SKU ya existe. Usa otro SKU o edita el producto existente.".to_string()
-        } else {
-            msg
-        }
-    })?;
+    )?;
 
     let new_id = conn.last_insert_rowid();
+    set_product_bin(conn, new_id, bin_id)?;
 
-    if let Some(max_qty) = product.max_stock {
-        if max_qty > 0 {
-            let _ = conn.execute(
-                "INSERT INTO stock_movements (product_id, type, quantity, note, created_by) VALUES (?1, 'ingreso', ?2, ?3, ?4)",
-                rusqlite::params![new_id as i32, max_qty, Option::<String>::None, Option::<i32>::None],
-            );
-        }
+    if product.sale_price.is_some() {
+        record_price_change(conn, new_id, None, product.sale_price, None, "alta")?;
     }
 
     Ok(new_id)
 }
 
 #[tauri::command]
-fn update_product(state: State<AppState>, product: Product) -> Result<(), String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn update_product(state: State<AppState>, product: Product) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let id = product.id.ok_or(AppError::validation("id"))?;
+    let old_image = product_image_path(&conn, id as i64)?;
+    in_transaction(&conn, || update_product_row(&conn, &product))?;
+    // Los archivos se tocan después del COMMIT
+    if old_image != product.image_path {
+        let media_dir = Path::new(MEDIA_DIR);
+        import_product_image(&conn, media_dir, id as i64)?;
+        release_image(&conn, media_dir, old_image)?;
+    }
+    Ok(())
+}
+
+fn update_product_row(conn: &Connection, product: &Product) -> Result<(), AppError> {
+    let (old_price, old_parent): (Option<Money>, Option<i64>) = conn
+        .query_row(
+            "SELECT sale_price, parent_id FROM products WHERE id=?1",
+            rusqlite::params![product.id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })?;
+    let parent_id = resolve_parent_product(conn, product)?;
     conn.execute(
-        "UPDATE products SET sku=?1, name=?2, sale_price=?3, brand=?4, category=?5, presentation=?6, flavor=?7, weight=?8, image_path=?9, expiry_date=?10, lot_number=?11, min_stock=?12, max_stock=?13, location=?14, status=?15 
+        "UPDATE products SET sku=?1, name=?2, sale_price=?3, brand=?4, category=?5, presentation=?6, flavor=?7, weight=?8, image_path=?9, expiry_date=?10, lot_number=?11, min_stock=?12, max_stock=?13, status=?14, parent_id=?15 
          WHERE id=?16",
         rusqlite::params![
             product.sku,
@@ -646,228 +1533,4254 @@ fn update_product(state: State<AppState>, product: Product) -> Result<(), String
             product.lot_number,
             product.min_stock,
             product.max_stock,
-            product.location,
             product.status,
+            parent_id,
             product.id,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
+    delete_orphan_parent(conn, old_parent)?;
+    if let Some(id) = product.id {
+        let bin_id = resolve_product_bin(conn, product)?;
+        set_product_bin(conn, id as i64, bin_id)?;
+    }
+    if old_price != product.sale_price {
+        if let Some(id) = product.id {
+            record_price_change(conn, id as i64, old_price, product.sale_price, None, "manual")?;
+        }
+    }
 
     Ok(())
 }
 
 #[tauri::command]
-fn delete_product(state: State<AppState>, id: i32) -> Result<(), String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM products WHERE id=?1", [id])
-        .map_err(|e| e.to_string())?;
+fn delete_product(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let image_path = in_transaction(&conn, || delete_product_row(&conn, id))?;
+    // El archivo se borra recién con la baja confirmada
+    release_image(&conn, Path::new(MEDIA_DIR), image_path)?;
 
     Ok(())
 }
 
-#[tauri::command]
-fn get_stock_movements(state: State<AppState>) -> Result<Vec<StockMovement>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, product_id, type, quantity, note, created_by FROM stock_movements ORDER BY created_at DESC LIMIT 100")
-        .map_err(|e| e.to_string())?;
+// Borra el producto con sus códigos y, si era la última variante, el padre.
+// Devuelve la imagen que tenía.
+fn delete_product_row(conn: &Connection, id: i32) -> Result<Option<String>, AppError> {
+    let parent_id: Option<i64> = conn
+        .query_row("SELECT parent_id FROM products WHERE id=?1", [id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })?;
+    let image_path = product_image_path(conn, id as i64)?;
+    conn.execute("DELETE FROM product_barcodes WHERE product_id=?1", [id])?;
+    conn.execute("DELETE FROM products WHERE id=?1", [id])?;
+    delete_orphan_parent(conn, parent_id)?;
+    Ok(image_path)
+}
 
-    let movements = stmt
-        .query_map([], |row| {
-            Ok(StockMovement {
-                id: row.get(0)?,
-                product_id: row.get(1)?,
-                movement_type: row.get(2)?,
-                quantity: row.get(3)?,
-                note: row.get(4)?,
-                created_by: row.get(5)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+// ============================================
+// LABEL COMMANDS
+// ============================================
+//
+// Etiquetas de góndola (nombre, sabor, precio y código de barras) y de
+// precio para los productos elegidos, en PDF o SVG sobre las plantillas de
+// labels.rs. Se guardan junto a los demás exportes en Descargas/VitaSport.
 
-    Ok(movements)
+// Tope de copias por producto en una sola exportación
+const MAX_LABEL_COPIES: u32 = 500;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LabelRequest {
+    product_ids: Vec<i32>,
+    /// Copias de cada producto, de 1 a MAX_LABEL_COPIES (por defecto 1)
+    #[serde(default)]
+    copies: Option<u32>,
+    /// "a4_3x8", "a4_5x13" o "rollo_50x25"
+    template: String,
+    /// "estante" (por defecto, con código de barras) o "precio"
+    #[serde(default)]
+    style: Option<String>,
+    /// "pdf" o "svg" (un archivo por hoja)
+    format: String,
 }
 
-#[tauri::command]
-fn add_stock_movement(state: State<AppState>, movement: StockMovement) -> Result<i64, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO stock_movements (product_id, type, quantity, note, created_by) 
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        rusqlite::params![
-            movement.product_id,
-            movement.movement_type,
-            movement.quantity,
-            movement.note,
+// Datos de la etiqueta de cada producto, en el orden pedido. El código de
+// barras es el primero que se pueda dibujar (EAN/UPC), si no el primero
+// cargado y, si no tiene, el SKU.
+fn product_labels(conn: &Connection, product_ids: &[i32], copies: u32) -> Result<Vec<labels::Label>, AppError> {
+    let mut result = Vec::new();
+    for product_id in product_ids {
+        let label = conn
+            .query_row(
+                "SELECT p.name, p.flavor, p.presentation, p.weight, p.sale_price,
+                        COALESCE((SELECT code FROM product_barcodes b WHERE b.product_id = p.id
+                                  ORDER BY b.kind IN ('ean13', 'upca', 'ean8') DESC, b.id LIMIT 1), p.sku)
+                 FROM products p WHERE p.id=?1",
+                [product_id],
+                |row| {
+                    let detail: Vec<String> = [row.get::<_, Option<String>>(1)?, row.get(2)?, row.get(3)?]
+                        .into_iter()
+                        .flatten()
+                        .filter(|s| !s.trim().is_empty())
+                        .collect();
+                    let price: Option<Money> = row.get(4)?;
+                    Ok(labels::Label {
+                        name: row.get(0)?,
+                        detail: (!detail.is_empty()).then(|| detail.join(" · ")),
+                        price: price.map(|p| format!("${}", p)).unwrap_or_default(),
+                        barcode: row.get(5)?,
+                    })
+                },
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+                other => other.into(),
+            })?;
+        for _ in 0..copies {
+            result.push(label.clone());
+        }
+    }
+    Ok(result)
+}
+
+// Devuelve las rutas de los archivos generados
+#[tauri::command]
+fn export_labels(state: State<AppState>, request: LabelRequest) -> Result<Vec<String>, AppError> {
+    let template = labels::template(&request.template).ok_or(AppError::validation("template"))?;
+    let style = request.style.as_deref().unwrap_or(labels::STYLE_SHELF);
+    if ![labels::STYLE_SHELF, labels::STYLE_PRICE].contains(&style) {
+        return Err(AppError::validation("style"));
+    }
+    if !["pdf", "svg"].contains(&request.format.as_str()) {
+        return Err(AppError::validation("format"));
+    }
+    let copies = request.copies.unwrap_or(1);
+    if !(1..=MAX_LABEL_COPIES).contains(&copies) {
+        return Err(AppError::validation("copies"));
+    }
+    if request.product_ids.is_empty() {
+        return Err(AppError::validation("product_ids"));
+    }
+    let items = {
+        let conn = state.db.lock()?;
+        product_labels(&conn, &request.product_ids, copies)?
+    };
+    let pages = labels::layout(template, &items, style);
+
+    let name = format!("etiquetas_{}", template.key);
+    let mut paths = Vec::new();
+    if request.format == "pdf" {
+        let path = export_path(&name, "pdf")?;
+        fs::write(&path, labels::to_pdf(template, &pages, "Etiquetas VitaSport")?)?;
+        paths.push(path.to_string_lossy().to_string());
+    } else {
+        for (i, page) in pages.iter().enumerate() {
+            let path = export_path(&format!("{}_{}", name, i + 1), "svg")?;
+            fs::write(&path, labels::to_svg(template, page))?;
+            paths.push(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(paths)
+}
+
+// ============================================
+// RECEIPT COMMANDS
+// ============================================
+//
+// Ticket impreso de una venta (ver receipt.rs): texto para vista previa,
+// ESC/POS para impresoras térmicas de 58/80 mm y PDF. Los datos del
+// comercio, el ancho del rollo y la impresora se guardan en `settings`.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReceiptSettings {
+    store_name: String,
+    address: Option<String>,
+    phone: Option<String>,
+    /// RUC del comercio
+    tax_id: Option<String>,
+    /// Texto al pie del ticket; None = "¡Gracias por su compra!"
+    footer: Option<String>,
+    /// 58 o 80
+    paper_width: u32,
+    /// Ruta de la impresora (/dev/usb/lp0, \\.\COM3...) o de un archivo;
+    /// None = archivo en Descargas/VitaSport
+    printer: Option<String>,
+}
+
+fn receipt_settings(conn: &Connection) -> Result<ReceiptSettings, AppError> {
+    let text = |key: &str| -> Result<Option<String>, AppError> {
+        Ok(get_setting(conn, key)?.filter(|v| !v.trim().is_empty()))
+    };
+    Ok(ReceiptSettings {
+        store_name: text("store_name")?.unwrap_or_else(|| "VitaSport".to_string()),
+        address: text("store_address")?,
+        phone: text("store_phone")?,
+        tax_id: text("store_tax_id")?,
+        footer: text("receipt_footer")?,
+        paper_width: text("receipt_paper_width")?.and_then(|v| v.parse().ok()).unwrap_or(80),
+        printer: text("receipt_printer")?,
+    })
+}
+
+fn payment_method_label(method: &str) -> String {
+    match method {
+        "efectivo" => "Efectivo",
+        "tarjeta" => "Tarjeta",
+        "transferencia" => "Transferencia",
+        "billetera" => "Billetera digital",
+        "credito" => "Crédito",
+        "puntos" => "Puntos",
+        other => other,
+    }
+    .to_string()
+}
+
+fn receipt_for_ticket(conn: &Connection, ticket_id: i32) -> Result<receipt::Receipt, AppError> {
+    let settings = receipt_settings(conn)?;
+    let (date, cashier, customer): (String, Option<String>, Option<String>) = conn
+        .query_row(
+            "SELECT t.sale_date, COALESCE(NULLIF(u.fullname, ''), u.username), c.name
+             FROM tickets t
+             LEFT JOIN users u ON u.id = t.created_by
+             LEFT JOIN customers c ON c.id = t.customer_id
+             WHERE t.id=?1",
+            [ticket_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("ticket"),
+            other => other.into(),
+        })?;
+
+    let mut stmt = conn.prepare(
+        "SELECT p.name || COALESCE(' ' || NULLIF(p.flavor, ''), ''), s.quantity, s.unit_price, s.gross_amount, s.discount_amount, s.sale_price
+         FROM sales s JOIN products p ON p.id = s.product_id
+         WHERE s.ticket_id=?1 ORDER BY s.id",
+    )?;
+    let lines = stmt
+        .query_map([ticket_id], |row| {
+            Ok(receipt::ReceiptLine {
+                name: row.get(0)?,
+                quantity: row.get(1)?,
+                unit_price: row.get::<_, Option<Money>>(2)?.unwrap_or_default(),
+                gross: row.get::<_, Option<Money>>(3)?.unwrap_or_default(),
+                discount: row.get::<_, Option<Money>>(4)?.unwrap_or_default(),
+                net: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT method, tendered, change_given, reference FROM ticket_payments WHERE ticket_id=?1 ORDER BY id",
+    )?;
+    let payments = stmt
+        .query_map([ticket_id], |row| {
+            Ok(receipt::ReceiptPayment {
+                method: payment_method_label(&row.get::<_, String>(0)?),
+                tendered: row.get(1)?,
+                change: row.get(2)?,
+                reference: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(receipt::Receipt {
+        store: receipt::Store {
+            name: settings.store_name,
+            address: settings.address,
+            phone: settings.phone,
+            tax_id: settings.tax_id,
+            footer: settings.footer,
+        },
+        ticket_id: ticket_id as i64,
+        // "2026-10-19T10:30:00" -> "2026-10-19 10:30"
+        date: date.replacen('T', " ", 1).chars().take(16).collect(),
+        cashier,
+        customer,
+        lines,
+        payments,
+    })
+}
+
+// Ancho pedido o el de la configuración
+fn receipt_paper(conn: &Connection, paper_width: Option<u32>) -> Result<&'static receipt::Paper, AppError> {
+    let width = match paper_width {
+        Some(width) => width,
+        None => receipt_settings(conn)?.paper_width,
+    };
+    receipt::paper(width).ok_or(AppError::validation("paper_width"))
+}
+
+#[tauri::command]
+fn get_receipt_settings(state: State<AppState>) -> Result<ReceiptSettings, AppError> {
+    let conn = state.db.lock()?;
+    receipt_settings(&conn)
+}
+
+#[tauri::command]
+fn update_receipt_settings(state: State<AppState>, settings: ReceiptSettings) -> Result<(), AppError> {
+    if settings.store_name.trim().is_empty() {
+        return Err(AppError::validation("store_name"));
+    }
+    if receipt::paper(settings.paper_width).is_none() {
+        return Err(AppError::validation("paper_width"));
+    }
+    let conn = state.db.lock()?;
+    let optional = |v: &Option<String>| v.as_deref().map(str::trim).unwrap_or("").to_string();
+    set_setting(&conn, "store_name", settings.store_name.trim())?;
+    set_setting(&conn, "store_address", &optional(&settings.address))?;
+    set_setting(&conn, "store_phone", &optional(&settings.phone))?;
+    set_setting(&conn, "store_tax_id", &optional(&settings.tax_id))?;
+    set_setting(&conn, "receipt_footer", &optional(&settings.footer))?;
+    set_setting(&conn, "receipt_paper_width", &settings.paper_width.to_string())?;
+    set_setting(&conn, "receipt_printer", &optional(&settings.printer))?;
+    Ok(())
+}
+
+// Vista previa en texto plano
+#[tauri::command]
+fn get_receipt_text(state: State<AppState>, ticket_id: i32, paper_width: Option<u32>) -> Result<String, AppError> {
+    let conn = state.db.lock()?;
+    let paper = receipt_paper(&conn, paper_width)?;
+    Ok(receipt::to_text(&receipt_for_ticket(&conn, ticket_id)?, paper))
+}
+
+// Envía el ticket en ESC/POS a `device`, a la impresora configurada o, si
+// no hay ninguna, a un archivo .prn nuevo en Descargas/VitaSport. Devuelve
+// la ruta.
+#[tauri::command]
+fn print_receipt(
+    state: State<AppState>,
+    ticket_id: i32,
+    paper_width: Option<u32>,
+    device: Option<String>,
+) -> Result<String, AppError> {
+    let (bytes, printer) = {
+        let conn = state.db.lock()?;
+        let paper = receipt_paper(&conn, paper_width)?;
+        let bytes = receipt::to_escpos(&receipt_for_ticket(&conn, ticket_id)?, paper);
+        (bytes, printer_target(device, receipt_settings(&conn)?.printer))
+    };
+    let path = match printer {
+        Some(device) => {
+            write_raw(&device, &bytes)?;
+            device
+        }
+        None => {
+            let path = export_path(&format!("ticket_{:06}", ticket_id), "prn")?;
+            fs::write(&path, &bytes)?;
+            path
+        }
+    };
+    Ok(path.to_string_lossy().to_string())
+}
+
+// Destino del ticket: el `device` pedido solo si es un dispositivo que ya
+// existe (puerto o impresora) o la impresora configurada. Un archivo común
+// nunca se sobrescribe: en ese caso se usa el archivo en Descargas.
+fn printer_target(device: Option<String>, configured: Option<String>) -> Option<PathBuf> {
+    let is_device = |path: &str| fs::metadata(path).map(|m| !m.is_file() && !m.is_dir()).unwrap_or(false);
+    let is_regular = |path: &str| fs::metadata(path).map(|m| m.is_file() || m.is_dir()).unwrap_or(false);
+    let target = match device.filter(|d| !d.trim().is_empty()) {
+        Some(device) if is_device(&device) || configured.as_deref() == Some(device.as_str()) => Some(device),
+        Some(_) => None,
+        None => configured,
+    };
+    // La impresora configurada vale aunque no se pueda consultar (p. ej. un
+    // recurso compartido de Windows), pero no si es un archivo
+    target.filter(|path| !is_regular(path)).map(PathBuf::from)
+}
+
+// Los dispositivos (puertos e impresoras) se abren sin truncar ni crear
+fn write_raw(path: &Path, bytes: &[u8]) -> Result<(), AppError> {
+    let mut device = fs::OpenOptions::new().write(true).open(path)?;
+    device.write_all(bytes)?;
+    device.flush()?;
+    Ok(())
+}
+
+#[tauri::command]
+fn export_receipt_pdf(state: State<AppState>, ticket_id: i32, paper_width: Option<u32>) -> Result<String, AppError> {
+    let pdf = {
+        let conn = state.db.lock()?;
+        let paper = receipt_paper(&conn, paper_width)?;
+        receipt::to_pdf(&receipt_for_ticket(&conn, ticket_id)?, paper)?
+    };
+    let path = export_path(&format!("ticket_{:06}", ticket_id), "pdf")?;
+    fs::write(&path, pdf)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+// ============================================
+// PRICE COMMANDS
+// ============================================
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PriceChange {
+    id: i32,
+    product_id: i32,
+    old_price: Option<Money>,
+    new_price: Option<Money>,
+    /// "alta", "manual" o "programado"
+    source: String,
+    changed_at: String,
+}
+
+// `starts_at` y `ends_at` aceptan fecha ("2024-03-01") o fecha y hora ISO.
+// Si `ends_at` es solo una fecha, ese día completo queda incluido. Sin
+// `ends_at` el cambio es definitivo: al llegar la fecha se copia al precio de
+// lista del producto y se marca con `applied_at`.
+#[derive(Debug, Serialize, Deserialize)]
+struct ScheduledPrice {
+    id: Option<i32>,
+    product_id: i32,
+    price: Money,
+    starts_at: String,
+    ends_at: Option<String>,
+    note: Option<String>,
+    applied_at: Option<String>,
+}
+
+// Condición SQL "la fila `alias` está vigente en el parámetro `at`", con
+// starts_at/ends_at opcionales y ends_at inclusivo si es solo fecha
+fn active_at_sql(alias: &str, at: &str) -> String {
+    format!(
+        "({a}.starts_at IS NULL OR datetime({a}.starts_at) <= datetime({at}))
+         AND ({a}.ends_at IS NULL OR datetime({at}) < CASE WHEN length({a}.ends_at) = 10
+                                                      THEN datetime({a}.ends_at, '+1 day')
+                                                      ELSE datetime({a}.ends_at) END)",
+        a = alias,
+        at = at
+    )
+}
+
+fn record_price_change(
+    conn: &Connection,
+    product_id: i64,
+    old_price: Option<Money>,
+    new_price: Option<Money>,
+    changed_at: Option<&str>,
+    source: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO price_history (product_id, old_price, new_price, source, changed_at)
+         VALUES (?1, ?2, ?3, ?4, COALESCE(datetime(?5), CURRENT_TIMESTAMP))",
+        rusqlite::params![product_id, old_price, new_price, source, changed_at],
+    )?;
+    Ok(())
+}
+
+/// Precio que se cobra por un producto en un momento dado: el programado
+/// vigente (el que empezó más tarde si se solapan) o, si no hay, el de lista.
+fn effective_price(conn: &Connection, product_id: i32, at: &str) -> Result<Option<Money>, AppError> {
+    let scheduled: Option<Money> = conn
+        .query_row(
+            &format!(
+                "SELECT sp.price FROM scheduled_prices sp
+                 WHERE sp.product_id = ?1 AND sp.applied_at IS NULL AND {}
+                 ORDER BY datetime(sp.starts_at) DESC, sp.id DESC LIMIT 1",
+                active_at_sql("sp", "?2")
+            ),
+            rusqlite::params![product_id, at],
+            |row| row.get(0),
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            other => Err(other),
+        })?;
+    if scheduled.is_some() {
+        return Ok(scheduled);
+    }
+    conn.query_row(
+        "SELECT sale_price FROM products WHERE id=?1",
+        rusqlite::params![product_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+        other => other.into(),
+    })
+}
+
+/// Precio que tenía un producto en una fecha pasada, reconstruido con el
+/// historial y los programados de esa fecha.
+fn price_at(conn: &Connection, product_id: i32, at: &str) -> Result<Option<Money>, AppError> {
+    if conn
+        .query_row("SELECT datetime(?1)", [at], |row| row.get::<_, Option<String>>(0))?
+        .is_none()
+    {
+        return Err(AppError::validation("date"));
+    }
+    let scheduled = conn
+        .query_row(
+            &format!(
+                "SELECT sp.price FROM scheduled_prices sp
+                 WHERE sp.product_id = ?1 AND sp.ends_at IS NOT NULL AND {}
+                 ORDER BY datetime(sp.starts_at) DESC, sp.id DESC LIMIT 1",
+                active_at_sql("sp", "?2")
+            ),
+            rusqlite::params![product_id, at],
+            |row| row.get::<_, Money>(0),
+        );
+    match scheduled {
+        Ok(price) => return Ok(Some(price)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    // Último cambio anterior a la fecha
+    let before = conn.query_row(
+        "SELECT new_price FROM price_history
+         WHERE product_id = ?1 AND datetime(changed_at) <= datetime(?2)
+         ORDER BY datetime(changed_at) DESC, id DESC LIMIT 1",
+        rusqlite::params![product_id, at],
+        |row| row.get::<_, Option<Money>>(0),
+    );
+    match before {
+        Ok(price) => return Ok(price),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {}
+        Err(e) => return Err(e.into()),
+    }
+    // Sin cambios previos: el precio anterior al primer cambio posterior
+    let after = conn.query_row(
+        "SELECT old_price FROM price_history
+         WHERE product_id = ?1 AND datetime(changed_at) > datetime(?2)
+         ORDER BY datetime(changed_at) ASC, id ASC LIMIT 1",
+        rusqlite::params![product_id, at],
+        |row| row.get::<_, Option<Money>>(0),
+    );
+    match after {
+        Ok(price) => Ok(price),
+        // Producto sin historial (anterior a esta tabla): su precio actual
+        Err(rusqlite::Error::QueryReturnedNoRows) => effective_price(conn, product_id, at),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Copia al precio de lista los cambios definitivos (sin fecha de fin) cuya
+// fecha de inicio ya pasó. Se ejecuta al arrancar y al programar un cambio.
+fn apply_due_price_changes(conn: &Connection) -> Result<usize> {
+    let mut due: Vec<(i32, i32, Money, String)> = Vec::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT id, product_id, price, starts_at FROM scheduled_prices
+             WHERE applied_at IS NULL AND ends_at IS NULL
+               AND datetime(starts_at) <= datetime('now')
+             ORDER BY datetime(starts_at) ASC, id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+        for r in rows {
+            due.push(r?);
+        }
+    }
+    for (id, product_id, price, starts_at) in &due {
+        let old_price: Option<Money> = conn.query_row(
+            "SELECT sale_price FROM products WHERE id=?1",
+            [product_id],
+            |row| row.get(0),
+        )?;
+        conn.execute(
+            "UPDATE products SET sale_price=?1 WHERE id=?2",
+            rusqlite::params![price, product_id],
+        )?;
+        conn.execute(
+            "INSERT INTO price_history (product_id, old_price, new_price, source, changed_at)
+             VALUES (?1, ?2, ?3, 'programado', datetime(?4))",
+            rusqlite::params![product_id, old_price, price, starts_at],
+        )?;
+        conn.execute(
+            "UPDATE scheduled_prices SET applied_at=CURRENT_TIMESTAMP WHERE id=?1",
+            [id],
+        )?;
+    }
+    Ok(due.len())
+}
+
+#[tauri::command]
+fn get_price_history(state: State<AppState>, product_id: i32) -> Result<Vec<PriceChange>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, product_id, old_price, new_price, source, changed_at FROM price_history
+         WHERE product_id = ?1 ORDER BY datetime(changed_at) DESC, id DESC",
+    )?;
+    let rows = stmt
+        .query_map([product_id], |row| {
+            Ok(PriceChange {
+                id: row.get(0)?,
+                product_id: row.get(1)?,
+                old_price: row.get(2)?,
+                new_price: row.get(3)?,
+                source: row.get(4)?,
+                changed_at: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+fn get_price_at(state: State<AppState>, product_id: i32, date: String) -> Result<Option<Money>, AppError> {
+    let conn = state.db.lock()?;
+    price_at(&conn, product_id, &date)
+}
+
+#[tauri::command]
+fn get_scheduled_prices(state: State<AppState>, product_id: Option<i32>) -> Result<Vec<ScheduledPrice>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, product_id, price, starts_at, ends_at, note, applied_at FROM scheduled_prices
+         WHERE (?1 IS NULL OR product_id = ?1)
+         ORDER BY datetime(starts_at) DESC, id DESC",
+    )?;
+    let rows = stmt
+        .query_map([product_id], |row| {
+            Ok(ScheduledPrice {
+                id: row.get(0)?,
+                product_id: row.get(1)?,
+                price: row.get(2)?,
+                starts_at: row.get(3)?,
+                ends_at: row.get(4)?,
+                note: row.get(5)?,
+                applied_at: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+fn schedule_price_change(state: State<AppState>, change: ScheduledPrice) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    let id = insert_scheduled_price(&conn, &change)?;
+    apply_due_price_changes(&conn)?;
+    Ok(id)
+}
+
+fn insert_scheduled_price(conn: &Connection, change: &ScheduledPrice) -> Result<i64, AppError> {
+    if change.price <= Money::ZERO {
+        return Err(AppError::validation("price"));
+    }
+    let (starts, ends): (Option<String>, Option<String>) = conn.query_row(
+        "SELECT datetime(?1), CASE WHEN length(?2) = 10 THEN datetime(?2, '+1 day') ELSE datetime(?2) END",
+        rusqlite::params![change.starts_at, change.ends_at],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let starts = starts.ok_or(AppError::validation("starts_at"))?;
+    if change.ends_at.is_some() && ends.as_deref().is_none_or(|e| e <= starts.as_str()) {
+        return Err(AppError::validation("ends_at"));
+    }
+    let exists: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM products WHERE id=?1",
+        [change.product_id],
+        |row| row.get(0),
+    )?;
+    if exists == 0 {
+        return Err(AppError::not_found("product"));
+    }
+    conn.execute(
+        "INSERT INTO scheduled_prices (product_id, price, starts_at, ends_at, note) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![change.product_id, change.price, change.starts_at, change.ends_at, change.note],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+#[tauri::command]
+fn delete_scheduled_price(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let deleted = conn.execute("DELETE FROM scheduled_prices WHERE id=?1 AND applied_at IS NULL", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("scheduled_price"));
+    }
+    Ok(())
+}
+
+// ============================================
+// COSTING COMMANDS
+// ============================================
+//
+// Costo de inventario. Cada producto lleva su costo promedio ponderado
+// (`products.average_cost`) y capas de costo por recepción (`cost_layers`)
+// para FIFO. Las capas se mantienen con cualquier método, así se puede
+// cambiar de método sin reconstruir nada. El costo de lo vendido se guarda
+// en `sales.cost_amount` al registrar la venta, y el costo de cada
+// movimiento de stock en `stock_movements.cost_amount`.
+
+const COSTING_METHODS: [&str; 2] = ["promedio", "fifo"];
+
+/// Fila del reporte de margen. El margen se calcula solo sobre las ventas
+/// con costo conocido (las anteriores al costeo quedan en `uncosted_units`).
+#[derive(Debug, Serialize, Deserialize)]
+struct MarginReportRow {
+    /// Id de producto, categoría, marca o inicio del período
+    key: String,
+    label: String,
+    units: i64,
+    revenue: Money,
+    cogs: Money,
+    gross_margin: Money,
+    /// Margen sobre las ventas con costo; None si no hay ninguna
+    margin_pct: Option<f64>,
+    uncosted_units: i64,
+}
+
+fn costing_method(conn: &Connection) -> Result<String> {
+    Ok(get_setting(conn, "costing_method")?.unwrap_or_else(|| "promedio".to_string()))
+}
+
+#[tauri::command]
+fn get_costing_method(state: State<AppState>) -> Result<String, AppError> {
+    let conn = state.db.lock()?;
+    Ok(costing_method(&conn)?)
+}
+
+#[tauri::command]
+fn set_costing_method(state: State<AppState>, method: String) -> Result<(), AppError> {
+    if !COSTING_METHODS.contains(&method.as_str()) {
+        return Err(AppError::validation("costing_method"));
+    }
+    let conn = state.db.lock()?;
+    set_setting(&conn, "costing_method", &method)?;
+    Ok(())
+}
+
+fn product_stock(conn: &Connection, product_id: i32) -> Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END),0)
+         FROM stock_movements WHERE product_id=?1",
+        [product_id],
+        |row| row.get(0),
+    )
+}
+
+// Costea un ingreso de stock ya insertado, con costo total `cost` (None = al
+// costo promedio actual, p. ej. ajustes o devoluciones). Actualiza el
+// promedio, crea la capa FIFO y guarda el costo en el movimiento.
+fn receive_stock_cost(
+    conn: &Connection,
+    product_id: i32,
+    movement_id: i64,
+    quantity: i64,
+    cost: Option<Money>,
+) -> Result<Money, AppError> {
+    let average: Option<Money> =
+        conn.query_row("SELECT average_cost FROM products WHERE id=?1", [product_id], |row| row.get(0))?;
+    let average = average.unwrap_or(Money::ZERO);
+    let overflow = || AppError::validation("quantity");
+    let cost = match cost {
+        Some(cost) => cost,
+        None => average.checked_times(quantity).ok_or_else(overflow)?,
+    };
+    let on_hand = (product_stock(conn, product_id)? - quantity).max(0);
+    let new_average = average
+        .checked_times(on_hand)
+        .and_then(|value| value.checked_add(cost))
+        .ok_or_else(overflow)?
+        .per_unit(on_hand + quantity);
+    conn.execute(
+        "UPDATE products SET average_cost=?1 WHERE id=?2",
+        rusqlite::params![new_average, product_id],
+    )?;
+    conn.execute(
+        "INSERT INTO cost_layers (product_id, stock_movement_id, quantity, remaining, remaining_cost)
+         VALUES (?1, ?2, ?3, ?3, ?4)",
+        rusqlite::params![product_id, movement_id, quantity, cost],
+    )?;
+    conn.execute("UPDATE stock_movements SET cost_amount=?1 WHERE id=?2", rusqlite::params![cost, movement_id])?;
+    Ok(cost)
+}
+
+// Costea un egreso de stock ya insertado: consume las capas de la más
+// antigua a la más reciente y devuelve el costo según el método configurado
+// (también queda en el movimiento). Lo que no cubren las capas (stock
+// anterior al costeo) se valora al costo promedio.
+fn consume_stock_cost(conn: &Connection, product_id: i32, movement_id: i64, quantity: i64) -> Result<Money, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, remaining, remaining_cost FROM cost_layers
+         WHERE product_id=?1 AND remaining > 0 ORDER BY id",
+    )?;
+    let layers = stmt
+        .query_map([product_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Money>(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pending = quantity;
+    let mut fifo_cost = Money::ZERO;
+    for (id, remaining, remaining_cost) in layers {
+        if pending == 0 {
+            break;
+        }
+        let take = pending.min(remaining);
+        // La última unidad de la capa se lleva el resto, sin perder céntimos
+        let cost = if take == remaining {
+            remaining_cost
+        } else {
+            remaining_cost.checked_times(take).ok_or(AppError::validation("quantity"))?.per_unit(remaining)
+        };
+        conn.execute(
+            "UPDATE cost_layers SET remaining=remaining-?1, remaining_cost=remaining_cost-?2 WHERE id=?3",
+            rusqlite::params![take, cost, id],
+        )?;
+        fifo_cost += cost;
+        pending -= take;
+    }
+
+    let average: Money = conn.query_row(
+        "SELECT COALESCE(average_cost, 0) FROM products WHERE id=?1",
+        [product_id],
+        |row| row.get(0),
+    )?;
+    let cost = if costing_method(conn)? == "fifo" {
+        average.checked_times(pending).and_then(|rest| rest.checked_add(fifo_cost))
+    } else {
+        average.checked_times(quantity)
+    }
+    .ok_or(AppError::validation("quantity"))?;
+    conn.execute("UPDATE stock_movements SET cost_amount=?1 WHERE id=?2", rusqlite::params![cost, movement_id])?;
+    Ok(cost)
+}
+
+#[tauri::command]
+fn get_purchases(state: State<AppState>, product_id: Option<i32>) -> Result<Vec<Purchase>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, product_id, supplier, purchase_price, purchase_date, discount, expected_replenish_days, quantity, created_by,
+                (SELECT location_id FROM stock_movements WHERE id = purchases.stock_movement_id)
+         FROM purchases WHERE ?1 IS NULL OR product_id=?1
+         ORDER BY purchase_date DESC, id DESC LIMIT 200",
+    )?;
+    let rows = stmt
+        .query_map([product_id], |row| {
+            Ok(Purchase {
+                id: row.get(0)?,
+                product_id: row.get(1)?,
+                supplier: row.get(2)?,
+                purchase_price: row.get(3)?,
+                purchase_date: row.get(4)?,
+                discount: row.get(5)?,
+                expected_replenish_days: row.get(6)?,
+                quantity: row.get(7)?,
+                created_by: row.get(8)?,
+                location_id: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// Recepción de mercadería: registra la compra, el ingreso de stock y
+// actualiza el costo del producto
+#[tauri::command]
+fn add_purchase(state: State<AppState>, purchase: Purchase) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || record_purchase(&conn, &purchase))
+}
+
+fn record_purchase(conn: &Connection, purchase: &Purchase) -> Result<i64, AppError> {
+    let quantity = purchase.quantity.unwrap_or(0);
+    if quantity <= 0 {
+        return Err(AppError::validation("quantity"));
+    }
+    let price = purchase.purchase_price.ok_or(AppError::validation("purchase_price"))?;
+    if price < Money::ZERO {
+        return Err(AppError::validation("purchase_price"));
+    }
+    let discount = purchase.discount.unwrap_or(Money::ZERO);
+    let total = price
+        .checked_times(quantity as i64)
+        .and_then(|gross| gross.checked_sub(discount))
+        .ok_or(AppError::validation("purchase_price"))?;
+    if discount < Money::ZERO || total < Money::ZERO {
+        return Err(AppError::validation("discount"));
+    }
+    conn.query_row("SELECT id FROM products WHERE id=?1", [purchase.product_id], |row| row.get::<_, i64>(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })?;
+
+    let location_id = resolve_location(conn, purchase.location_id)?;
+    conn.execute(
+        "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id)
+         VALUES (?1, 'ingreso', ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            purchase.product_id,
+            quantity,
+            purchase.supplier.as_ref().map(|s| format!("Compra a {}", s)),
+            purchase.created_by,
+            location_id,
+        ],
+    )?;
+    let movement_id = conn.last_insert_rowid();
+    receive_stock_cost(conn, purchase.product_id, movement_id, quantity as i64, Some(total))?;
+    conn.execute(
+        "INSERT INTO purchases (product_id, supplier, purchase_price, purchase_date, discount, expected_replenish_days, quantity, stock_movement_id, created_by)
+         VALUES (?1, ?2, ?3, COALESCE(?4, date('now')), ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            purchase.product_id,
+            purchase.supplier,
+            price,
+            purchase.purchase_date,
+            discount,
+            purchase.expected_replenish_days,
+            quantity,
+            movement_id,
+            purchase.created_by,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Agrupaciones por período compartidas por los reportes: expresión SQL de la
+// fecha de inicio del período sobre la columna `{d}` (la semana empieza el lunes)
+fn period_key_sql(bucket: &str) -> Result<&'static str, AppError> {
+    match bucket {
+        "dia" => Ok("substr({d},1,10)"),
+        "semana" => Ok("date(substr({d},1,10), 'weekday 0', '-6 days')"),
+        "mes" => Ok("substr({d},1,7) || '-01'"),
+        _ => Err(AppError::validation("bucket")),
+    }
+}
+
+// `group_by`: "producto", "padre", "categoria", "marca", "dia", "semana" o "mes"
+#[tauri::command]
+fn get_margin_report(
+    state: State<AppState>,
+    group_by: String,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<MarginReportRow>, AppError> {
+    let conn = state.db.lock()?;
+    margin_report(&conn, &group_by, start_date.as_deref(), end_date.as_deref())
+}
+
+fn margin_report(
+    conn: &Connection,
+    group_by: &str,
+    start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<Vec<MarginReportRow>, AppError> {
+    let (key, label, order) = match group_by {
+        "producto" => ("CAST(p.id AS TEXT)".to_string(), "p.name".to_string(), "gross_margin DESC"),
+        "padre" => (
+            "CAST(COALESCE(pp.id, 0) AS TEXT)".to_string(),
+            "COALESCE(pp.name, 'Sin producto padre')".to_string(),
+            "gross_margin DESC",
+        ),
+        "categoria" => {
+            let c = "COALESCE(NULLIF(TRIM(p.category), ''), 'Sin categoría')".to_string();
+            (c.clone(), c, "gross_margin DESC")
+        }
+        "marca" => {
+            let b = "COALESCE(NULLIF(TRIM(p.brand), ''), 'Sin marca')".to_string();
+            (b.clone(), b, "gross_margin DESC")
+        }
+        bucket => {
+            let k = period_key_sql(bucket)
+                .map_err(|_| AppError::validation("group_by"))?
+                .replace("{d}", "s.sale_date");
+            (k.clone(), k, "key")
+        }
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {key} AS key, {label},
+                COALESCE(SUM(s.quantity),0),
+                COALESCE(SUM(s.sale_price),0),
+                COALESCE(SUM(s.cost_amount),0),
+                COALESCE(SUM(CASE WHEN s.cost_amount IS NOT NULL THEN s.sale_price - s.cost_amount ELSE 0 END),0) AS gross_margin,
+                COALESCE(SUM(CASE WHEN s.cost_amount IS NOT NULL THEN s.sale_price ELSE 0 END),0),
+                COALESCE(SUM(CASE WHEN s.cost_amount IS NULL THEN s.quantity ELSE 0 END),0)
+         FROM sales s JOIN products p ON p.id = s.product_id
+         LEFT JOIN parent_products pp ON pp.id = p.parent_id
+         WHERE (?1 IS NULL OR substr(s.sale_date,1,10) >= ?1)
+           AND (?2 IS NULL OR substr(s.sale_date,1,10) <= ?2)
+         GROUP BY 1
+         ORDER BY {order}",
+    ))?;
+    let rows = stmt
+        .query_map(rusqlite::params![start_date, end_date], |row| {
+            let gross_margin: Money = row.get(5)?;
+            let costed_revenue: Money = row.get(6)?;
+            Ok(MarginReportRow {
+                key: row.get(0)?,
+                label: row.get(1)?,
+                units: row.get(2)?,
+                revenue: row.get(3)?,
+                cogs: row.get(4)?,
+                gross_margin,
+                margin_pct: (costed_revenue != Money::ZERO)
+                    .then(|| gross_margin.cents() as f64 * 100.0 / costed_revenue.cents() as f64),
+                uncosted_units: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// ============================================
+// INVENTORY VALUATION
+// ============================================
+//
+// Valor del inventario al costo y a precio de venta, por producto,
+// producto padre, categoría o ubicación (la de cada movimiento de stock). Se reconstruye
+// sumando los movimientos de stock hasta la fecha pedida: el costo de cada
+// movimiento es su `cost_amount` o, en los anteriores al costeo, la
+// cantidad por el costo promedio actual. El precio de venta histórico sale
+// de `price_history`.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InventoryValuationRow {
+    /// Id de producto o de producto padre, categoría o ubicación
+    key: String,
+    label: String,
+    units: i64,
+    cost_value: Money,
+    retail_value: Money,
+    /// retail_value - cost_value
+    potential_margin: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InventoryValuation {
+    /// None = ahora
+    as_of: Option<String>,
+    group_by: String,
+    rows: Vec<InventoryValuationRow>,
+    total_units: i64,
+    total_cost: Money,
+    total_retail: Money,
+}
+
+// Condición SQL "la columna `col` es anterior o igual a `at`"; si `at` es
+// solo fecha incluye todo ese día
+fn at_or_before_sql(col: &str, at: &str) -> String {
+    format!(
+        "(CASE WHEN length({at}) = 10 THEN datetime({col}) < datetime({at}, '+1 day')
+               ELSE datetime({col}) <= datetime({at}) END)",
+        col = col,
+        at = at
+    )
+}
+
+// Valida una fecha u hora opcional que se pasa a SQLite
+fn validate_timestamp(conn: &Connection, value: Option<&str>, field: &str) -> Result<(), AppError> {
+    if let Some(at) = value {
+        let valid: bool = conn.query_row("SELECT datetime(?1) IS NOT NULL", [at], |row| row.get(0))?;
+        if !valid {
+            return Err(AppError::validation(field));
+        }
+    }
+    Ok(())
+}
+
+// `group_by`: "producto", "padre", "categoria" o "ubicacion"; `as_of`: fecha u hora
+// ("YYYY-MM-DD" incluye todo el día), None = ahora
+#[tauri::command]
+fn get_inventory_valuation(
+    state: State<AppState>,
+    group_by: Option<String>,
+    as_of: Option<String>,
+) -> Result<InventoryValuation, AppError> {
+    let conn = state.db.lock()?;
+    inventory_valuation(&conn, group_by.as_deref().unwrap_or("producto"), as_of.as_deref())
+}
+
+fn inventory_valuation(conn: &Connection, group_by: &str, as_of: Option<&str>) -> Result<InventoryValuation, AppError> {
+    if !["producto", "padre", "categoria", "ubicacion"].contains(&group_by) {
+        return Err(AppError::validation("group_by"));
+    }
+    validate_timestamp(conn, as_of, "as_of")?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.id, p.name, COALESCE(NULLIF(TRIM(p.category), ''), 'Sin categoría'),
+                COALESCE(l.name, 'Sin ubicación'),
+                COALESCE(SUM(CASE m.type WHEN 'ingreso' THEN m.quantity WHEN 'egreso' THEN -m.quantity ELSE 0 END), 0),
+                COALESCE(SUM(CASE m.type WHEN 'ingreso' THEN 1 WHEN 'egreso' THEN -1 ELSE 0 END
+                             * COALESCE(m.cost_amount, m.quantity * COALESCE(p.average_cost, 0))), 0),
+                CASE WHEN ?1 IS NULL THEN p.sale_price ELSE COALESCE(
+                    (SELECT h.new_price FROM price_history h WHERE h.product_id = p.id AND {before}
+                     ORDER BY datetime(h.changed_at) DESC, h.id DESC LIMIT 1),
+                    (SELECT h.old_price FROM price_history h WHERE h.product_id = p.id AND NOT {before}
+                     ORDER BY datetime(h.changed_at), h.id LIMIT 1),
+                    p.sale_price) END,
+                COALESCE(pp.id, 0), COALESCE(pp.name, 'Sin producto padre')
+         FROM products p
+         LEFT JOIN parent_products pp ON pp.id = p.parent_id
+         LEFT JOIN stock_movements m ON m.product_id = p.id AND (?1 IS NULL OR {moved})
+         LEFT JOIN locations l ON l.id = m.location_id
+         GROUP BY p.id, l.id
+         ORDER BY p.name",
+        before = at_or_before_sql("h.changed_at", "?1"),
+        moved = at_or_before_sql("m.created_at", "?1"),
+    ))?;
+    let products = stmt
+        .query_map([as_of], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Money>(5)?,
+                row.get::<_, Option<Money>>(6)?,
+                row.get::<_, i64>(7)?,
+                row.get::<_, String>(8)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rows: Vec<InventoryValuationRow> = Vec::new();
+    for (id, name, category, location, units, cost_value, price, parent_id, parent_name) in products {
+        if units == 0 && cost_value == Money::ZERO {
+            continue;
+        }
+        let retail_value = price.unwrap_or(Money::ZERO).times(units);
+        let (key, label) = match group_by {
+            "producto" => (id.to_string(), name),
+            "padre" => (parent_id.to_string(), parent_name),
+            "categoria" => (category.clone(), category),
+            _ => (location.clone(), location),
+        };
+        match rows.iter_mut().find(|r| r.key == key) {
+            Some(row) => {
+                row.units += units;
+                row.cost_value += cost_value;
+                row.retail_value += retail_value;
+                row.potential_margin = row.retail_value - row.cost_value;
+            }
+            None => rows.push(InventoryValuationRow {
+                key,
+                label,
+                units,
+                cost_value,
+                retail_value,
+                potential_margin: retail_value - cost_value,
+            }),
+        }
+    }
+    if group_by != "producto" {
+        rows.sort_by(|a, b| a.label.cmp(&b.label));
+    }
+
+    Ok(InventoryValuation {
+        as_of: as_of.map(str::to_string),
+        group_by: group_by.to_string(),
+        total_units: rows.iter().map(|r| r.units).sum(),
+        total_cost: rows.iter().map(|r| r.cost_value).sum(),
+        total_retail: rows.iter().map(|r| r.retail_value).sum(),
+        rows,
+    })
+}
+
+fn valuation_sheet(valuation: &InventoryValuation) -> report::Sheet {
+    let mut sheet = report::Sheet::new(
+        "Valorización",
+        &["key", "label", "units", "cost_value", "retail_value", "potential_margin"],
+    )
+    .with_totals(&["units", "cost_value", "retail_value", "potential_margin"]);
+    for row in &valuation.rows {
+        sheet.push(vec![
+            row.key.clone().into(),
+            row.label.clone().into(),
+            row.units.into(),
+            row.cost_value.into(),
+            row.retail_value.into(),
+            row.potential_margin.into(),
+        ]);
+    }
+    sheet
+}
+
+#[tauri::command]
+fn export_inventory_valuation(
+    state: State<AppState>,
+    group_by: Option<String>,
+    as_of: Option<String>,
+    format: Option<String>,
+) -> Result<String, AppError> {
+    let format = report_format(format.as_deref())?;
+    let conn = state.db.lock()?;
+    let valuation = inventory_valuation(&conn, group_by.as_deref().unwrap_or("producto"), as_of.as_deref())?;
+    write_report(&conn, &format!("inventory_valuation_{}", valuation.group_by), format, &[valuation_sheet(&valuation)])
+}
+
+// ============================================
+// STOCK CARD (KARDEX)
+// ============================================
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StockCardEntry {
+    movement_id: i32,
+    created_at: String,
+    movement_type: String,
+    note: Option<String>,
+    created_by: Option<i32>,
+    quantity_in: i64,
+    quantity_out: i64,
+    /// Saldo en unidades después del movimiento
+    balance: i64,
+    cost_in: Money,
+    cost_out: Money,
+    /// Saldo valorizado al costo después del movimiento
+    cost_balance: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StockCard {
+    product_id: i32,
+    name: String,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    opening_balance: i64,
+    opening_cost: Money,
+    entries: Vec<StockCardEntry>,
+    closing_balance: i64,
+    closing_cost: Money,
+}
+
+// Movimientos de un producto entre dos fechas (inclusivas; pueden llevar
+// hora) con saldo acumulado en unidades y al costo. El saldo inicial es la
+// suma de todo lo anterior a `start_date`.
+#[tauri::command]
+fn get_stock_card(
+    state: State<AppState>,
+    product_id: i32,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<StockCard, AppError> {
+    let conn = state.db.lock()?;
+    stock_card(&conn, product_id, start_date.as_deref(), end_date.as_deref())
+}
+
+fn stock_card(
+    conn: &Connection,
+    product_id: i32,
+    start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<StockCard, AppError> {
+    validate_timestamp(conn, start_date, "start_date")?;
+    validate_timestamp(conn, end_date, "end_date")?;
+    let name: String = conn
+        .query_row("SELECT name FROM products WHERE id=?1", [product_id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+            other => other.into(),
+        })?;
+
+    // Costo de un movimiento con signo, igual que en la valorización
+    let signed_cost = "CASE m.type WHEN 'ingreso' THEN 1 WHEN 'egreso' THEN -1 ELSE 0 END
+                       * COALESCE(m.cost_amount, m.quantity * COALESCE(p.average_cost, 0))";
+    let (opening_balance, opening_cost): (i64, Money) = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(CASE m.type WHEN 'ingreso' THEN m.quantity WHEN 'egreso' THEN -m.quantity ELSE 0 END), 0),
+                    COALESCE(SUM({}), 0)
+             FROM stock_movements m JOIN products p ON p.id = m.product_id
+             WHERE m.product_id = ?1 AND ?2 IS NOT NULL AND datetime(m.created_at) < datetime(?2)",
+            signed_cost
+        ),
+        rusqlite::params![product_id, start_date],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT m.id, m.created_at, m.type, m.note, m.created_by, m.quantity, {}
+         FROM stock_movements m JOIN products p ON p.id = m.product_id
+         WHERE m.product_id = ?1
+           AND (?2 IS NULL OR datetime(m.created_at) >= datetime(?2))
+           AND (?3 IS NULL OR {})
+         ORDER BY datetime(m.created_at), m.id",
+        signed_cost,
+        at_or_before_sql("m.created_at", "?3")
+    ))?;
+    let movements = stmt
+        .query_map(rusqlite::params![product_id, start_date, end_date], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<i32>>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, Money>(6)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut balance = opening_balance;
+    let mut cost_balance = opening_cost;
+    let mut entries = Vec::with_capacity(movements.len());
+    for (movement_id, created_at, movement_type, note, created_by, quantity, cost) in movements {
+        let (quantity_in, quantity_out) = match movement_type.as_str() {
+            "ingreso" => (quantity, 0),
+            "egreso" => (0, quantity),
+            _ => (0, 0),
+        };
+        balance += quantity_in - quantity_out;
+        cost_balance += cost;
+        entries.push(StockCardEntry {
+            movement_id,
+            created_at,
+            movement_type,
+            note,
+            created_by,
+            quantity_in,
+            quantity_out,
+            balance,
+            cost_in: if cost > Money::ZERO { cost } else { Money::ZERO },
+            cost_out: if cost < Money::ZERO { -cost } else { Money::ZERO },
+            cost_balance,
+        });
+    }
+
+    Ok(StockCard {
+        product_id,
+        name,
+        start_date: start_date.map(str::to_string),
+        end_date: end_date.map(str::to_string),
+        opening_balance,
+        opening_cost,
+        entries,
+        closing_balance: balance,
+        closing_cost: cost_balance,
+    })
+}
+
+// El saldo inicial va como primera fila
+fn stock_card_sheet(card: &StockCard) -> report::Sheet {
+    let mut sheet = report::Sheet::new(
+        "Kardex",
+        &["date", "movement_id", "type", "note", "quantity_in", "quantity_out", "balance", "cost_in", "cost_out", "cost_balance"],
+    )
+    .with_totals(&["quantity_in", "quantity_out", "cost_in", "cost_out"]);
+    let empty = || report::Cell::Empty;
+    sheet.push(vec![
+        card.start_date.clone().map(report::Cell::Date).unwrap_or(report::Cell::Empty),
+        empty(),
+        "saldo_inicial".into(),
+        empty(),
+        empty(),
+        empty(),
+        card.opening_balance.into(),
+        empty(),
+        empty(),
+        card.opening_cost.into(),
+    ]);
+    for e in &card.entries {
+        sheet.push(vec![
+            report::Cell::Date(e.created_at.clone()),
+            e.movement_id.into(),
+            e.movement_type.clone().into(),
+            e.note.clone().into(),
+            e.quantity_in.into(),
+            e.quantity_out.into(),
+            e.balance.into(),
+            e.cost_in.into(),
+            e.cost_out.into(),
+            e.cost_balance.into(),
+        ]);
+    }
+    sheet
+}
+
+#[tauri::command]
+fn export_stock_card(
+    state: State<AppState>,
+    product_id: i32,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    format: Option<String>,
+) -> Result<String, AppError> {
+    let format = report_format(format.as_deref())?;
+    let conn = state.db.lock()?;
+    let card = stock_card(&conn, product_id, start_date.as_deref(), end_date.as_deref())?;
+    write_report(&conn, &format!("stock_card_{}", product_id), format, &[stock_card_sheet(&card)])
+}
+
+// ============================================
+// REPORT EXPORTS
+// ============================================
+//
+// Los reportes se arman como hojas (ver report.rs) y se guardan en
+// Descargas/VitaSport como CSV (por defecto) o Excel (XLSX). El separador
+// del CSV y la marca BOM se eligen en `settings`.
+
+const REPORT_FORMATS: [&str; 2] = ["csv", "xlsx"];
+const CSV_DELIMITERS: [&str; 2] = [",", ";"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvSettings {
+    /// "," o ";" (Excel en español)
+    delimiter: String,
+    /// Marca BOM de UTF-8, para que Excel lea bien los acentos
+    bom: bool,
+}
+
+fn csv_options(conn: &Connection) -> Result<csv::WriteOptions, AppError> {
+    let delimiter = get_setting(conn, "csv_delimiter")?.filter(|d| CSV_DELIMITERS.contains(&d.as_str()));
+    Ok(csv::WriteOptions {
+        delimiter: delimiter.and_then(|d| d.chars().next()).unwrap_or(','),
+        bom: get_setting(conn, "csv_bom")?.as_deref() == Some("1"),
+    })
+}
+
+#[tauri::command]
+fn get_csv_settings(state: State<AppState>) -> Result<CsvSettings, AppError> {
+    let conn = state.db.lock()?;
+    let options = csv_options(&conn)?;
+    Ok(CsvSettings { delimiter: options.delimiter.to_string(), bom: options.bom })
+}
+
+#[tauri::command]
+fn update_csv_settings(state: State<AppState>, settings: CsvSettings) -> Result<(), AppError> {
+    if !CSV_DELIMITERS.contains(&settings.delimiter.as_str()) {
+        return Err(AppError::validation("delimiter"));
+    }
+    let conn = state.db.lock()?;
+    set_setting(&conn, "csv_delimiter", &settings.delimiter)?;
+    set_setting(&conn, "csv_bom", if settings.bom { "1" } else { "0" })?;
+    Ok(())
+}
+
+fn report_format(format: Option<&str>) -> Result<&str, AppError> {
+    let format = format.unwrap_or("csv");
+    if !REPORT_FORMATS.contains(&format) {
+        return Err(AppError::validation("format"));
+    }
+    Ok(format)
+}
+
+// Con `;` los decimales van con coma, como los lee Excel en español
+fn report_csv(sheet: &report::Sheet, options: csv::WriteOptions) -> String {
+    let field = |cell: &report::Cell| match cell {
+        report::Cell::Money(_) | report::Cell::Number(_) if options.delimiter == ';' => cell.to_string().replace('.', ","),
+        _ => cell.to_string(),
+    };
+    let mut records: Vec<Vec<String>> = vec![sheet.columns.iter().map(|c| c.to_string()).collect()];
+    for row in sheet.rows.iter().chain(sheet.total_row().as_ref()) {
+        records.push(row.iter().map(field).collect());
+    }
+    csv::write(&records, options)
+}
+
+// Ruta de un archivo exportado: Descargas/VitaSport/<name>_<ts>.<ext>
+fn export_path(name: &str, ext: &str) -> Result<PathBuf, AppError> {
+    let base: PathBuf = download_dir().ok_or(AppError::not_found("downloads_dir"))?;
+    let out_dir = base.join("VitaSport");
+    fs::create_dir_all(&out_dir)?;
+    let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(out_dir.join(format!("{}_{}.{}", name, ts, ext)))
+}
+
+// Guarda el reporte como `<name>_<ts>.<format>`; en CSV solo la primera hoja
+fn write_report(conn: &Connection, name: &str, format: &str, sheets: &[report::Sheet]) -> Result<String, AppError> {
+    let content = match (format, sheets.first()) {
+        ("xlsx", _) => report::to_xlsx(sheets)?,
+        (_, Some(sheet)) => report_csv(sheet, csv_options(conn)?).into_bytes(),
+        (_, None) => Vec::new(),
+    };
+    let path = export_path(name, format)?;
+    fs::write(&path, content)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn sales_sheet(conn: &Connection, start_date: Option<&str>, end_date: Option<&str>) -> Result<report::Sheet, AppError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM sales
+         WHERE (?1 IS NULL OR ?2 IS NULL OR substr(sale_date,1,10) BETWEEN ?1 AND ?2)
+         ORDER BY sale_date DESC",
+        SALE_COLUMNS
+    ))?;
+    let rows = stmt
+        .query_map(rusqlite::params![start_date, end_date], sale_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut sheet = report::Sheet::new(
+        "Ventas",
+        &["id", "product_id", "quantity", "unit_price", "gross_amount", "discount", "discount_amount", "sale_price", "channel", "sale_date", "created_by"],
+    )
+    .with_totals(&["quantity", "gross_amount", "discount_amount", "sale_price"]);
+    for sale in rows {
+        sheet.push(vec![
+            sale.id.unwrap_or_default().into(),
+            sale.product_id.into(),
+            sale.quantity.into(),
+            sale.unit_price.into(),
+            sale.gross_amount.into(),
+            sale.discount.into(),
+            sale.discount_amount.into(),
+            sale.sale_price.into(),
+            sale.channel.into(),
+            report::Cell::Date(sale.sale_date),
+            sale.created_by.into(),
+        ]);
+    }
+    Ok(sheet)
+}
+
+fn inventory_sheet(conn: &Connection) -> Result<report::Sheet, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT id, sku, name, sale_price, brand, category, presentation, flavor, weight, expiry_date, lot_number, min_stock, max_stock, location, status FROM products")?;
+
+    let mut sheet = report::Sheet::new(
+        "Inventario",
+        &["id", "sku", "name", "sale_price", "brand", "category", "presentation", "flavor", "weight", "expiry_date", "lot_number", "min_stock", "max_stock", "location", "status", "current_stock"],
+    );
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i32>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<Money>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<String>>(7)?,
+            row.get::<_, Option<String>>(8)?,
+            row.get::<_, Option<String>>(9)?,
+            row.get::<_, Option<String>>(10)?,
+            row.get::<_, Option<i32>>(11)?,
+            row.get::<_, Option<i32>>(12)?,
+            row.get::<_, Option<String>>(13)?,
+            row.get::<_, Option<String>>(14)?,
+        ))
+    })?;
+
+    for r in rows {
+        let (id, sku, name, sale_price, brand, category, presentation, flavor, weight, expiry_date, lot_number, min_stock, max_stock, location, status) = r?;
+
+        let ingreso: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(quantity),0) FROM stock_movements WHERE product_id=?1 AND type='ingreso'",
+            rusqlite::params![id],
+            |row| row.get(0),
+        ).unwrap_or(0);
+        let egreso: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(quantity),0) FROM stock_movements WHERE product_id=?1 AND type='egreso'",
+            rusqlite::params![id],
+            |row| row.get(0),
+        ).unwrap_or(0);
+        let current_stock = ingreso - egreso;
+
+        sheet.push(vec![
+            id.into(),
+            sku.into(),
+            name.into(),
+            sale_price.into(),
+            brand.into(),
+            category.into(),
+            presentation.into(),
+            flavor.into(),
+            weight.into(),
+            expiry_date.map(report::Cell::Date).unwrap_or(report::Cell::Empty),
+            lot_number.into(),
+            min_stock.into(),
+            max_stock.into(),
+            location.into(),
+            status.into(),
+            current_stock.into(),
+        ]);
+    }
+    Ok(sheet)
+}
+
+// format: "csv" (por defecto) o "xlsx"
+#[tauri::command]
+fn export_sales_report(
+    state: State<AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    format: Option<String>,
+) -> Result<String, AppError> {
+    let format = report_format(format.as_deref())?;
+    let conn = state.db.lock()?;
+    let sheet = sales_sheet(&conn, start_date.as_deref(), end_date.as_deref())?;
+    write_report(&conn, "sales_report", format, &[sheet])
+}
+
+#[tauri::command]
+fn export_inventory_report(state: State<AppState>, format: Option<String>) -> Result<String, AppError> {
+    let format = report_format(format.as_deref())?;
+    let conn = state.db.lock()?;
+    let sheet = inventory_sheet(&conn)?;
+    write_report(&conn, "inventory_report", format, &[sheet])
+}
+
+// Inventario, valorización y ventas: en CSV un archivo por reporte, en XLSX
+// un solo libro con una hoja por reporte
+#[tauri::command]
+fn export_all_reports(state: State<AppState>, format: Option<String>) -> Result<Vec<String>, AppError> {
+    let format = report_format(format.as_deref())?;
+    let conn = state.db.lock()?;
+    let valuation = inventory_valuation(&conn, "producto", None)?;
+    let sheets = [inventory_sheet(&conn)?, valuation_sheet(&valuation), sales_sheet(&conn, None, None)?];
+    if format == "xlsx" {
+        return Ok(vec![write_report(&conn, "reportes", format, &sheets)?]);
+    }
+    let names = ["inventory_report", "inventory_valuation_producto", "sales_report"];
+    names
+        .iter()
+        .zip(sheets)
+        .map(|(name, sheet)| write_report(&conn, name, format, &[sheet]))
+        .collect()
+}
+
+// ============================================
+// PROMOTION COMMANDS
+// ============================================
+
+const PROMOTION_COLUMNS: &str = "p.id, p.name, p.kind, p.percent, p.buy_qty, p.pay_qty, p.bundle_price, p.product_id, p.category, p.brand, p.flavor, p.channels, p.starts_at, p.ends_at, p.active";
+
+fn promotion_from_row(row: &rusqlite::Row) -> rusqlite::Result<Promotion> {
+    Ok(Promotion {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        kind: row.get(2)?,
+        percent: row.get(3)?,
+        buy_qty: row.get(4)?,
+        pay_qty: row.get(5)?,
+        bundle_price: row.get(6)?,
+        product_id: row.get(7)?,
+        category: row.get(8)?,
+        brand: row.get(9)?,
+        flavor: row.get(10)?,
+        channels: row.get(11)?,
+        starts_at: row.get(12)?,
+        ends_at: row.get(13)?,
+        active: row.get(14)?,
+        bundle_items: Vec::new(),
+    })
+}
+
+fn load_bundle_items(conn: &Connection, promos: &mut [Promotion]) -> Result<(), AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT product_id, quantity FROM promotion_bundle_items WHERE promotion_id=?1 ORDER BY product_id",
+    )?;
+    for promo in promos.iter_mut().filter(|p| p.kind == promotions::KIND_BUNDLE) {
+        promo.bundle_items = stmt
+            .query_map([promo.id], |row| {
+                Ok(promotions::BundleItem {
+                    product_id: row.get(0)?,
+                    quantity: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+    Ok(())
+}
+
+/// Promociones activas, vigentes en `at` y permitidas en el canal
+fn active_promotions(conn: &Connection, channel: Option<&str>, at: &str) -> Result<Vec<Promotion>, AppError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM promotions p WHERE p.active = 1 AND {}",
+        PROMOTION_COLUMNS,
+        active_at_sql("p", "?1")
+    ))?;
+    let mut promos = stmt
+        .query_map([at], promotion_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    promos.retain(|p| p.allows_channel(channel));
+    load_bundle_items(conn, &mut promos)?;
+    Ok(promos)
+}
+
+fn save_bundle_items(conn: &Connection, promotion_id: i64, promo: &Promotion) -> Result<(), AppError> {
+    conn.execute("DELETE FROM promotion_bundle_items WHERE promotion_id=?1", [promotion_id])?;
+    if promo.kind == promotions::KIND_BUNDLE {
+        for item in &promo.bundle_items {
+            conn.execute(
+                "INSERT INTO promotion_bundle_items (promotion_id, product_id, quantity) VALUES (?1, ?2, ?3)",
+                rusqlite::params![promotion_id, item.product_id, item.quantity],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_promotions(state: State<AppState>, active_only: Option<bool>) -> Result<Vec<Promotion>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM promotions p WHERE (?1 = 0 OR p.active = 1) ORDER BY p.id DESC",
+        PROMOTION_COLUMNS
+    ))?;
+    let mut promos = stmt
+        .query_map([active_only.unwrap_or(false)], promotion_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    load_bundle_items(&conn, &mut promos)?;
+    Ok(promos)
+}
+
+#[tauri::command]
+fn add_promotion(state: State<AppState>, promotion: Promotion) -> Result<i64, AppError> {
+    promotion.validate().map_err(AppError::validation)?;
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        conn.execute(
+            "INSERT INTO promotions (name, kind, percent, buy_qty, pay_qty, bundle_price, product_id, category, brand, flavor, channels, starts_at, ends_at, active)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            rusqlite::params![
+                promotion.name,
+                promotion.kind,
+                promotion.percent,
+                promotion.buy_qty,
+                promotion.pay_qty,
+                promotion.bundle_price,
+                promotion.product_id,
+                promotion.category,
+                promotion.brand,
+                promotion.flavor,
+                promotion.channels,
+                promotion.starts_at,
+                promotion.ends_at,
+                promotion.active,
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        save_bundle_items(&conn, id, &promotion)?;
+        Ok(id)
+    })
+}
+
+#[tauri::command]
+fn update_promotion(state: State<AppState>, promotion: Promotion) -> Result<(), AppError> {
+    promotion.validate().map_err(AppError::validation)?;
+    let id = promotion.id.ok_or(AppError::validation("id"))?;
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        let updated = conn.execute(
+            "UPDATE promotions SET name=?1, kind=?2, percent=?3, buy_qty=?4, pay_qty=?5, bundle_price=?6, product_id=?7, category=?8, brand=?9, flavor=?10, channels=?11, starts_at=?12, ends_at=?13, active=?14
+             WHERE id=?15",
+            rusqlite::params![
+                promotion.name,
+                promotion.kind,
+                promotion.percent,
+                promotion.buy_qty,
+                promotion.pay_qty,
+                promotion.bundle_price,
+                promotion.product_id,
+                promotion.category,
+                promotion.brand,
+                promotion.flavor,
+                promotion.channels,
+                promotion.starts_at,
+                promotion.ends_at,
+                promotion.active,
+                id,
+            ],
+        )?;
+        if updated == 0 {
+            return Err(AppError::not_found("promotion"));
+        }
+        save_bundle_items(&conn, id as i64, &promotion)
+    })
+}
+
+// Las promociones que ya se usaron en ventas solo se desactivan, para no
+// perder la referencia en los reportes
+#[tauri::command]
+fn delete_promotion(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let used: i64 = conn.query_row("SELECT COUNT(*) FROM sales WHERE promotion_id=?1", [id], |row| row.get(0))?;
+    let changed = if used > 0 {
+        conn.execute("UPDATE promotions SET active=0 WHERE id=?1", [id])?
+    } else {
+        conn.execute("DELETE FROM promotions WHERE id=?1", [id])?
+    };
+    if changed == 0 {
+        return Err(AppError::not_found("promotion"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PromotionReportRow {
+    promotion_id: i32,
+    name: String,
+    kind: String,
+    tickets: i64,
+    units: i64,
+    /// Descuento otorgado por la promoción (sin contar descuentos manuales)
+    total_discount: Money,
+    /// Ingresos netos de las líneas con la promoción
+    net_revenue: Money,
+}
+
+#[tauri::command]
+fn get_promotion_report(
+    state: State<AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<PromotionReportRow>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.name, p.kind,
+                COUNT(DISTINCT s.ticket_id),
+                COALESCE(SUM(s.quantity),0),
+                COALESCE(SUM(s.promotion_amount),0),
+                COALESCE(SUM(s.sale_price),0)
+         FROM sales s
+         JOIN promotions p ON p.id = s.promotion_id
+         WHERE (?1 IS NULL OR substr(s.sale_date,1,10) >= ?1)
+           AND (?2 IS NULL OR substr(s.sale_date,1,10) <= ?2)
+         GROUP BY p.id, p.name, p.kind
+         ORDER BY 6 DESC",
+    )?;
+    let rows = stmt
+        .query_map(rusqlite::params![start_date, end_date], |row| {
+            Ok(PromotionReportRow {
+                promotion_id: row.get(0)?,
+                name: row.get(1)?,
+                kind: row.get(2)?,
+                tickets: row.get(3)?,
+                units: row.get(4)?,
+                total_discount: row.get(5)?,
+                net_revenue: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+fn get_stock_movements(state: State<AppState>) -> Result<Vec<StockMovement>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn
+        .prepare("SELECT id, product_id, type, quantity, note, created_by, location_id, bin_id FROM stock_movements ORDER BY created_at DESC LIMIT 100")?;
+
+    let movements = stmt
+        .query_map([], |row| {
+            Ok(StockMovement {
+                id: row.get(0)?,
+                product_id: row.get(1)?,
+                movement_type: row.get(2)?,
+                quantity: row.get(3)?,
+                note: row.get(4)?,
+                created_by: row.get(5)?,
+                location_id: row.get(6)?,
+                bin_id: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(movements)
+}
+
+#[tauri::command]
+fn add_stock_movement(state: State<AppState>, movement: StockMovement) -> Result<i64, AppError> {
+    if movement.movement_type != "ingreso" && movement.movement_type != "egreso" {
+        return Err(AppError::validation("movement_type"));
+    }
+    if movement.quantity <= 0 {
+        return Err(AppError::validation("quantity"));
+    }
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        let location_id = resolve_location(&conn, movement.location_id)?;
+        if let Some(bin_id) = movement.bin_id {
+            if load_bin(&conn, bin_id)?.location_id as i64 != location_id {
+                return Err(AppError::validation("bin_id"));
+            }
+        }
+        conn.execute(
+            "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id, bin_id) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                movement.product_id,
+                movement.movement_type,
+                movement.quantity,
+                movement.note,
+                movement.created_by,
+                location_id,
+                movement.bin_id,
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        // Los ajustes entran al costo promedio; las compras van por add_purchase
+        if movement.movement_type == "ingreso" {
+            receive_stock_cost(&conn, movement.product_id, id, movement.quantity as i64, None)?;
+        } else {
+            consume_stock_cost(&conn, movement.product_id, id, movement.quantity as i64)?;
+        }
+        Ok(id)
+    })
+}
+
+#[tauri::command]
+fn get_sales(state: State<AppState>) -> Result<Vec<Sale>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM sales ORDER BY sale_date DESC LIMIT 100", SALE_COLUMNS))?;
+
+    let sales = stmt
+        .query_map([], sale_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sales)
+}
+
+#[tauri::command]
+fn add_sale(state: State<AppState>, sale: Sale) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || record_sale(&conn, &sale))
+}
+
+#[tauri::command]
+fn add_ticket(state: State<AppState>, ticket: NewTicket) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || record_ticket(&conn, &ticket).map(|(ticket_id, _)| ticket_id))
+}
+
+// Ejecuta `f` dentro de BEGIN IMMEDIATE / COMMIT, con ROLLBACK si falla
+fn in_transaction<T>(conn: &Connection, f: impl FnOnce() -> Result<T, AppError>) -> Result<T, AppError> {
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])?;
+    match f() {
+        Ok(value) => {
+            conn.execute("COMMIT", [])?;
+            Ok(value)
+        }
+        Err(err) => {
+            let _ = conn.execute("ROLLBACK", []);
+            Err(err)
+        }
+    }
+}
+
+// Reparte lo entregado contra el total del ticket. El vuelto sale del
+// efectivo; el resto de formas de pago no puede pasarse del total.
+fn settle_tenders(tenders: &[Tender], total: Money) -> Result<Vec<TicketPayment>, AppError> {
+    let tendered = Money::checked_sum(tenders.iter().map(|t| t.amount)).ok_or(AppError::validation("payments"))?;
+    let non_cash: Money = tenders.iter().filter(|t| t.method != "efectivo").map(|t| t.amount).sum();
+    if tendered < total || non_cash > total {
+        return Err(AppError::validation("payments"));
+    }
+    let mut change = tendered - total;
+    let mut payments: Vec<TicketPayment> = tenders
+        .iter()
+        .map(|t| TicketPayment {
+            id: None,
+            ticket_id: 0,
+            method: t.method.clone(),
+            amount: t.amount,
+            tendered: t.amount,
+            change_given: Money::ZERO,
+            reference: t.reference.clone(),
+        })
+        .collect();
+    for payment in payments.iter_mut().rev().filter(|p| p.method == "efectivo") {
+        let given = change.min(payment.tendered);
+        payment.change_given = given;
+        payment.amount = payment.tendered - given;
+        change -= given;
+    }
+    Ok(payments)
+}
+
+// Venta de una sola línea (ticket de un producto). Devuelve el id de la línea.
+fn record_sale(conn: &Connection, sale: &Sale) -> Result<i64, AppError> {
+    let ticket = NewTicket {
+        lines: vec![TicketLine {
+            product_id: sale.product_id,
+            quantity: sale.quantity,
+            discount: sale.discount,
+        }],
+        customer_id: sale.customer_id,
+        redeem_points: None,
+        on_account: sale.on_account,
+        payments: sale.payments.clone(),
+        session_id: None,
+        location_id: None,
+        channel: sale.channel.clone(),
+        sale_date: sale.sale_date.clone(),
+        created_by: sale.created_by,
+    };
+    let (_, sale_ids) = record_ticket(conn, &ticket)?;
+    Ok(sale_ids[0])
+}
+
+// Registra el ticket, sus líneas y los egresos de stock. Los precios salen
+// del precio vigente de cada producto y las promociones se evalúan aquí.
+// Debe llamarse dentro de una transacción.
+fn record_ticket(conn: &Connection, ticket: &NewTicket) -> Result<(i64, Vec<i64>), AppError> {
+    if ticket.lines.is_empty() {
+        return Err(AppError::validation("lines"));
+    }
+    for tender in &ticket.payments {
+        if !PAYMENT_METHODS.contains(&tender.method.as_str()) || tender.amount <= Money::ZERO {
+            return Err(AppError::validation("payments"));
+        }
+    }
+    let on_account = if ticket.payments.is_empty() {
+        ticket.on_account
+    } else {
+        let has_credit = ticket.payments.iter().any(|t| t.method == "credito");
+        if ticket.on_account && !has_credit {
+            return Err(AppError::validation("payments"));
+        }
+        has_credit
+    };
+    if on_account && ticket.customer_id.is_none() {
+        return Err(AppError::validation("customer_id"));
+    }
+    if let Some(customer_id) = ticket.customer_id {
+        let exists: i64 = conn.query_row("SELECT COUNT(*) FROM customers WHERE id=?1", [customer_id], |row| row.get(0))?;
+        if exists == 0 {
+            return Err(AppError::not_found("customer"));
+        }
+    }
+    let mut infos: Vec<promotions::LineInfo> = Vec::with_capacity(ticket.lines.len());
+    for line in &ticket.lines {
+        if line.quantity <= 0 {
+            return Err(AppError::validation("quantity"));
+        }
+        if !(0.0..=100.0).contains(&line.discount.unwrap_or(0.0)) {
+            return Err(AppError::validation("discount"));
+        }
+        let (category, brand, flavor): (Option<String>, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT category, brand, flavor FROM products WHERE id=?1",
+                [line.product_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => AppError::not_found("product"),
+                other => other.into(),
+            })?;
+        let unit_price = effective_price(conn, line.product_id, &ticket.sale_date)?
+            .ok_or(AppError::validation("sale_price"))?;
+        unit_price.checked_times(line.quantity as i64).ok_or(AppError::validation("quantity"))?;
+        infos.push(promotions::LineInfo {
+            product_id: line.product_id,
+            quantity: line.quantity,
+            unit_price,
+            category,
+            brand,
+            flavor,
+        });
+    }
+
+    // Con el bruto del ticket acotado, los importes que se derivan de él
+    // (promociones, descuentos, netos) tampoco desbordan
+    Money::checked_sum(infos.iter().map(|i| i.unit_price.times(i.quantity as i64)))
+        .ok_or(AppError::validation("lines"))?;
+
+    // Stock suficiente para el total de cada producto en el ticket
+    let mut requested: Vec<(i32, i64)> = Vec::new();
+    for line in &ticket.lines {
+        match requested.iter_mut().find(|(id, _)| *id == line.product_id) {
+            Some((_, qty)) => *qty += line.quantity as i64,
+            None => requested.push((line.product_id, line.quantity as i64)),
+        }
+    }
+    // Solo cuenta el stock de la ubicación de la venta: no el de otras
+    // sucursales ni el que está en tránsito
+    let location_id = resolve_location(conn, ticket.location_id)?;
+    for (product_id, qty) in &requested {
+        let current_stock = location_stock(conn, *product_id, location_id)?;
+        if *qty > current_stock {
+            return Err(AppError::InsufficientStock {
+                available: current_stock,
+                requested: *qty,
+            });
+        }
+    }
+
+    let active = active_promotions(conn, ticket.channel.as_deref(), &ticket.sale_date)?;
+    let applied = promotions::evaluate(&active, &infos);
+
+    let mut amounts: Vec<SaleAmounts> = ticket
+        .lines
+        .iter()
+        .zip(&infos)
+        .zip(&applied)
+        .map(|((line, info), promo)| {
+            SaleAmounts::compute(
+                info.unit_price,
+                line.quantity,
+                line.discount.unwrap_or(0.0),
+                promo.map(|p| p.amount).unwrap_or(Money::ZERO),
+            )
+        })
+        .collect();
+
+    let session_id = resolve_session(conn, ticket.session_id, ticket.created_by)?;
+    conn.execute(
+        "INSERT INTO tickets (customer_id, on_account, session_id, location_id, channel, sale_date, created_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![ticket.customer_id, on_account, session_id, location_id, ticket.channel, ticket.sale_date, ticket.created_by],
+    )?;
+    let ticket_id = conn.last_insert_rowid();
+
+    // Canje de puntos: descuento sobre el total, repartido entre las líneas
+    let mut points_amounts = vec![Money::ZERO; amounts.len()];
+    if let Some(points) = ticket.redeem_points.filter(|p| *p != 0) {
+        let customer_id = ticket.customer_id.ok_or(AppError::validation("customer_id"))?;
+        let nets: Vec<Money> = amounts.iter().map(|a| a.net).collect();
+        let value = loyalty_points_value(conn, points)?;
+        if points < 0 || value > nets.iter().copied().sum() {
+            return Err(AppError::validation("redeem_points"));
+        }
+        redeem_loyalty_points(conn, customer_id, Some(ticket_id), points, &ticket.sale_date)?;
+        points_amounts = value.allocate(&nets);
+        for (a, share) in amounts.iter_mut().zip(&points_amounts) {
+            a.discount += *share;
+            a.net -= *share;
+        }
+    }
+
+    let total: Money = amounts.iter().map(|a| a.net).sum();
+    let tenders = if ticket.payments.is_empty() {
+        let method = if on_account { "credito" } else { "efectivo" };
+        vec![Tender { method: method.to_string(), amount: total, reference: None }]
+    } else {
+        ticket.payments.clone()
+    };
+    let settled = settle_tenders(&tenders, total)?;
+
+    // Pago con puntos: se descuentan del cliente en la misma transacción
+    let points_paid: Money = settled.iter().filter(|p| p.method == "puntos").map(|p| p.amount).sum();
+    if points_paid > Money::ZERO {
+        let customer_id = ticket.customer_id.ok_or(AppError::validation("customer_id"))?;
+        let point_value = loyalty_settings(conn)?.point_value;
+        if point_value <= Money::ZERO || points_paid.cents() % point_value.cents() != 0 {
+            return Err(AppError::validation("payments"));
+        }
+        let points = points_paid.cents() / point_value.cents();
+        redeem_loyalty_points(conn, customer_id, Some(ticket_id), points, &ticket.sale_date)?;
+    }
+
+    for payment in settled {
+        conn.execute(
+            "INSERT INTO ticket_payments (ticket_id, method, amount, tendered, change_given, reference)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                ticket_id,
+                payment.method,
+                payment.amount,
+                payment.tendered,
+                payment.change_given,
+                payment.reference,
+            ],
+        )?;
+    }
+
+    let mut sale_ids = Vec::with_capacity(ticket.lines.len());
+    for (((line, promo), a), points_amount) in ticket.lines.iter().zip(&applied).zip(&amounts).zip(&points_amounts) {
+        // Una salida por bin, en el mismo orden que la lista de picking
+        let mut cost = Money::ZERO;
+        for pick in pick_bins(conn, line.product_id, location_id, line.quantity as i64)? {
+            conn.execute(
+                "INSERT INTO stock_movements (product_id, type, quantity, note, created_by, location_id, bin_id)
+                 VALUES (?1, 'egreso', ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![
+                    line.product_id,
+                    pick.quantity,
+                    Option::<String>::None,
+                    ticket.created_by,
+                    location_id,
+                    pick.bin_id,
+                ],
+            )?;
+            cost += consume_stock_cost(conn, line.product_id, conn.last_insert_rowid(), pick.quantity)?;
+        }
+        conn.execute(
+            "INSERT INTO sales (product_id, quantity, unit_price, gross_amount, discount, discount_amount, sale_price, ticket_id, promotion_id, promotion_amount, customer_id, points_amount, on_account, channel, sale_date, created_by, cost_amount)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            rusqlite::params![
+                line.product_id,
+                line.quantity,
+                a.unit_price,
+                a.gross,
+                line.discount.unwrap_or(0.0),
+                a.discount,
+                a.net,
+                ticket_id,
+                promo.map(|p| p.promotion_id),
+                a.promotion,
+                ticket.customer_id,
+                points_amount,
+                on_account,
+                ticket.channel,
+                ticket.sale_date,
+                ticket.created_by,
+                cost,
+            ],
+        )?;
+        sale_ids.push(conn.last_insert_rowid());
+    }
+
+    if let Some(customer_id) = ticket.customer_id {
+        // Lo pagado con puntos no genera puntos nuevos
+        let nets: Vec<Money> = amounts.iter().map(|a| a.net).collect();
+        let lines: Vec<(Option<String>, Money)> = infos
+            .iter()
+            .zip(nets.iter().zip(points_paid.allocate(&nets)))
+            .map(|(info, (net, paid))| (info.category.clone(), *net - paid))
+            .collect();
+        accrue_loyalty_points(conn, customer_id, ticket_id, &lines, &ticket.sale_date)?;
+    }
+    Ok((ticket_id, sale_ids))
+}
+
+const CASH_MOVEMENT_COLUMNS: &str =
+    "id, movement_type, amount, category, description, movement_date, payment_method, session_id, created_by, category_id, status, recurring_expense_id";
+
+fn cash_movement_from_row(row: &rusqlite::Row) -> Result<CashMovement> {
+    Ok(CashMovement {
+        id: row.get(0)?,
+        movement_type: row.get(1)?,
+        amount: row.get(2)?,
+        category: row.get(3)?,
+        description: row.get(4)?,
+        movement_date: row.get(5)?,
+        payment_method: row.get(6)?,
+        session_id: row.get(7)?,
+        created_by: row.get(8)?,
+        category_id: row.get(9)?,
+        status: row.get(10)?,
+        recurring_expense_id: row.get(11)?,
+    })
+}
+
+#[tauri::command]
+fn get_cash_movements(state: State<AppState>) -> Result<Vec<CashMovement>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cash_movements ORDER BY movement_date DESC, id DESC LIMIT 100",
+        CASH_MOVEMENT_COLUMNS
+    ))?;
+
+    let rows = stmt
+        .query_map([], cash_movement_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(rows)
+}
+
+#[tauri::command]
+fn add_cash_movement(state: State<AppState>, movement: CashMovement) -> Result<i64, AppError> {
+    if movement.movement_type != "ingreso" && movement.movement_type != "egreso" {
+        return Err(AppError::validation("movement_type"));
+    }
+    if movement.amount <= Money::ZERO {
+        return Err(AppError::validation("amount"));
+    }
+    let method = movement.payment_method.as_deref().unwrap_or("efectivo");
+    if TICKET_ONLY_METHODS.contains(&method) || !PAYMENT_METHODS.contains(&method) {
+        return Err(AppError::validation("payment_method"));
+    }
+    let conn = state.db.lock()?;
+    let session_id = resolve_session(&conn, movement.session_id, movement.created_by)?;
+    let (category_id, category) = if movement.movement_type == "egreso" {
+        resolve_expense_category(&conn, movement.category_id, movement.category)?
+    } else {
+        (None, movement.category)
+    };
+    conn.execute(
+        "INSERT INTO cash_movements (movement_type, amount, category, category_id, description, movement_date, payment_method, session_id, created_by) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            movement.movement_type,
+            movement.amount,
+            category,
+            category_id,
+            movement.description,
+            movement.movement_date,
+            method,
+            session_id,
             movement.created_by,
         ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+#[tauri::command]
+fn get_cash_summary(
+    state: State<AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    categories: Option<Vec<String>>,
+    bucket: Option<String>,
+) -> Result<CashSummary, AppError> {
+    let conn = state.db.lock()?;
+    let filter = CashFilter {
+        session_id: None,
+        start_date,
+        end_date,
+        categories,
+    };
+    cash_summary(&conn, &filter, bucket.as_deref())
+}
+
+// Condiciones SQL de `CashFilter`. Todas usan los mismos parámetros:
+// ?1 turno, ?2 desde, ?3 hasta, ?4 categorías (lista JSON).
+fn filter_dates_sql(date_col: &str) -> String {
+    format!(
+        "(?2 IS NULL OR substr({c},1,10) >= ?2) AND (?3 IS NULL OR substr({c},1,10) <= ?3)",
+        c = date_col
+    )
+}
+
+fn filter_sales_sql(alias: &str) -> String {
+    format!(
+        "(?1 IS NULL OR {a}.ticket_id IN (SELECT id FROM tickets WHERE session_id = ?1)) AND {dates}
+         AND (?4 IS NULL OR EXISTS (SELECT 1 FROM json_each(?4) WHERE value = '{cat}'))",
+        a = alias,
+        dates = filter_dates_sql(&format!("{}.sale_date", alias)),
+        cat = SALES_CATEGORY
+    )
+}
+
+fn filter_ticket_payments_sql(ticket_alias: &str) -> String {
+    format!(
+        "(?1 IS NULL OR {t}.session_id = ?1) AND {dates}
+         AND (?4 IS NULL OR EXISTS (SELECT 1 FROM json_each(?4) WHERE value = '{cat}'))",
+        t = ticket_alias,
+        dates = filter_dates_sql(&format!("{}.sale_date", ticket_alias)),
+        cat = SALES_CATEGORY
+    )
+}
+
+fn filter_movements_sql(alias: &str) -> String {
+    format!(
+        "{a}.status = 'confirmado' AND (?1 IS NULL OR {a}.session_id = ?1) AND {dates}
+         AND (?4 IS NULL OR {a}.category IN (SELECT value FROM json_each(?4)))",
+        a = alias,
+        dates = filter_dates_sql(&format!("{}.movement_date", alias))
+    )
+}
+
+// (turno, desde, hasta, categorías en JSON)
+type CashFilterParams = (Option<i64>, Option<String>, Option<String>, Option<String>);
+
+fn filter_params(filter: &CashFilter) -> Result<CashFilterParams, AppError> {
+    let categories = match &filter.categories {
+        Some(list) => Some(serde_json::to_string(list).map_err(|e| AppError::Internal(e.to_string()))?),
+        None => None,
+    };
+    Ok((filter.session_id, filter.start_date.clone(), filter.end_date.clone(), categories))
+}
+
+// Ingresos y egresos agrupados por `key_sql`, una expresión sobre la columna
+// de fecha `d` ("'total'" para un solo grupo). Los ingresos son las ventas
+// menos lo pagado al crédito o con puntos más los ingresos de caja.
+fn cash_flow(conn: &Connection, filter: &CashFilter, key_sql: &str) -> Result<Vec<(String, Money, Money)>, AppError> {
+    let key = |col: &str| key_sql.replace("{d}", col);
+    let sql = format!(
+        "SELECT k, COALESCE(SUM(income),0), COALESCE(SUM(expense),0) FROM (
+             SELECT {k_sales} AS k, s.sale_price AS income, 0 AS expense FROM sales s WHERE {sales}
+             UNION ALL
+             SELECT {k_tickets}, -p.amount, 0 FROM ticket_payments p JOIN tickets t ON t.id = p.ticket_id
+             WHERE p.method IN ('credito', 'puntos') AND {payments}
+             UNION ALL
+             SELECT {k_movements},
+                    CASE WHEN m.movement_type = 'ingreso' THEN m.amount ELSE 0 END,
+                    CASE WHEN m.movement_type = 'egreso' THEN m.amount ELSE 0 END
+             FROM cash_movements m WHERE {movements}
+         )
+         GROUP BY k ORDER BY k",
+        k_sales = key("s.sale_date"),
+        k_tickets = key("t.sale_date"),
+        k_movements = key("m.movement_date"),
+        sales = filter_sales_sql("s"),
+        payments = filter_ticket_payments_sql("t"),
+        movements = filter_movements_sql("m"),
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(filter_params(filter)?, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// Período anterior equivalente: si el rango son meses completos, los mismos
+// meses justo antes; si no, el mismo número de días justo antes
+fn previous_period(conn: &Connection, start: &str, end: &str) -> Result<(String, String), AppError> {
+    let (valid, full_months, days, months): (bool, bool, i64, i64) = conn.query_row(
+        "SELECT date(?1) IS NOT NULL AND date(?2) IS NOT NULL AND date(?1) <= date(?2),
+                strftime('%d', ?1) = '01' AND strftime('%d', date(?2, '+1 day')) = '01',
+                CAST(julianday(?2) - julianday(?1) AS INTEGER) + 1,
+                (CAST(strftime('%Y', ?2) AS INTEGER) * 12 + CAST(strftime('%m', ?2) AS INTEGER))
+                  - (CAST(strftime('%Y', ?1) AS INTEGER) * 12 + CAST(strftime('%m', ?1) AS INTEGER)) + 1",
+        [start, end],
+        |row| Ok((row.get(0)?, row.get::<_, Option<bool>>(1)?.unwrap_or(false), row.get::<_, Option<i64>>(2)?.unwrap_or(0), row.get::<_, Option<i64>>(3)?.unwrap_or(0))),
+    )?;
+    if !valid {
+        return Err(AppError::validation("end_date"));
+    }
+    let shift = if full_months {
+        format!("-{} months", months)
+    } else {
+        format!("-{} days", days)
+    };
+    Ok(conn.query_row(
+        "SELECT date(?1, ?2), date(?1, '-1 day')",
+        [start, shift.as_str()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+fn cash_summary(conn: &Connection, filter: &CashFilter, bucket: Option<&str>) -> Result<CashSummary, AppError> {
+    let params = filter_params(filter)?;
+    let (sales_gross, sales_discount, total_sales_income): (Money, Money, Money) = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(s.gross_amount),0), COALESCE(SUM(s.discount_amount),0), COALESCE(SUM(s.sale_price),0)
+             FROM sales s WHERE {}",
+            filter_sales_sql("s")
+        ),
+        params.clone(),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let (sales_on_account, sales_in_points): (Money, Money) = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(CASE WHEN p.method='credito' THEN p.amount ELSE 0 END),0),
+                    COALESCE(SUM(CASE WHEN p.method='puntos' THEN p.amount ELSE 0 END),0)
+             FROM ticket_payments p JOIN tickets t ON t.id = p.ticket_id
+             WHERE {}",
+            filter_ticket_payments_sql("t")
+        ),
+        params.clone(),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let (total_other_income, total_expense): (Money, Money) = conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(CASE WHEN m.movement_type='ingreso' THEN m.amount ELSE 0 END),0),
+                    COALESCE(SUM(CASE WHEN m.movement_type='egreso' THEN m.amount ELSE 0 END),0)
+             FROM cash_movements m WHERE {}",
+            filter_movements_sql("m")
+        ),
+        params,
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let income = total_sales_income - sales_on_account - sales_in_points + total_other_income;
+    let balance = income - total_expense;
+    let by_method = totals_by_method(conn, filter)?;
+    let cash_in_drawer = by_method
+        .iter()
+        .find(|m| m.method == "efectivo")
+        .map(|m| m.net)
+        .unwrap_or(Money::ZERO);
+
+    let key_sql = bucket.map(period_key_sql).transpose()?;
+    let buckets = match key_sql {
+        Some(key_sql) => cash_flow(conn, filter, key_sql)?
+            .into_iter()
+            .map(|(period_start, total_income, total_expense)| CashBucket {
+                period_start,
+                total_income,
+                total_expense,
+                balance: total_income - total_expense,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let previous = match (&filter.start_date, &filter.end_date) {
+        (Some(start), Some(end)) => {
+            let (prev_start, prev_end) = previous_period(conn, start, end)?;
+            let prev_filter = CashFilter {
+                start_date: Some(prev_start.clone()),
+                end_date: Some(prev_end.clone()),
+                ..filter.clone()
+            };
+            let (prev_income, prev_expense) = cash_flow(conn, &prev_filter, "'total'")?
+                .into_iter()
+                .next()
+                .map(|(_, i, e)| (i, e))
+                .unwrap_or((Money::ZERO, Money::ZERO));
+            let prev_balance = prev_income - prev_expense;
+            let balance_change = balance - prev_balance;
+            Some(PeriodComparison {
+                start_date: prev_start,
+                end_date: prev_end,
+                total_income: prev_income,
+                total_expense: prev_expense,
+                balance: prev_balance,
+                balance_change,
+                balance_change_pct: (prev_balance != Money::ZERO).then(|| {
+                    balance_change.cents() as f64 * 100.0 / prev_balance.cents().abs() as f64
+                }),
+            })
+        }
+        _ => None,
+    };
+
+    Ok(CashSummary {
+        total_income: income,
+        total_expense,
+        balance,
+        sales_gross,
+        sales_discount,
+        sales_net: total_sales_income,
+        sales_on_account,
+        sales_in_points,
+        by_method,
+        cash_in_drawer,
+        buckets,
+        previous,
+    })
+}
+
+// Totales por forma de pago con los filtros del resumen (p. ej. un turno).
+// Las ventas anteriores a los tickets no tienen forma de pago: se cuentan
+// como efectivo (y no pertenecen a ningún turno).
+fn totals_by_method(conn: &Connection, filter: &CashFilter) -> Result<Vec<PaymentMethodTotal>, AppError> {
+    fn slot<'a>(totals: &'a mut Vec<PaymentMethodTotal>, method: &str) -> &'a mut PaymentMethodTotal {
+        let pos = match totals.iter().position(|t| t.method == method) {
+            Some(pos) => pos,
+            None => {
+                totals.push(PaymentMethodTotal {
+                    method: method.to_string(),
+                    sales: Money::ZERO,
+                    other_income: Money::ZERO,
+                    expense: Money::ZERO,
+                    net: Money::ZERO,
+                });
+                totals.len() - 1
+            }
+        };
+        &mut totals[pos]
+    }
+
+    let mut totals: Vec<PaymentMethodTotal> = Vec::new();
+    for method in PAYMENT_METHODS.iter().filter(|m| !TICKET_ONLY_METHODS.contains(m)) {
+        slot(&mut totals, method);
+    }
+
+    let params = filter_params(filter)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.method, SUM(p.amount) FROM ticket_payments p JOIN tickets t ON t.id = p.ticket_id
+         WHERE p.method NOT IN ('credito', 'puntos') AND {}
+         GROUP BY p.method
+         UNION ALL
+         SELECT 'efectivo', SUM(s.sale_price) FROM sales s WHERE s.ticket_id IS NULL AND s.on_account = 0 AND {}",
+        filter_ticket_payments_sql("t"),
+        filter_sales_sql("s")
+    ))?;
+    let rows = stmt.query_map(params.clone(), |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<Money>>(1)?)))?;
+    for row in rows {
+        let (method, amount) = row?;
+        slot(&mut totals, &method).sales += amount.unwrap_or_default();
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT COALESCE(m.payment_method, 'efectivo'), m.movement_type, SUM(m.amount) FROM cash_movements m
+         WHERE {}
+         GROUP BY 1, 2",
+        filter_movements_sql("m")
+    ))?;
+    let rows = stmt.query_map(params, |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Money>(2)?)))?;
+    for row in rows {
+        let (method, movement_type, amount) = row?;
+        let total = slot(&mut totals, &method);
+        match movement_type.as_str() {
+            "ingreso" => total.other_income += amount,
+            "egreso" => total.expense += amount,
+            _ => {}
+        }
+    }
+
+    for total in &mut totals {
+        total.net = total.sales + total.other_income - total.expense;
+    }
+    Ok(totals)
+}
+
+#[tauri::command]
+fn get_ticket_payments(state: State<AppState>, ticket_id: i32) -> Result<Vec<TicketPayment>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, ticket_id, method, amount, tendered, change_given, reference FROM ticket_payments WHERE ticket_id=?1 ORDER BY id",
+    )?;
+    let payments = stmt
+        .query_map([ticket_id], |row| {
+            Ok(TicketPayment {
+                id: row.get(0)?,
+                ticket_id: row.get(1)?,
+                method: row.get(2)?,
+                amount: row.get(3)?,
+                tendered: row.get(4)?,
+                change_given: row.get(5)?,
+                reference: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(payments)
+}
+
+// ============================================
+// EXPENSE COMMANDS
+// ============================================
+//
+// Categorías de gasto en árbol (p. ej. Servicios > Luz) y gastos recurrentes.
+// Cada plantilla recurrente genera, al abrir la app, un egreso "pendiente"
+// por cada vencimiento ya alcanzado. Los pendientes no cuentan en caja hasta
+// que se confirman con `confirm_expense`.
+
+const RECURRING_FREQUENCIES: [&str; 2] = ["semanal", "mensual"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExpenseCategory {
+    id: Option<i32>,
+    name: String,
+    parent_id: Option<i32>,
+    /// Ruta completa, p. ej. "Servicios > Luz" (solo lectura)
+    #[serde(default)]
+    path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecurringExpense {
+    id: Option<i32>,
+    name: String,
+    amount: Money,
+    category_id: Option<i32>,
+    description: Option<String>,
+    /// "semanal" o "mensual"
+    frequency: String,
+    /// Cada cuántas semanas o meses; None = 1
+    #[serde(default)]
+    every: Option<i32>,
+    /// Primer vencimiento ("YYYY-MM-DD")
+    start_date: String,
+    end_date: Option<String>,
+    /// Próximo vencimiento por generar (solo lectura)
+    #[serde(default)]
+    next_date: Option<String>,
+    #[serde(default)]
+    payment_method: Option<String>,
+    active: bool,
+    created_by: Option<i32>,
+}
+
+/// Gasto confirmado y pendiente de una categoría en un período
+#[derive(Debug, Serialize, Deserialize)]
+struct ExpenseCategoryTotal {
+    /// None agrupa los egresos sin categoría
+    category_id: Option<i32>,
+    name: String,
+    parent_id: Option<i32>,
+    /// Egresos de la categoría sin contar subcategorías
+    own_total: Money,
+    /// Egresos de la categoría y todas sus subcategorías
+    total: Money,
+    /// Egresos pendientes de confirmar (con subcategorías)
+    pending: Money,
+    movements: i64,
+}
+
+#[tauri::command]
+fn get_expense_categories(state: State<AppState>) -> Result<Vec<ExpenseCategory>, AppError> {
+    let conn = state.db.lock()?;
+    expense_categories(&conn)
+}
+
+fn expense_categories(conn: &Connection) -> Result<Vec<ExpenseCategory>, AppError> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE tree(id, name, parent_id, path) AS (
+             SELECT id, name, parent_id, name FROM expense_categories WHERE parent_id IS NULL
+             UNION ALL
+             SELECT c.id, c.name, c.parent_id, tree.path || ' > ' || c.name
+             FROM expense_categories c JOIN tree ON c.parent_id = tree.id
+         )
+         SELECT id, name, parent_id, path FROM tree ORDER BY path",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(ExpenseCategory {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                parent_id: row.get(2)?,
+                path: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+#[tauri::command]
+fn add_expense_category(state: State<AppState>, name: String, parent_id: Option<i32>) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    insert_expense_category(&conn, &name, parent_id)
+}
+
+fn insert_expense_category(conn: &Connection, name: &str, parent_id: Option<i32>) -> Result<i64, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    if let Some(parent) = parent_id {
+        expense_category_name(conn, parent)?;
+    }
+    conn.execute(
+        "INSERT INTO expense_categories (name, parent_id) VALUES (?1, ?2)",
+        rusqlite::params![name, parent_id],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Renombra o mueve una categoría. No puede colgar de sí misma ni de una de
+// sus subcategorías. El nombre se actualiza también en los movimientos.
+#[tauri::command]
+fn update_expense_category(state: State<AppState>, category: ExpenseCategory) -> Result<(), AppError> {
+    let id = category.id.ok_or(AppError::validation("id"))?;
+    let name = category.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        expense_category_name(&conn, id)?;
+        if let Some(parent) = category.parent_id {
+            expense_category_name(&conn, parent)?;
+            let cycle: bool = conn.query_row(
+                "WITH RECURSIVE up(id) AS (
+                     SELECT ?1
+                     UNION ALL
+                     SELECT c.parent_id FROM expense_categories c JOIN up ON c.id = up.id WHERE c.parent_id IS NOT NULL
+                 )
+                 SELECT EXISTS (SELECT 1 FROM up WHERE id = ?2)",
+                [parent, id],
+                |row| row.get(0),
+            )?;
+            if cycle {
+                return Err(AppError::validation("parent_id"));
+            }
+        }
+        conn.execute(
+            "UPDATE expense_categories SET name=?1, parent_id=?2 WHERE id=?3",
+            rusqlite::params![name, category.parent_id, id],
+        )?;
+        conn.execute("UPDATE cash_movements SET category=?1 WHERE category_id=?2", rusqlite::params![name, id])?;
+        Ok(())
+    })
+}
+
+// Solo se pueden borrar categorías sin subcategorías, movimientos ni
+// plantillas recurrentes
+#[tauri::command]
+fn delete_expense_category(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let in_use: i64 = conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM expense_categories WHERE parent_id=?1)
+              + (SELECT COUNT(*) FROM cash_movements WHERE category_id=?1)
+              + (SELECT COUNT(*) FROM recurring_expenses WHERE category_id=?1)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if in_use > 0 {
+        return Err(AppError::validation("category_id"));
+    }
+    let deleted = conn.execute("DELETE FROM expense_categories WHERE id=?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("expense_category"));
+    }
+    Ok(())
+}
+
+fn expense_category_name(conn: &Connection, id: i32) -> Result<String, AppError> {
+    conn.query_row("SELECT name FROM expense_categories WHERE id=?1", [id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("expense_category"),
+            other => other.into(),
+        })
+}
+
+// Categoría de un egreso: por id, o por nombre si coincide con una categoría
+// existente. Devuelve (category_id, category).
+fn resolve_expense_category(
+    conn: &Connection,
+    category_id: Option<i32>,
+    category: Option<String>,
+) -> Result<(Option<i32>, Option<String>), AppError> {
+    if let Some(id) = category_id {
+        return Ok((Some(id), Some(expense_category_name(conn, id)?)));
+    }
+    let id = match &category {
+        Some(name) => match conn.query_row("SELECT id FROM expense_categories WHERE name=?1", [name.trim()], |row| row.get(0)) {
+            Ok(id) => Some(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    Ok((id, category))
+}
+
+#[tauri::command]
+fn get_expenses_by_category(
+    state: State<AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<ExpenseCategoryTotal>, AppError> {
+    let conn = state.db.lock()?;
+    expenses_by_category(&conn, start_date.as_deref(), end_date.as_deref())
+}
+
+// Egresos por categoría, sumando cada subcategoría en sus categorías padre.
+// Las fechas son "YYYY-MM-DD" inclusivas.
+fn expenses_by_category(
+    conn: &Connection,
+    start_date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<Vec<ExpenseCategoryTotal>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT m.category_id, COALESCE(NULLIF(TRIM(m.category), ''), 'Sin categoría'),
+                COALESCE(SUM(CASE WHEN m.status = 'confirmado' THEN m.amount ELSE 0 END), 0),
+                COALESCE(SUM(CASE WHEN m.status = 'pendiente' THEN m.amount ELSE 0 END), 0),
+                COUNT(*)
+         FROM cash_movements m
+         WHERE m.movement_type = 'egreso'
+           AND (?1 IS NULL OR substr(m.movement_date,1,10) >= ?1) AND (?2 IS NULL OR substr(m.movement_date,1,10) <= ?2)
+         GROUP BY m.category_id, CASE WHEN m.category_id IS NULL THEN COALESCE(NULLIF(TRIM(m.category), ''), 'Sin categoría') END",
+    )?;
+    let sums = stmt
+        .query_map(rusqlite::params![start_date, end_date], |row| {
+            Ok((
+                row.get::<_, Option<i32>>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Money>(2)?,
+                row.get::<_, Money>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let categories = expense_categories(conn)?;
+    let mut totals: Vec<ExpenseCategoryTotal> = categories
+        .iter()
+        .map(|c| ExpenseCategoryTotal {
+            category_id: c.id,
+            name: c.path.clone(),
+            parent_id: c.parent_id,
+            own_total: Money::ZERO,
+            total: Money::ZERO,
+            pending: Money::ZERO,
+            movements: 0,
+        })
+        .collect();
+    let parent_of = |id: i32| categories.iter().find(|c| c.id == Some(id)).and_then(|c| c.parent_id);
+
+    for (category_id, name, confirmed, pending, count) in sums {
+        let Some(id) = category_id else {
+            totals.push(ExpenseCategoryTotal {
+                category_id: None,
+                name,
+                parent_id: None,
+                own_total: confirmed,
+                total: confirmed,
+                pending,
+                movements: count,
+            });
+            continue;
+        };
+        if let Some(row) = totals.iter_mut().find(|t| t.category_id == Some(id)) {
+            row.own_total += confirmed;
+            row.movements += count;
+        }
+        // La categoría y todos sus ancestros
+        let mut current = Some(id);
+        while let Some(cid) = current {
+            if let Some(row) = totals.iter_mut().find(|t| t.category_id == Some(cid)) {
+                row.total += confirmed;
+                row.pending += pending;
+            }
+            current = parent_of(cid);
+        }
+    }
+    Ok(totals)
+}
+
+#[tauri::command]
+fn get_recurring_expenses(state: State<AppState>) -> Result<Vec<RecurringExpense>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, name, amount, category_id, description, frequency, every, start_date, end_date, next_date, payment_method, active, created_by
+         FROM recurring_expenses ORDER BY active DESC, next_date",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(RecurringExpense {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                amount: row.get(2)?,
+                category_id: row.get(3)?,
+                description: row.get(4)?,
+                frequency: row.get(5)?,
+                every: row.get(6)?,
+                start_date: row.get(7)?,
+                end_date: row.get(8)?,
+                next_date: row.get(9)?,
+                payment_method: row.get(10)?,
+                active: row.get(11)?,
+                created_by: row.get(12)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+fn validate_recurring_expense(conn: &Connection, expense: &RecurringExpense) -> Result<(), AppError> {
+    if expense.name.trim().is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    if expense.amount <= Money::ZERO {
+        return Err(AppError::validation("amount"));
+    }
+    if !RECURRING_FREQUENCIES.contains(&expense.frequency.as_str()) {
+        return Err(AppError::validation("frequency"));
+    }
+    if expense.every.is_some_and(|n| n < 1) {
+        return Err(AppError::validation("every"));
+    }
+    let method = expense.payment_method.as_deref().unwrap_or("efectivo");
+    if TICKET_ONLY_METHODS.contains(&method) || !PAYMENT_METHODS.contains(&method) {
+        return Err(AppError::validation("payment_method"));
+    }
+    let dates_ok: bool = conn.query_row(
+        "SELECT date(?1) IS NOT NULL AND (?2 IS NULL OR date(?2) >= date(?1))",
+        rusqlite::params![expense.start_date, expense.end_date],
+        |row| row.get(0),
+    )?;
+    if !dates_ok {
+        return Err(AppError::validation("start_date"));
+    }
+    if let Some(id) = expense.category_id {
+        expense_category_name(conn, id)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn add_recurring_expense(state: State<AppState>, expense: RecurringExpense) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    let id = insert_recurring_expense(&conn, &expense)?;
+    generate_recurring_expenses(&conn, "now")?;
+    Ok(id)
+}
+
+fn insert_recurring_expense(conn: &Connection, expense: &RecurringExpense) -> Result<i64, AppError> {
+    validate_recurring_expense(conn, expense)?;
+    conn.execute(
+        "INSERT INTO recurring_expenses (name, amount, category_id, description, frequency, every, start_date, end_date, next_date, occurrences, payment_method, active, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, date(?7), ?8, date(?7), 0, ?9, ?10, ?11)",
+        rusqlite::params![
+            expense.name.trim(),
+            expense.amount,
+            expense.category_id,
+            expense.description,
+            expense.frequency,
+            expense.every.unwrap_or(1),
+            expense.start_date,
+            expense.end_date,
+            expense.payment_method.as_deref().unwrap_or("efectivo"),
+            expense.active,
+            expense.created_by,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+// Si cambia la frecuencia o el inicio, la serie se recalcula desde el primer
+// vencimiento posterior al último egreso ya generado
+#[tauri::command]
+fn update_recurring_expense(state: State<AppState>, expense: RecurringExpense) -> Result<(), AppError> {
+    let id = expense.id.ok_or(AppError::validation("id"))?;
+    let conn = state.db.lock()?;
+    validate_recurring_expense(&conn, &expense)?;
+    in_transaction(&conn, || {
+        let last: Option<String> = conn.query_row(
+            "SELECT MAX(substr(movement_date,1,10)) FROM cash_movements WHERE recurring_expense_id=?1",
+            [id],
+            |row| row.get(0),
+        )?;
+        let updated = conn.execute(
+            "UPDATE recurring_expenses SET name=?1, amount=?2, category_id=?3, description=?4, frequency=?5, every=?6,
+                    start_date=date(?7), end_date=?8, payment_method=?9, active=?10, occurrences=0, next_date=date(?7)
+             WHERE id=?11",
+            rusqlite::params![
+                expense.name.trim(),
+                expense.amount,
+                expense.category_id,
+                expense.description,
+                expense.frequency,
+                expense.every.unwrap_or(1),
+                expense.start_date,
+                expense.end_date,
+                expense.payment_method.as_deref().unwrap_or("efectivo"),
+                expense.active,
+                id,
+            ],
+        )?;
+        if updated == 0 {
+            return Err(AppError::not_found("recurring_expense"));
+        }
+        if let Some(last) = last {
+            let every = expense.every.unwrap_or(1);
+            let mut n = 0;
+            let mut next = occurrence_date(&conn, &expense.frequency, every, &expense.start_date, n)?;
+            while next <= last {
+                n += 1;
+                next = occurrence_date(&conn, &expense.frequency, every, &expense.start_date, n)?;
+            }
+            conn.execute(
+                "UPDATE recurring_expenses SET occurrences=?1, next_date=?2 WHERE id=?3",
+                rusqlite::params![n, next, id],
+            )?;
+        }
+        Ok(())
+    })?;
+    generate_recurring_expenses(&conn, "now")?;
+    Ok(())
+}
+
+// Borra la plantilla y sus egresos aún pendientes; los confirmados se quedan
+#[tauri::command]
+fn delete_recurring_expense(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        conn.execute(
+            "DELETE FROM cash_movements WHERE recurring_expense_id=?1 AND status='pendiente'",
+            [id],
+        )?;
+        conn.execute("UPDATE cash_movements SET recurring_expense_id=NULL WHERE recurring_expense_id=?1", [id])?;
+        let deleted = conn.execute("DELETE FROM recurring_expenses WHERE id=?1", [id])?;
+        if deleted == 0 {
+            return Err(AppError::not_found("recurring_expense"));
+        }
+        Ok(())
+    })
+}
+
+// Vencimiento número `n` (desde 0) de una serie. En la serie mensual el día
+// se ajusta al último del mes cuando no existe (31 -> 30, 28 o 29).
+fn occurrence_date(conn: &Connection, frequency: &str, every: i32, start: &str, n: i32) -> Result<String> {
+    if frequency == "semanal" {
+        conn.query_row(
+            "SELECT date(?1, ?2)",
+            rusqlite::params![start, format!("+{} days", 7 * every * n)],
+            |row| row.get(0),
+        )
+    } else {
+        conn.query_row(
+            "SELECT min(date(?1, 'start of month', ?2, '+' || (CAST(strftime('%d', ?1) AS INTEGER) - 1) || ' days'),
+                        date(?1, 'start of month', ?3, '-1 day'))",
+            rusqlite::params![start, format!("+{} months", every * n), format!("+{} months", every * n + 1)],
+            |row| row.get(0),
+        )
+    }
+}
+
+#[tauri::command]
+fn run_recurring_expenses(state: State<AppState>) -> Result<usize, AppError> {
+    let conn = state.db.lock()?;
+    Ok(generate_recurring_expenses(&conn, "now")?)
+}
+
+// Genera los egresos pendientes de todas las plantillas activas cuyo
+// vencimiento ya llegó en `at`. Devuelve cuántos egresos creó.
+fn generate_recurring_expenses(conn: &Connection, at: &str) -> Result<usize> {
+    let today: String = conn.query_row("SELECT date(?1)", [at], |row| row.get(0))?;
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.name, r.amount, r.category_id, c.name, r.description, r.frequency, r.every, r.start_date,
+                r.end_date, r.occurrences, r.next_date, r.payment_method, r.created_by
+         FROM recurring_expenses r LEFT JOIN expense_categories c ON c.id = r.category_id
+         WHERE r.active = 1 AND r.next_date <= ?1",
+    )?;
+    type Template = (
+        i32,
+        String,
+        Money,
+        Option<i32>,
+        Option<String>,
+        Option<String>,
+        String,
+        i32,
+        String,
+        Option<String>,
+        i32,
+        String,
+        String,
+        Option<i32>,
+    );
+    let due = stmt
+        .query_map([&today], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                row.get(7)?,
+                row.get(8)?,
+                row.get(9)?,
+                row.get(10)?,
+                row.get(11)?,
+                row.get(12)?,
+                row.get(13)?,
+            ))
+        })?
+        .collect::<Result<Vec<Template>, _>>()?;
+
+    conn.execute("SAVEPOINT recurring_expenses", [])?;
+    let result = (|| {
+        let mut created = 0;
+        for (id, name, amount, category_id, category, description, frequency, every, start, end, mut n, mut next, method, created_by) in due {
+            while next <= today && end.as_ref().is_none_or(|end| next <= *end) {
+                conn.execute(
+                    "INSERT INTO cash_movements (movement_type, amount, category, category_id, description, movement_date, payment_method, status, recurring_expense_id, created_by)
+                     VALUES ('egreso', ?1, ?2, ?3, ?4, ?5, ?6, 'pendiente', ?7, ?8)",
+                    rusqlite::params![
+                        amount,
+                        category,
+                        category_id,
+                        description.as_deref().unwrap_or(&name),
+                        next,
+                        method,
+                        id,
+                        created_by,
+                    ],
+                )?;
+                created += 1;
+                n += 1;
+                next = occurrence_date(conn, &frequency, every, &start, n)?;
+            }
+            conn.execute(
+                "UPDATE recurring_expenses SET occurrences=?1, next_date=?2 WHERE id=?3",
+                rusqlite::params![n, next, id],
+            )?;
+        }
+        Ok(created)
+    })();
+    if result.is_err() {
+        let _ = conn.execute("ROLLBACK TO recurring_expenses", []);
+    }
+    conn.execute("RELEASE recurring_expenses", [])?;
+    result
+}
+
+#[tauri::command]
+fn get_pending_expenses(state: State<AppState>) -> Result<Vec<CashMovement>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cash_movements WHERE status='pendiente' ORDER BY movement_date, id",
+        CASH_MOVEMENT_COLUMNS
+    ))?;
+    let rows = stmt
+        .query_map([], cash_movement_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+// Confirma un egreso pendiente (ya pagado). Se puede corregir el monto, la
+// fecha y la forma de pago; queda en el turno indicado o en el del usuario.
+#[tauri::command]
+fn confirm_expense(
+    state: State<AppState>,
+    id: i32,
+    amount: Option<Money>,
+    movement_date: Option<String>,
+    payment_method: Option<String>,
+    session_id: Option<i32>,
+    confirmed_by: Option<i32>,
+) -> Result<(), AppError> {
+    if amount.is_some_and(|a| a <= Money::ZERO) {
+        return Err(AppError::validation("amount"));
+    }
+    if let Some(method) = payment_method.as_deref() {
+        if TICKET_ONLY_METHODS.contains(&method) || !PAYMENT_METHODS.contains(&method) {
+            return Err(AppError::validation("payment_method"));
+        }
+    }
+    let conn = state.db.lock()?;
+    let session_id = resolve_session(&conn, session_id, confirmed_by)?;
+    let updated = conn.execute(
+        "UPDATE cash_movements
+         SET status='confirmado', amount=COALESCE(?1, amount), movement_date=COALESCE(?2, movement_date),
+             payment_method=COALESCE(?3, payment_method), session_id=?4, created_by=COALESCE(?5, created_by)
+         WHERE id=?6 AND status='pendiente'",
+        rusqlite::params![amount, movement_date, payment_method, session_id, confirmed_by, id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("pending_expense"));
+    }
+    Ok(())
+}
+
+// ============================================
+// CASH SESSION COMMANDS
+// ============================================
+//
+// Turnos de caja. Cada caja (`register`) tiene como mucho un turno abierto y
+// cada usuario también. Los tickets y movimientos de caja se asocian al
+// turno indicado o, si no, al turno abierto del usuario que los registra.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CashSession {
+    id: Option<i32>,
+    register: String,
+    opened_by: Option<i32>,
+    opened_at: String,
+    opening_float: Money,
+    closed_by: Option<i32>,
+    closed_at: Option<String>,
+    /// Fondo inicial + efectivo recibido - efectivo entregado en el turno
+    expected_cash: Option<Money>,
+    counted_cash: Option<Money>,
+    /// counted_cash - expected_cash (negativo = faltante)
+    variance: Option<Money>,
+    notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DenominationCount {
+    /// Valor del billete o moneda (p. ej. 0.50, 100)
+    denomination: Money,
+    quantity: i64,
+}
+
+/// Reporte Z de un turno cerrado
+#[derive(Debug, Serialize, Deserialize)]
+struct ZReport {
+    session: CashSession,
+    tickets: i64,
+    units: i64,
+    sales_gross: Money,
+    sales_discount: Money,
+    sales_net: Money,
+    sales_on_account: Money,
+    sales_in_points: Money,
+    change_given: Money,
+    by_method: Vec<PaymentMethodTotal>,
+    counts: Vec<DenominationCount>,
+}
+
+const CASH_SESSION_COLUMNS: &str = "id, register, opened_by, opened_at, opening_float, closed_by, closed_at, expected_cash, counted_cash, variance, notes";
+
+fn cash_session_from_row(row: &rusqlite::Row) -> rusqlite::Result<CashSession> {
+    Ok(CashSession {
+        id: row.get(0)?,
+        register: row.get(1)?,
+        opened_by: row.get(2)?,
+        opened_at: row.get(3)?,
+        opening_float: row.get(4)?,
+        closed_by: row.get(5)?,
+        closed_at: row.get(6)?,
+        expected_cash: row.get(7)?,
+        counted_cash: row.get(8)?,
+        variance: row.get(9)?,
+        notes: row.get(10)?,
+    })
+}
+
+fn load_cash_session(conn: &Connection, id: i64) -> Result<CashSession, AppError> {
+    conn.query_row(
+        &format!("SELECT {} FROM cash_sessions WHERE id=?1", CASH_SESSION_COLUMNS),
+        [id],
+        cash_session_from_row,
     )
-    .map_err(|e| e.to_string())?;
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => AppError::not_found("cash_session"),
+        other => other.into(),
+    })
+}
+
+// Turno al que se asocia un ticket o movimiento: el indicado (debe estar
+// abierto) o el abierto del usuario. Sin turno abierto no se registra nada.
+fn resolve_session(conn: &Connection, explicit: Option<i32>, user_id: Option<i32>) -> Result<i64, AppError> {
+    if let Some(id) = explicit {
+        let session = load_cash_session(conn, id as i64)?;
+        if session.closed_at.is_some() {
+            return Err(AppError::validation("session_id"));
+        }
+        return Ok(id as i64);
+    }
+    let Some(user_id) = user_id else { return Err(AppError::validation("session_id")) };
+    match conn.query_row(
+        "SELECT id FROM cash_sessions WHERE opened_by=?1 AND closed_at IS NULL",
+        [user_id],
+        |row| row.get(0),
+    ) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(AppError::validation("session_id")),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn open_session(conn: &Connection, register: &str, opening_float: Money, opened_by: Option<i32>) -> Result<i64, AppError> {
+    let register = register.trim();
+    if register.is_empty() {
+        return Err(AppError::validation("register"));
+    }
+    if opening_float < Money::ZERO {
+        return Err(AppError::validation("opening_float"));
+    }
+    let busy: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM cash_sessions WHERE closed_at IS NULL AND (register=?1 OR opened_by=?2)",
+        rusqlite::params![register, opened_by],
+        |row| row.get(0),
+    )?;
+    if busy > 0 {
+        return Err(AppError::conflict("register"));
+    }
+    conn.execute(
+        "INSERT INTO cash_sessions (register, opened_by, opened_at, opening_float) VALUES (?1, ?2, datetime('now'), ?3)",
+        rusqlite::params![register, opened_by, opening_float],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn expected_cash(conn: &Connection, session: &CashSession, session_id: i64) -> Result<Money, AppError> {
+    let filter = CashFilter { session_id: Some(session_id), ..CashFilter::default() };
+    let cash = totals_by_method(conn, &filter)?
+        .into_iter()
+        .find(|m| m.method == "efectivo")
+        .map(|m| m.net)
+        .unwrap_or(Money::ZERO);
+    Ok(session.opening_float + cash)
+}
+
+fn close_session(
+    conn: &Connection,
+    session_id: i64,
+    counts: &[DenominationCount],
+    closed_by: Option<i32>,
+    notes: Option<String>,
+) -> Result<CashSession, AppError> {
+    let session = load_cash_session(conn, session_id)?;
+    if session.closed_at.is_some() {
+        return Err(AppError::validation("session_id"));
+    }
+    if counts.iter().any(|c| c.denomination <= Money::ZERO || c.quantity < 0) {
+        return Err(AppError::validation("counts"));
+    }
+    let expected = expected_cash(conn, &session, session_id)?;
+    let counted = counts
+        .iter()
+        .map(|c| c.denomination.checked_times(c.quantity))
+        .sum::<Option<Money>>()
+        .ok_or(AppError::validation("counts"))?;
+    for count in counts.iter().filter(|c| c.quantity > 0) {
+        conn.execute(
+            "INSERT INTO cash_session_counts (session_id, denomination, quantity) VALUES (?1, ?2, ?3)
+             ON CONFLICT(session_id, denomination) DO UPDATE SET quantity = quantity + excluded.quantity",
+            rusqlite::params![session_id, count.denomination, count.quantity],
+        )?;
+    }
+    conn.execute(
+        "UPDATE cash_sessions SET closed_by=?1, closed_at=datetime('now'), expected_cash=?2, counted_cash=?3, variance=?4, notes=?5
+         WHERE id=?6",
+        rusqlite::params![closed_by, expected, counted, counted - expected, notes, session_id],
+    )?;
+    load_cash_session(conn, session_id)
+}
+
+fn z_report(conn: &Connection, session_id: i64) -> Result<ZReport, AppError> {
+    let session = load_cash_session(conn, session_id)?;
+    if session.closed_at.is_none() {
+        return Err(AppError::validation("session_id"));
+    }
+    let (tickets, units, sales_gross, sales_discount, sales_net): (i64, i64, Money, Money, Money) = conn.query_row(
+        "SELECT COUNT(DISTINCT s.ticket_id), COALESCE(SUM(s.quantity),0), COALESCE(SUM(s.gross_amount),0),
+                COALESCE(SUM(s.discount_amount),0), COALESCE(SUM(s.sale_price),0)
+         FROM sales s JOIN tickets t ON t.id = s.ticket_id
+         WHERE t.session_id = ?1",
+        [session_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )?;
+    let (sales_on_account, sales_in_points, change_given): (Money, Money, Money) = conn.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN p.method='credito' THEN p.amount ELSE 0 END),0),
+                COALESCE(SUM(CASE WHEN p.method='puntos' THEN p.amount ELSE 0 END),0),
+                COALESCE(SUM(p.change_given),0)
+         FROM ticket_payments p JOIN tickets t ON t.id = p.ticket_id
+         WHERE t.session_id = ?1",
+        [session_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT denomination, quantity FROM cash_session_counts WHERE session_id=?1 ORDER BY denomination DESC",
+    )?;
+    let counts = stmt
+        .query_map([session_id], |row| {
+            Ok(DenominationCount {
+                denomination: row.get(0)?,
+                quantity: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ZReport {
+        session,
+        tickets,
+        units,
+        sales_gross,
+        sales_discount,
+        sales_net,
+        sales_on_account,
+        sales_in_points,
+        change_given,
+        by_method: totals_by_method(conn, &CashFilter { session_id: Some(session_id), ..CashFilter::default() })?,
+        counts,
+    })
+}
+
+#[tauri::command]
+fn open_cash_session(
+    state: State<AppState>,
+    register: String,
+    opening_float: Money,
+    opened_by: Option<i32>,
+) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    open_session(&conn, &register, opening_float, opened_by)
+}
+
+/// Turnos abiertos con el efectivo que debería haber en cada caja
+#[tauri::command]
+fn get_open_cash_sessions(state: State<AppState>) -> Result<Vec<CashSession>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cash_sessions WHERE closed_at IS NULL ORDER BY register",
+        CASH_SESSION_COLUMNS
+    ))?;
+    let mut sessions = stmt
+        .query_map([], cash_session_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    for session in &mut sessions {
+        let id = session.id.unwrap_or_default() as i64;
+        session.expected_cash = Some(expected_cash(&conn, session, id)?);
+    }
+    Ok(sessions)
+}
+
+#[tauri::command]
+fn get_cash_sessions(state: State<AppState>, limit: Option<i64>) -> Result<Vec<CashSession>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cash_sessions ORDER BY id DESC LIMIT ?1",
+        CASH_SESSION_COLUMNS
+    ))?;
+    let sessions = stmt
+        .query_map([limit.unwrap_or(50)], cash_session_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sessions)
+}
+
+/// Cierra el turno con el arqueo por denominación y calcula la diferencia
+#[tauri::command]
+fn close_cash_session(
+    state: State<AppState>,
+    session_id: i32,
+    counts: Vec<DenominationCount>,
+    closed_by: Option<i32>,
+    notes: Option<String>,
+) -> Result<CashSession, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || close_session(&conn, session_id as i64, &counts, closed_by, notes))
+}
+
+#[tauri::command]
+fn get_z_report(state: State<AppState>, session_id: i32) -> Result<ZReport, AppError> {
+    let conn = state.db.lock()?;
+    z_report(&conn, session_id as i64)
+}
+
+// ============================================
+// CUSTOMER COMMANDS
+// ============================================
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Customer {
+    id: Option<i32>,
+    name: String,
+    /// DNI/RUC u otro documento; único si se informa
+    document_id: Option<String>,
+    phone: Option<String>,
+    email: Option<String>,
+    notes: Option<String>,
+}
+
+const CUSTOMER_COLUMNS: &str = "id, name, document_id, phone, email, notes";
 
+fn customer_from_row(row: &rusqlite::Row) -> rusqlite::Result<Customer> {
+    Ok(Customer {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        document_id: row.get(2)?,
+        phone: row.get(3)?,
+        email: row.get(4)?,
+        notes: row.get(5)?,
+    })
+}
+
+// Recorta espacios y convierte los textos vacíos en NULL, para que dos
+// clientes sin documento no choquen con el índice único
+fn normalize_customer(customer: &Customer) -> Result<Customer, AppError> {
+    fn clean(value: &Option<String>) -> Option<String> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+    }
+    let name = customer.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::validation("name"));
+    }
+    let email = clean(&customer.email);
+    if email.as_deref().is_some_and(|e| !e.contains('@')) {
+        return Err(AppError::validation("email"));
+    }
+    Ok(Customer {
+        id: customer.id,
+        name,
+        document_id: clean(&customer.document_id),
+        phone: clean(&customer.phone),
+        email,
+        notes: clean(&customer.notes),
+    })
+}
+
+#[tauri::command]
+fn get_customers(state: State<AppState>) -> Result<Vec<Customer>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM customers ORDER BY name COLLATE NOCASE", CUSTOMER_COLUMNS))?;
+    let customers = stmt
+        .query_map([], customer_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(customers)
+}
+
+/// Busca por nombre, documento, teléfono o email (coincidencia parcial)
+#[tauri::command]
+fn search_customers(state: State<AppState>, query: String) -> Result<Vec<Customer>, AppError> {
+    let conn = state.db.lock()?;
+    let pattern = format!("%{}%", query.trim());
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM customers
+         WHERE name LIKE ?1 OR document_id LIKE ?1 OR phone LIKE ?1 OR email LIKE ?1
+         ORDER BY name COLLATE NOCASE
+         LIMIT 50",
+        CUSTOMER_COLUMNS
+    ))?;
+    let customers = stmt
+        .query_map([pattern], customer_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(customers)
+}
+
+#[tauri::command]
+fn add_customer(state: State<AppState>, customer: Customer) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    insert_customer(&conn, &customer)
+}
+
+fn insert_customer(conn: &Connection, customer: &Customer) -> Result<i64, AppError> {
+    let c = normalize_customer(customer)?;
+    conn.execute(
+        "INSERT INTO customers (name, document_id, phone, email, notes) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![c.name, c.document_id, c.phone, c.email, c.notes],
+    )?;
     Ok(conn.last_insert_rowid())
 }
 
 #[tauri::command]
-fn get_sales(state: State<AppState>) -> Result<Vec<Sale>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, product_id, quantity, sale_price, discount, channel, sale_date, created_by FROM sales ORDER BY sale_date DESC LIMIT 100")
-        .map_err(|e| e.to_string())?;
+fn update_customer(state: State<AppState>, customer: Customer) -> Result<(), AppError> {
+    let c = normalize_customer(&customer)?;
+    let id = c.id.ok_or(AppError::validation("id"))?;
+    let conn = state.db.lock()?;
+    let updated = conn.execute(
+        "UPDATE customers SET name=?1, document_id=?2, phone=?3, email=?4, notes=?5, updated_at=CURRENT_TIMESTAMP WHERE id=?6",
+        rusqlite::params![c.name, c.document_id, c.phone, c.email, c.notes, id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("customer"));
+    }
+    Ok(())
+}
+
+// Las ventas del cliente se conservan como ventas de mostrador. Los clientes
+// con ventas al crédito no se pueden eliminar para no perder la cuenta.
+#[tauri::command]
+fn delete_customer(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || {
+        let credit: i64 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM tickets WHERE customer_id=?1 AND on_account=1)
+                  + (SELECT COUNT(*) FROM customer_payments WHERE customer_id=?1)",
+            [id],
+            |row| row.get(0),
+        )?;
+        if credit > 0 {
+            return Err(AppError::validation("customer_id"));
+        }
+        conn.execute("UPDATE sales SET customer_id=NULL WHERE customer_id=?1", [id])?;
+        conn.execute("UPDATE tickets SET customer_id=NULL WHERE customer_id=?1", [id])?;
+        let deleted = conn.execute("DELETE FROM customers WHERE id=?1", [id])?;
+        if deleted == 0 {
+            return Err(AppError::not_found("customer"));
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomerTicket {
+    /// None en ventas anteriores a los tickets (una línea por ticket)
+    ticket_id: Option<i32>,
+    sale_date: String,
+    channel: Option<String>,
+    units: i64,
+    total: Money,
+    lines: Vec<Sale>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomerHistory {
+    customer: Customer,
+    /// Del más reciente al más antiguo
+    tickets: Vec<CustomerTicket>,
+    ticket_count: i64,
+    /// Suma de los importes netos de todas sus compras
+    lifetime_value: Money,
+    last_visit: Option<String>,
+}
+
+#[tauri::command]
+fn get_customer_history(state: State<AppState>, customer_id: i32) -> Result<CustomerHistory, AppError> {
+    let conn = state.db.lock()?;
+    customer_history(&conn, customer_id)
+}
 
+fn customer_history(conn: &Connection, customer_id: i32) -> Result<CustomerHistory, AppError> {
+    let customer = conn
+        .query_row(
+            &format!("SELECT {} FROM customers WHERE id=?1", CUSTOMER_COLUMNS),
+            [customer_id],
+            customer_from_row,
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("customer"),
+            other => other.into(),
+        })?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM sales WHERE customer_id=?1 ORDER BY datetime(sale_date) DESC, ticket_id DESC, id",
+        SALE_COLUMNS
+    ))?;
     let sales = stmt
+        .query_map([customer_id], sale_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut tickets: Vec<CustomerTicket> = Vec::new();
+    for sale in sales {
+        let same_ticket = sale.ticket_id.is_some()
+            && tickets.last().is_some_and(|t| t.ticket_id == sale.ticket_id);
+        if !same_ticket {
+            tickets.push(CustomerTicket {
+                ticket_id: sale.ticket_id,
+                sale_date: sale.sale_date.clone(),
+                channel: sale.channel.clone(),
+                units: 0,
+                total: Money::ZERO,
+                lines: Vec::new(),
+            });
+        }
+        if let Some(ticket) = tickets.last_mut() {
+            ticket.units += sale.quantity as i64;
+            ticket.total += sale.sale_price;
+            ticket.lines.push(sale);
+        }
+    }
+
+    Ok(CustomerHistory {
+        customer,
+        ticket_count: tickets.len() as i64,
+        lifetime_value: tickets.iter().map(|t| t.total).sum(),
+        last_visit: tickets.first().map(|t| t.sale_date.clone()),
+        tickets,
+    })
+}
+
+// ============================================
+// SETTINGS
+// ============================================
+
+fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    match conn.query_row("SELECT value FROM settings WHERE key=?1", [key], |row| row.get(0)) {
+        Ok(value) => Ok(Some(value)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+// ============================================
+// BACKUP COMMANDS
+// ============================================
+
+// Respaldo .tar.gz con una copia de la base (VACUUM INTO, consistente
+// aunque la app siga en uso) y la carpeta de medios con las imágenes
+fn write_backup(conn: &Connection, media_dir: &Path, out: &Path) -> Result<(), AppError> {
+    let snapshot = out.with_extension("db.tmp");
+    let _ = fs::remove_file(&snapshot);
+    conn.execute("VACUUM INTO ?1", [snapshot.to_string_lossy()])?;
+    let archive = || -> Result<(), AppError> {
+        let file = fs::File::create(out)?;
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+        archive.append_path_with_name(&snapshot, "vitasport.db")?;
+        if media_dir.is_dir() {
+            archive.append_dir_all(MEDIA_DIR, media_dir)?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(())
+    };
+    let result = archive();
+    let _ = fs::remove_file(&snapshot);
+    result
+}
+
+// Devuelve la ruta del respaldo en Descargas/VitaSport
+#[tauri::command]
+fn create_backup(state: State<AppState>) -> Result<String, AppError> {
+    let path = export_path("respaldo", "tar.gz")?;
+    let conn = state.db.lock()?;
+    write_backup(&conn, Path::new(MEDIA_DIR), &path)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+// ============================================
+// LOYALTY COMMANDS
+// ============================================
+//
+// Libro de puntos por cliente. Tipos de movimiento:
+// - "acumulacion": puntos ganados en un ticket; `remaining` es lo que queda
+//   sin canjear ni vencer
+// - "canje": puntos usados como descuento (negativo)
+// - "vencimiento": puntos caducados (negativo)
+// Los canjes consumen primero los puntos que vencen antes.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoyaltySettings {
+    /// Valor de un punto al canjearlo
+    point_value: Money,
+    /// Días de validez de los puntos; None = no vencen
+    expiry_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoyaltyRule {
+    id: Option<i32>,
+    /// None = regla general
+    category: Option<String>,
+    /// Puntos por cada unidad de moneda del importe neto
+    points_per_unit: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoyaltyEntry {
+    id: i32,
+    customer_id: i32,
+    ticket_id: Option<i32>,
+    entry_type: String,
+    points: i64,
+    remaining: i64,
+    expires_at: Option<String>,
+    note: Option<String>,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoyaltyBalance {
+    customer_id: i32,
+    points: i64,
+    /// Valor de los puntos al canjearlos
+    value: Money,
+}
+
+fn loyalty_settings(conn: &Connection) -> Result<LoyaltySettings, AppError> {
+    let point_value = get_setting(conn, "loyalty_point_value")?
+        .and_then(|v| v.parse().ok())
+        .map(Money::from_cents)
+        .unwrap_or(Money::from_cents(1));
+    let expiry_days = match get_setting(conn, "loyalty_expiry_days")? {
+        Some(v) => v.parse().ok().filter(|d| *d > 0),
+        None => Some(365),
+    };
+    Ok(LoyaltySettings { point_value, expiry_days })
+}
+
+fn loyalty_points_value(conn: &Connection, points: i64) -> Result<Money, AppError> {
+    loyalty_settings(conn)?
+        .point_value
+        .checked_times(points)
+        .ok_or(AppError::validation("redeem_points"))
+}
+
+// Da por vencidos los puntos cuya fecha de vencimiento ya pasó en `at`
+fn expire_loyalty_points(conn: &Connection, at: &str) -> Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT id, customer_id, remaining, expires_at FROM loyalty_ledger
+         WHERE entry_type='acumulacion' AND remaining > 0
+           AND expires_at IS NOT NULL AND datetime(expires_at) <= datetime(?1)",
+    )?;
+    let due = stmt
+        .query_map([at], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (id, customer_id, remaining, expires_at) in &due {
+        conn.execute(
+            "INSERT INTO loyalty_ledger (customer_id, entry_type, points, note, created_at)
+             VALUES (?1, 'vencimiento', ?2, ?3, ?4)",
+            rusqlite::params![customer_id, -remaining, format!("Vencen puntos del movimiento {}", id), expires_at],
+        )?;
+        conn.execute("UPDATE loyalty_ledger SET remaining=0 WHERE id=?1", [id])?;
+    }
+    Ok(due.len())
+}
+
+fn loyalty_balance(conn: &Connection, customer_id: i32, at: &str) -> Result<i64, AppError> {
+    expire_loyalty_points(conn, at)?;
+    Ok(conn.query_row(
+        "SELECT COALESCE(SUM(points),0) FROM loyalty_ledger WHERE customer_id=?1",
+        [customer_id],
+        |row| row.get(0),
+    )?)
+}
+
+fn accrue_loyalty_points(
+    conn: &Connection,
+    customer_id: i32,
+    ticket_id: i64,
+    lines: &[(Option<String>, Money)],
+    at: &str,
+) -> Result<i64, AppError> {
+    let mut stmt = conn.prepare("SELECT category, points_per_unit FROM loyalty_rules")?;
+    let rules = stmt
+        .query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, f64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let default_rate = rules.iter().find(|(c, _)| c.is_none()).map(|(_, r)| *r).unwrap_or(0.0);
+
+    let mut points = 0;
+    for (category, net) in lines {
+        let rate = rules
+            .iter()
+            .find(|(c, _)| match (c, category) {
+                (Some(c), Some(cat)) => c.trim().eq_ignore_ascii_case(cat.trim()),
+                _ => false,
+            })
+            .map(|(_, r)| *r)
+            .unwrap_or(default_rate);
+        points += (net.to_major() * rate).floor().max(0.0) as i64;
+    }
+    if points == 0 {
+        return Ok(0);
+    }
+    let expiry_days = loyalty_settings(conn)?.expiry_days;
+    conn.execute(
+        "INSERT INTO loyalty_ledger (customer_id, ticket_id, entry_type, points, remaining, expires_at, created_at)
+         VALUES (?1, ?2, 'acumulacion', ?3, ?3,
+                 CASE WHEN ?4 IS NULL THEN NULL ELSE datetime(?5, '+' || ?4 || ' days') END, ?5)",
+        rusqlite::params![customer_id, ticket_id, points, expiry_days, at],
+    )?;
+    Ok(points)
+}
+
+fn redeem_loyalty_points(
+    conn: &Connection,
+    customer_id: i32,
+    ticket_id: Option<i64>,
+    points: i64,
+    at: &str,
+) -> Result<(), AppError> {
+    if points <= 0 || points > loyalty_balance(conn, customer_id, at)? {
+        return Err(AppError::validation("redeem_points"));
+    }
+    let mut stmt = conn.prepare(
+        "SELECT id, remaining FROM loyalty_ledger
+         WHERE customer_id=?1 AND entry_type='acumulacion' AND remaining > 0
+         ORDER BY expires_at IS NULL, datetime(expires_at), id",
+    )?;
+    let open = stmt
+        .query_map([customer_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pending = points;
+    for (id, remaining) in open {
+        if pending == 0 {
+            break;
+        }
+        let used = remaining.min(pending);
+        conn.execute("UPDATE loyalty_ledger SET remaining=remaining-?1 WHERE id=?2", [used, id])?;
+        pending -= used;
+    }
+    conn.execute(
+        "INSERT INTO loyalty_ledger (customer_id, ticket_id, entry_type, points, created_at)
+         VALUES (?1, ?2, 'canje', ?3, ?4)",
+        rusqlite::params![customer_id, ticket_id, -points, at],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_loyalty_balance(state: State<AppState>, customer_id: i32) -> Result<LoyaltyBalance, AppError> {
+    let conn = state.db.lock()?;
+    let points = loyalty_balance(&conn, customer_id, "now")?;
+    Ok(LoyaltyBalance {
+        customer_id,
+        points,
+        value: loyalty_points_value(&conn, points)?,
+    })
+}
+
+#[tauri::command]
+fn get_loyalty_ledger(state: State<AppState>, customer_id: i32) -> Result<Vec<LoyaltyEntry>, AppError> {
+    let conn = state.db.lock()?;
+    expire_loyalty_points(&conn, "now")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, customer_id, ticket_id, entry_type, points, remaining, expires_at, note, created_at
+         FROM loyalty_ledger WHERE customer_id=?1 ORDER BY datetime(created_at) DESC, id DESC",
+    )?;
+    let entries = stmt
+        .query_map([customer_id], |row| {
+            Ok(LoyaltyEntry {
+                id: row.get(0)?,
+                customer_id: row.get(1)?,
+                ticket_id: row.get(2)?,
+                entry_type: row.get(3)?,
+                points: row.get(4)?,
+                remaining: row.get(5)?,
+                expires_at: row.get(6)?,
+                note: row.get(7)?,
+                created_at: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+#[tauri::command]
+fn get_loyalty_rules(state: State<AppState>) -> Result<Vec<LoyaltyRule>, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare("SELECT id, category, points_per_unit FROM loyalty_rules ORDER BY category IS NOT NULL, category")?;
+    let rules = stmt
         .query_map([], |row| {
-            Ok(Sale {
+            Ok(LoyaltyRule {
                 id: row.get(0)?,
-                product_id: row.get(1)?,
-                quantity: row.get(2)?,
-                sale_price: row.get(3)?,
-                discount: row.get(4)?,
-                channel: row.get(5)?,
-                sale_date: row.get(6)?,
-                created_by: row.get(7)?,
+                category: row.get(1)?,
+                points_per_unit: row.get(2)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-    Ok(sales)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rules)
 }
 
+/// Crea o reemplaza la regla de la categoría (o la general si no hay categoría)
 #[tauri::command]
-fn add_sale(state: State<AppState>, sale: Sale) -> Result<i64, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    conn.execute("BEGIN IMMEDIATE TRANSACTION", []).map_err(|e| e.to_string())?;
-    let result: Result<i64, String> = (|| {
-        let current_stock: i64 = conn
-            .query_row(
-                "SELECT COALESCE(SUM(CASE WHEN type='ingreso' THEN quantity WHEN type='egreso' THEN -quantity ELSE 0 END),0) FROM stock_movements WHERE product_id=?1",
-                rusqlite::params![sale.product_id],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-        if (sale.quantity as i64) > current_stock {
-            return Err(format!("Stock insuficiente. Disponible: {}, solicitado: {}", current_stock, sale.quantity));
-        }
-        conn.execute(
-            "INSERT INTO sales (product_id, quantity, sale_price, discount, channel, sale_date, created_by) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            rusqlite::params![
-                sale.product_id,
-                sale.quantity,
-                sale.sale_price,
-                sale.discount,
-                sale.channel,
-                sale.sale_date,
-                sale.created_by,
-            ],
-        ).map_err(|e| e.to_string())?;
-        let sale_id = conn.last_insert_rowid();
+fn save_loyalty_rule(state: State<AppState>, rule: LoyaltyRule) -> Result<(), AppError> {
+    if !rule.points_per_unit.is_finite() || rule.points_per_unit < 0.0 {
+        return Err(AppError::validation("points_per_unit"));
+    }
+    let category = rule.category.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let conn = state.db.lock()?;
+    let updated = conn.execute(
+        "UPDATE loyalty_rules SET points_per_unit=?1 WHERE category IS ?2",
+        rusqlite::params![rule.points_per_unit, category],
+    )?;
+    if updated == 0 {
         conn.execute(
-            "INSERT INTO stock_movements (product_id, type, quantity, note, created_by)
-             VALUES (?1, 'egreso', ?2, ?3, ?4)",
-            rusqlite::params![
-                sale.product_id,
-                sale.quantity,
-                Option::<String>::None,
-                sale.created_by,
-            ],
-        ).map_err(|e| e.to_string())?;
-        Ok(sale_id)
-    })();
-    match result {
-        Ok(sale_id) => {
-            conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
-            Ok(sale_id)
+            "INSERT INTO loyalty_rules (category, points_per_unit) VALUES (?1, ?2)",
+            rusqlite::params![category, rule.points_per_unit],
+        )?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_loyalty_rule(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let deleted = conn.execute("DELETE FROM loyalty_rules WHERE id=?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("loyalty_rule"));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_loyalty_settings(state: State<AppState>) -> Result<LoyaltySettings, AppError> {
+    let conn = state.db.lock()?;
+    loyalty_settings(&conn)
+}
+
+#[tauri::command]
+fn update_loyalty_settings(state: State<AppState>, settings: LoyaltySettings) -> Result<(), AppError> {
+    if settings.point_value <= Money::ZERO {
+        return Err(AppError::validation("point_value"));
+    }
+    if settings.expiry_days.is_some_and(|d| d <= 0) {
+        return Err(AppError::validation("expiry_days"));
+    }
+    let conn = state.db.lock()?;
+    set_setting(&conn, "loyalty_point_value", &settings.point_value.cents().to_string())?;
+    // "0" = sin vencimiento
+    set_setting(&conn, "loyalty_expiry_days", &settings.expiry_days.unwrap_or(0).to_string())?;
+    Ok(())
+}
+
+// ============================================
+// CREDIT COMMANDS
+// ============================================
+//
+// Ventas al crédito (fiado). Cada ticket con `on_account` es un cargo en la
+// cuenta del cliente por lo pagado con la forma de pago "credito"; los cobros se registran como
+// `ingreso` en cash_movements y se reparten entre los tickets pendientes,
+// del más antiguo al más reciente.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomerPayment {
+    id: Option<i32>,
+    customer_id: i32,
+    amount: Money,
+    /// Forma de pago del cobro (cualquiera salvo "credito" y "puntos"); por
+    /// defecto efectivo
+    #[serde(default)]
+    method: Option<String>,
+    payment_date: String,
+    note: Option<String>,
+    /// Ticket al que se aplica primero el cobro
+    #[serde(default)]
+    ticket_id: Option<i32>,
+    created_by: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReceivableTicket {
+    ticket_id: i32,
+    customer_id: i32,
+    customer_name: String,
+    sale_date: String,
+    total: Money,
+    paid: Money,
+    balance: Money,
+    /// Días desde la venta hasta la fecha de corte
+    age_days: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AgingRow {
+    customer_id: i32,
+    customer_name: String,
+    days_0_30: Money,
+    days_31_60: Money,
+    days_over_60: Money,
+    total: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AccountEntry {
+    date: String,
+    /// "cargo" (venta al crédito) o "abono" (cobro)
+    entry_type: String,
+    /// Id del ticket o del cobro
+    reference_id: i32,
+    amount: Money,
+    /// Saldo pendiente después del movimiento
+    balance: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomerAccount {
+    customer_id: i32,
+    balance: Money,
+    entries: Vec<AccountEntry>,
+}
+
+/// Tickets al crédito con saldo pendiente, del más antiguo al más reciente.
+/// `as_of` es la fecha de corte para calcular la antigüedad ("now" = hoy).
+fn open_receivables(conn: &Connection, customer_id: Option<i32>, as_of: &str) -> Result<Vec<ReceivableTicket>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.customer_id, c.name, t.sale_date,
+                (SELECT COALESCE(SUM(tp.amount),0) FROM ticket_payments tp WHERE tp.ticket_id = t.id AND tp.method = 'credito') AS total,
+                (SELECT COALESCE(SUM(pa.amount),0) FROM payment_allocations pa WHERE pa.ticket_id = t.id) AS paid,
+                CAST(julianday(date(?2)) - julianday(date(t.sale_date)) AS INTEGER)
+         FROM tickets t
+         JOIN customers c ON c.id = t.customer_id
+         WHERE t.on_account = 1 AND (?1 IS NULL OR t.customer_id = ?1)
+           AND datetime(t.sale_date) < datetime(date(?2), '+1 day')
+         ORDER BY datetime(t.sale_date), t.id",
+    )?;
+    let rows = stmt
+        .query_map(rusqlite::params![customer_id, as_of], |row| {
+            let total: Money = row.get(4)?;
+            let paid: Money = row.get(5)?;
+            Ok(ReceivableTicket {
+                ticket_id: row.get(0)?,
+                customer_id: row.get(1)?,
+                customer_name: row.get(2)?,
+                sale_date: row.get(3)?,
+                total,
+                paid,
+                balance: total - paid,
+                age_days: row.get::<_, Option<i64>>(6)?.unwrap_or(0).max(0),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows.into_iter().filter(|r| r.balance > Money::ZERO).collect())
+}
+
+fn record_customer_payment(conn: &Connection, payment: &CustomerPayment) -> Result<i64, AppError> {
+    if payment.amount <= Money::ZERO {
+        return Err(AppError::validation("amount"));
+    }
+    let method = payment.method.as_deref().unwrap_or("efectivo");
+    if TICKET_ONLY_METHODS.contains(&method) || !PAYMENT_METHODS.contains(&method) {
+        return Err(AppError::validation("method"));
+    }
+    let customer_name: String = conn
+        .query_row("SELECT name FROM customers WHERE id=?1", [payment.customer_id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("customer"),
+            other => other.into(),
+        })?;
+
+    let mut open = open_receivables(conn, Some(payment.customer_id), &payment.payment_date)?;
+    if let Some(first) = payment.ticket_id {
+        let pos = open
+            .iter()
+            .position(|t| t.ticket_id == first)
+            .ok_or(AppError::validation("ticket_id"))?;
+        let ticket = open.remove(pos);
+        open.insert(0, ticket);
+    }
+    // No se aceptan cobros por encima de la deuda (no hay saldo a favor)
+    let due: Money = open.iter().map(|t| t.balance).sum();
+    if payment.amount > due {
+        return Err(AppError::validation("amount"));
+    }
+
+    let session_id = resolve_session(conn, None, payment.created_by)?;
+    conn.execute(
+        "INSERT INTO cash_movements (movement_type, amount, category, description, movement_date, payment_method, session_id, created_by)
+         VALUES ('ingreso', ?1, 'Cobro de crédito', ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            payment.amount,
+            format!("Cobro a {}", customer_name),
+            payment.payment_date,
+            method,
+            session_id,
+            payment.created_by,
+        ],
+    )?;
+    let cash_movement_id = conn.last_insert_rowid();
+    conn.execute(
+        "INSERT INTO customer_payments (customer_id, amount, method, payment_date, note, cash_movement_id, created_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            payment.customer_id,
+            payment.amount,
+            method,
+            payment.payment_date,
+            payment.note,
+            cash_movement_id,
+            payment.created_by,
+        ],
+    )?;
+    let payment_id = conn.last_insert_rowid();
+
+    let mut pending = payment.amount;
+    for ticket in open {
+        if pending == Money::ZERO {
+            break;
         }
-        Err(err) => {
-            let _ = conn.execute("ROLLBACK", []);
-            Err(err)
+        let applied = pending.min(ticket.balance);
+        conn.execute(
+            "INSERT INTO payment_allocations (payment_id, ticket_id, amount) VALUES (?1, ?2, ?3)",
+            rusqlite::params![payment_id, ticket.ticket_id, applied],
+        )?;
+        pending -= applied;
+    }
+    Ok(payment_id)
+}
+
+fn receivables_aging(conn: &Connection, as_of: &str) -> Result<Vec<AgingRow>, AppError> {
+    let mut rows: Vec<AgingRow> = Vec::new();
+    for ticket in open_receivables(conn, None, as_of)? {
+        let pos = match rows.iter().position(|r| r.customer_id == ticket.customer_id) {
+            Some(pos) => pos,
+            None => {
+                rows.push(AgingRow {
+                    customer_id: ticket.customer_id,
+                    customer_name: ticket.customer_name.clone(),
+                    days_0_30: Money::ZERO,
+                    days_31_60: Money::ZERO,
+                    days_over_60: Money::ZERO,
+                    total: Money::ZERO,
+                });
+                rows.len() - 1
+            }
+        };
+        let row = &mut rows[pos];
+        match ticket.age_days {
+            0..=30 => row.days_0_30 += ticket.balance,
+            31..=60 => row.days_31_60 += ticket.balance,
+            _ => row.days_over_60 += ticket.balance,
         }
+        row.total += ticket.balance;
     }
-}
-#[tauri::command]
-fn get_cash_movements(state: State<AppState>) -> Result<Vec<CashMovement>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, movement_type, amount, category, description, movement_date, created_by FROM cash_movements ORDER BY movement_date DESC, id DESC LIMIT 100")
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(CashMovement {
-                id: row.get(0)?,
-                movement_type: row.get(1)?,
-                amount: row.get(2)?,
-                category: row.get(3)?,
-                description: row.get(4)?,
-                movement_date: row.get(5)?,
-                created_by: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-
+    rows.sort_by_key(|r| std::cmp::Reverse(r.total));
     Ok(rows)
 }
 
 #[tauri::command]
-fn add_cash_movement(state: State<AppState>, movement: CashMovement) -> Result<i64, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO cash_movements (movement_type, amount, category, description, movement_date, created_by) \
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![
-            movement.movement_type,
-            movement.amount,
-            movement.category,
-            movement.description,
-            movement.movement_date,
-            movement.created_by,
-        ],
-    )
-    .map_err(|e| e.to_string())?;
-
-    Ok(conn.last_insert_rowid())
+fn add_customer_payment(state: State<AppState>, payment: CustomerPayment) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
+    in_transaction(&conn, || record_customer_payment(&conn, &payment))
 }
 
 #[tauri::command]
-fn get_cash_summary(state: State<AppState>) -> Result<CashSummary, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-
-    let total_sales_income: f64 = conn
-        .query_row(
-            "SELECT COALESCE(SUM(sale_price),0.0) FROM sales",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0.0);
-
-    let total_other_income: f64 = conn
-        .query_row(
-            "SELECT COALESCE(SUM(amount),0.0) FROM cash_movements WHERE movement_type='ingreso'",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0.0);
+fn get_receivables(state: State<AppState>, customer_id: Option<i32>) -> Result<Vec<ReceivableTicket>, AppError> {
+    let conn = state.db.lock()?;
+    open_receivables(&conn, customer_id, "now")
+}
 
-    let total_expense: f64 = conn
-        .query_row(
-            "SELECT COALESCE(SUM(amount),0.0) FROM cash_movements WHERE movement_type='egreso'",
-            [],
-            |row| row.get(0),
-        )
-        .unwrap_or(0.0);
+/// Saldos pendientes por cliente en tramos de 0-30, 31-60 y más de 60 días
+#[tauri::command]
+fn get_receivables_aging(state: State<AppState>, as_of: Option<String>) -> Result<Vec<AgingRow>, AppError> {
+    let conn = state.db.lock()?;
+    receivables_aging(&conn, as_of.as_deref().unwrap_or("now"))
+}
 
-    let income = total_sales_income + total_other_income;
+/// Estado de cuenta: cargos y abonos con saldo acumulado
+#[tauri::command]
+fn get_customer_account(state: State<AppState>, customer_id: i32) -> Result<CustomerAccount, AppError> {
+    let conn = state.db.lock()?;
+    let mut stmt = conn.prepare(
+        "SELECT date, entry_type, reference_id, amount FROM (
+             SELECT t.sale_date AS date, 'cargo' AS entry_type, t.id AS reference_id,
+                    (SELECT COALESCE(SUM(tp.amount),0) FROM ticket_payments tp WHERE tp.ticket_id = t.id AND tp.method = 'credito') AS amount
+             FROM tickets t WHERE t.customer_id=?1 AND t.on_account=1
+             UNION ALL
+             SELECT payment_date, 'abono', id, amount FROM customer_payments WHERE customer_id=?1
+         )
+         ORDER BY datetime(date), entry_type DESC, reference_id",
+    )?;
+    let rows = stmt
+        .query_map([customer_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i32>(2)?, row.get::<_, Money>(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(CashSummary {
-        total_income: income,
-        total_expense,
-        balance: income - total_expense,
-    })
+    let mut balance = Money::ZERO;
+    let mut entries = Vec::with_capacity(rows.len());
+    for (date, entry_type, reference_id, amount) in rows {
+        if entry_type == "cargo" {
+            balance += amount;
+        } else {
+            balance -= amount;
+        }
+        entries.push(AccountEntry { date, entry_type, reference_id, amount, balance });
+    }
+    Ok(CustomerAccount { customer_id, balance, entries })
 }
 
 // ============================================
@@ -876,11 +5789,10 @@ fn get_cash_summary(state: State<AppState>) -> Result<CashSummary, String> {
 
 // ... (rest of the code remains the same)
 #[tauri::command]
-fn get_users(state: State<AppState>) -> Result<Vec<User>, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn get_users(state: State<AppState>) -> Result<Vec<User>, AppError> {
+    let conn = state.db.lock()?;
     let mut stmt = conn
-        .prepare("SELECT id, username, role, fullname FROM users")
-        .map_err(|e| e.to_string())?;
+        .prepare("SELECT id, username, role, fullname FROM users")?;
 
     let users = stmt
         .query_map([], |row| {
@@ -891,20 +5803,18 @@ fn get_users(state: State<AppState>) -> Result<Vec<User>, String> {
                 role: row.get(2)?,
                 fullname: row.get(3)?,
             })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(users)
 }
 
 #[tauri::command]
-fn add_user(state: State<AppState>, username: String, fullname: String, password: String, role: String) -> Result<i64, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn add_user(state: State<AppState>, username: String, fullname: String, password: String, role: String) -> Result<i64, AppError> {
+    let conn = state.db.lock()?;
     
     // Hash seguro de la contraseña con bcrypt
-    let password_hash = hash(&password, DEFAULT_COST).map_err(|e| e.to_string())?;
+    let password_hash = hash(&password, DEFAULT_COST)?;
     
     conn.execute(
         "INSERT INTO users (username, fullname, password_hash, role) 
@@ -915,49 +5825,51 @@ fn add_user(state: State<AppState>, username: String, fullname: String, password
             password_hash,
             role,
         ],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
     Ok(conn.last_insert_rowid())
 }
 
 #[tauri::command]
-fn update_user(state: State<AppState>, id: i32, username: String, fullname: String, role: String, password: Option<String>) -> Result<(), String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn update_user(state: State<AppState>, id: i32, username: String, fullname: String, role: String, password: Option<String>) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
     
-    if let Some(pwd) = password {
+    let updated = if let Some(pwd) = password {
         // Si se proporciona contraseña, hashearla y actualizarla
-        let password_hash = hash(&pwd, DEFAULT_COST).map_err(|e| e.to_string())?;
+        let password_hash = hash(&pwd, DEFAULT_COST)?;
         conn.execute(
             "UPDATE users SET username = ?1, fullname = ?2, role = ?3, password_hash = ?4, updated_at = CURRENT_TIMESTAMP WHERE id = ?5",
             rusqlite::params![username, fullname, role, password_hash, id],
-        )
-        .map_err(|e| e.to_string())?;
+        )?
     } else {
         // Solo actualizar username, fullname y role (mantener contraseña actual)
         conn.execute(
             "UPDATE users SET username = ?1, fullname = ?2, role = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?4",
             rusqlite::params![username, fullname, role, id],
-        )
-        .map_err(|e| e.to_string())?;
+        )?
+    };
+    if updated == 0 {
+        return Err(AppError::not_found("user"));
     }
 
     Ok(())
 }
 
 #[tauri::command]
-fn delete_user(state: State<AppState>, id: i32) -> Result<(), String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM users WHERE id = ?1", rusqlite::params![id])
-        .map_err(|e| e.to_string())?;
+fn delete_user(state: State<AppState>, id: i32) -> Result<(), AppError> {
+    let conn = state.db.lock()?;
+    let deleted = conn.execute("DELETE FROM users WHERE id = ?1", rusqlite::params![id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("user"));
+    }
     Ok(())
 }
 
 /// Verifica las credenciales de login contra la base de datos
 /// Retorna el usuario si las credenciales son correctas, error si no
 #[tauri::command]
-fn verify_login(state: State<AppState>, username: String, password: String) -> Result<User, String> {
-    let conn = state.db.lock().map_err(|e| e.to_string())?;
+fn verify_login(state: State<AppState>, username: String, password: String) -> Result<User, AppError> {
+    let conn = state.db.lock()?;
     
     // Buscar usuario por username
     let result = conn.query_row(
@@ -977,8 +5889,7 @@ fn verify_login(state: State<AppState>, username: String, password: String) -> R
     match result {
         Ok(user) => {
             // Verificar contraseña con bcrypt
-            let is_valid = verify(&password, &user.password_hash)
-                .map_err(|e| format!("Error verificando contraseña: {}", e))?;
+            let is_valid = verify(&password, &user.password_hash)?;
             
             if is_valid {
                 // No enviar el hash de contraseña al frontend
@@ -990,15 +5901,27 @@ fn verify_login(state: State<AppState>, username: String, password: String) -> R
                     fullname: user.fullname,
                 })
             } else {
-                Err("Contraseña incorrecta".to_string())
+                Err(AppError::Unauthorized)
             }
         }
-        Err(_) => Err("Usuario no encontrado".to_string()),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(AppError::Unauthorized),
+        Err(e) => Err(e.into()),
     }
 }
 
+/// Cambia el idioma de los mensajes de error devueltos por los comandos ("es" o "en")
+#[tauri::command]
+fn set_language(lang: String) -> Result<(), AppError> {
+    error::set_lang(&lang);
+    Ok(())
+}
+
 fn main() {
     let db = init_database().expect("Failed to initialize database");
+    // Imágenes elegidas antes con rutas externas: se copian a la carpeta de medios
+    if let Err(e) = import_external_images(&db, Path::new(MEDIA_DIR)) {
+        eprintln!("⚠️ No se pudieron importar las imágenes de productos: {}", e);
+    }
 
     tauri::Builder::default()
         .manage(AppState { db: Mutex::new(db) })
@@ -1008,10 +5931,71 @@ fn main() {
             update_product,
             delete_product,
             get_stock_movements,
+            get_purchases,
+            add_purchase,
+            get_costing_method,
+            set_costing_method,
+            get_margin_report,
+            get_inventory_valuation,
+            export_inventory_valuation,
+            get_stock_card,
+            export_stock_card,
+            inventory::get_locations,
+            inventory::add_location,
+            inventory::update_location,
+            inventory::delete_location,
+            inventory::get_location_balances,
+            inventory::get_transfers,
+            inventory::transfer_stock,
+            inventory::receive_transfer,
+            inventory::cancel_transfer,
+            inventory::get_bins,
+            inventory::add_bin,
+            inventory::update_bin,
+            inventory::delete_bin,
+            inventory::assign_product_bin,
+            inventory::get_bin_balances,
+            inventory::get_picking_list,
+            inventory::move_stock_between_bins,
+            catalog::get_parent_products,
+            catalog::get_product_variants,
+            catalog::add_parent_product,
+            catalog::update_parent_product,
+            catalog::delete_parent_product,
+            catalog::get_product_barcodes,
+            catalog::add_product_barcode,
+            catalog::delete_product_barcode,
+            catalog::find_product_by_barcode,
+            catalog::get_barcode_prefix,
+            catalog::set_barcode_prefix,
+            catalog::generate_product_barcode,
+            export_labels,
+            get_receipt_settings,
+            update_receipt_settings,
+            get_receipt_text,
+            print_receipt,
+            export_receipt_pdf,
+            catalog::attach_product_image,
+            catalog::get_product_image,
+            catalog::remove_product_image,
+            create_backup,
+            catalog::import_products_csv,
             add_stock_movement,
             get_sales,
             add_sale,
             get_cash_movements,
+            get_expense_categories,
+            add_expense_category,
+            update_expense_category,
+            delete_expense_category,
+            get_expenses_by_category,
+            get_recurring_expenses,
+            add_recurring_expense,
+            update_recurring_expense,
+            delete_recurring_expense,
+            run_recurring_expenses,
+            get_pending_expenses,
+            confirm_expense,
             add_cash_movement,
             get_cash_summary,
             get_sales_by_product,
@@ -1021,12 +6005,1381 @@ fn main() {
             export_inventory_report,
             export_sales_report,
             export_all_reports,
+            get_csv_settings,
+            update_csv_settings,
             get_users,
             add_user,
             update_user,
             delete_user,
             verify_login,
+            set_language,
+            get_price_history,
+            get_price_at,
+            get_scheduled_prices,
+            schedule_price_change,
+            delete_scheduled_price,
+            add_ticket,
+            get_promotions,
+            add_promotion,
+            update_promotion,
+            delete_promotion,
+            get_promotion_report,
+            get_customers,
+            search_customers,
+            add_customer,
+            update_customer,
+            delete_customer,
+            get_customer_history,
+            get_loyalty_balance,
+            get_loyalty_ledger,
+            get_loyalty_rules,
+            save_loyalty_rule,
+            delete_loyalty_rule,
+            get_loyalty_settings,
+            update_loyalty_settings,
+            add_customer_payment,
+            get_receivables,
+            get_receivables_aging,
+            get_customer_account,
+            get_ticket_payments,
+            open_cash_session,
+            get_open_cash_sessions,
+            get_cash_sessions,
+            close_cash_session,
+            get_z_report,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use catalog::{import_products, insert_barcode, plan_product_import};
+    use inventory::{record_transfer, NewTransfer};
+    use test_util::{seed_customer, seed_product, seed_session, seed_stock, test_db};
+
+    #[test]
+    fn legacy_real_money_columns_are_migrated_to_cents() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sales (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                product_id INTEGER NOT NULL,
+                quantity INTEGER NOT NULL,
+                sale_price REAL NOT NULL,
+                discount REAL,
+                channel TEXT,
+                sale_date TEXT NOT NULL,
+                created_by INTEGER
+            );
+            CREATE TABLE cash_movements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                movement_type TEXT NOT NULL,
+                amount REAL NOT NULL,
+                category TEXT,
+                description TEXT,
+                movement_date TEXT NOT NULL,
+                created_by INTEGER
+            );
+            INSERT INTO sales (product_id, quantity, sale_price, discount, sale_date)
+                VALUES (1, 1, 19.99, 10, '2024-01-01'), (1, 2, 1.005, NULL, '2024-01-02');
+            INSERT INTO cash_movements (movement_type, amount, movement_date)
+                VALUES ('ingreso', 0.1, '2024-01-01'), ('egreso', 0.3, '2024-01-01');",
+        )
+        .unwrap();
+
+        init_schema(&conn).unwrap();
+
+        let prices: Vec<i64> = conn
+            .prepare("SELECT sale_price FROM sales ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(prices, vec![1999, 101]);
+        let discount: f64 = conn
+            .query_row("SELECT discount FROM sales WHERE id=1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(discount, 10.0);
+
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        assert_eq!(summary.total_income, Money::from_cents(1999 + 101 + 10));
+        assert_eq!(summary.total_expense, Money::from_cents(30));
+        assert_eq!(summary.balance, Money::from_cents(2080));
+
+        // Una segunda ejecución no vuelve a tocar los datos
+        init_schema(&conn).unwrap();
+        let total: i64 = conn
+            .query_row("SELECT SUM(sale_price) FROM sales", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 2100);
+    }
+
+    #[test]
+    fn money_migration_keeps_foreign_keys_setting() {
+        for setting in [0, 1] {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(&format!(
+                "PRAGMA foreign_keys = {};
+                 CREATE TABLE cash_movements (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     movement_type TEXT NOT NULL,
+                     amount REAL NOT NULL,
+                     movement_date TEXT NOT NULL
+                 );
+                 INSERT INTO cash_movements (movement_type, amount, movement_date) VALUES ('ingreso', 0.1, '2024-01-01');",
+                setting
+            ))
+            .unwrap();
+            migrate_money_columns(&conn).unwrap();
+            let amount: i64 = conn.query_row("SELECT amount FROM cash_movements", [], |row| row.get(0)).unwrap();
+            assert_eq!(amount, 10);
+            let after: i64 = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0)).unwrap();
+            assert_eq!(after, setting);
+        }
+    }
+
+    fn test_sale(product_id: i32, quantity: i32, discount: Option<f64>) -> Sale {
+        Sale {
+            id: None,
+            product_id,
+            quantity,
+            unit_price: Money::ZERO,
+            gross_amount: Money::ZERO,
+            discount,
+            discount_amount: Money::ZERO,
+            sale_price: Money::from_cents(999_900),
+            ticket_id: None,
+            promotion_id: None,
+            promotion_amount: Money::ZERO,
+            customer_id: None,
+            points_amount: Money::ZERO,
+            on_account: false,
+            payments: Vec::new(),
+            channel: Some("Tienda".to_string()),
+            sale_date: "2024-03-01T10:00:00Z".to_string(),
+            created_by: None,
+        }
+    }
+
+    #[test]
+    fn record_sale_computes_amounts_from_list_price() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        seed_product(&conn, 1, "Whey", 1999);
+        seed_stock(&conn, 1, 10);
+
+        let mut input = test_sale(1, 3, Some(15.0));
+        input.created_by = Some(user);
+        let id = record_sale(&conn, &input).unwrap();
+        let sale = conn
+            .query_row(&format!("SELECT {} FROM sales WHERE id=?1", SALE_COLUMNS), [id], sale_from_row)
+            .unwrap();
+        // El precio enviado por el cliente se ignora
+        assert_eq!(sale.unit_price, Money::from_cents(1999));
+        assert_eq!(sale.gross_amount, Money::from_cents(5997));
+        assert_eq!(sale.discount_amount, Money::from_cents(900));
+        assert_eq!(sale.sale_price, Money::from_cents(5097));
+
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        assert_eq!(summary.sales_gross, Money::from_cents(5997));
+        assert_eq!(summary.sales_discount, Money::from_cents(900));
+        assert_eq!(summary.total_income, Money::from_cents(5097));
+
+        input.quantity = 1;
+        input.discount = Some(120.0);
+        assert!(matches!(record_sale(&conn, &input), Err(AppError::Validation { .. })));
+        input.quantity = 8;
+        input.discount = None;
+        assert!(matches!(
+            record_sale(&conn, &input),
+            Err(AppError::InsufficientStock { available: 7, requested: 8 })
+        ));
+    }
+
+    #[test]
+    fn scheduled_prices_and_history() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        seed_product(&conn, 1, "Whey", 10000);
+        conn.execute_batch(
+            "INSERT INTO price_history (product_id, old_price, new_price, source, changed_at)
+                 VALUES (1, NULL, 10000, 'alta', '2024-01-01 00:00:00');",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 10);
+
+        let promo = ScheduledPrice {
+            id: None,
+            product_id: 1,
+            price: Money::from_cents(8000),
+            starts_at: "2024-03-01".to_string(),
+            ends_at: Some("2024-03-15".to_string()),
+            note: Some("Promo marzo".to_string()),
+            applied_at: None,
+        };
+        insert_scheduled_price(&conn, &promo).unwrap();
+
+        let mut sale = test_sale(1, 1, None);
+
+        sale.created_by = Some(user);
+        sale.sale_date = "2024-03-15T18:30:00.000Z".to_string();
+        let id = record_sale(&conn, &sale).unwrap();
+        let price: Money = conn.query_row("SELECT sale_price FROM sales WHERE id=?1", [id], |r| r.get(0)).unwrap();
+        assert_eq!(price, Money::from_cents(8000));
+
+        sale.sale_date = "2024-03-16T09:00:00.000Z".to_string();
+        let id = record_sale(&conn, &sale).unwrap();
+        let price: Money = conn.query_row("SELECT sale_price FROM sales WHERE id=?1", [id], |r| r.get(0)).unwrap();
+        assert_eq!(price, Money::from_cents(10000));
+
+        let product = Product {
+            id: Some(1),
+            sku: None,
+            name: "Whey".to_string(),
+            sale_price: Some(Money::from_cents(12000)),
+            brand: None,
+            category: None,
+            presentation: None,
+            flavor: None,
+            weight: None,
+            image_path: None,
+            expiry_date: None,
+            lot_number: None,
+            min_stock: None,
+            max_stock: None,
+            location: None,
+            status: None,
+            default_bin_id: None,
+            parent_id: None,
+        };
+        update_product_row(&conn, &product).unwrap();
+        conn.execute("UPDATE price_history SET changed_at='2024-04-01 00:00:00' WHERE source='manual'", [])
+            .unwrap();
+
+        assert_eq!(price_at(&conn, 1, "2024-02-10").unwrap(), Some(Money::from_cents(10000)));
+        assert_eq!(price_at(&conn, 1, "2024-03-10").unwrap(), Some(Money::from_cents(8000)));
+        assert_eq!(price_at(&conn, 1, "2024-04-02").unwrap(), Some(Money::from_cents(12000)));
+        assert!(price_at(&conn, 1, "ayer").is_err());
+
+        let bad = ScheduledPrice { ends_at: Some("2024-02-01".to_string()), ..promo };
+        assert!(matches!(insert_scheduled_price(&conn, &bad), Err(AppError::Validation { .. })));
+    }
+
+    #[test]
+    fn ticket_applies_promotions_by_date_and_channel() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, category) VALUES
+                 (1, 'Whey', 10000, 'Proteínas'), (2, 'Shaker', 2500, 'Accesorios');",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 10);
+        seed_stock(&conn, 2, 10);
+        conn.execute_batch(
+            "INSERT INTO promotions (id, name, kind, percent, category, channels, starts_at, ends_at)
+                 VALUES (1, 'Proteínas -10%', 'porcentaje', 10, 'Proteínas', 'Tienda', '2024-03-01', '2024-03-31');
+             INSERT INTO promotions (id, name, kind, bundle_price) VALUES (2, 'Combo shaker', 'combo', 11000);
+             INSERT INTO promotion_bundle_items (promotion_id, product_id, quantity) VALUES (2, 1, 1), (2, 2, 1);",
+        )
+        .unwrap();
+
+        let ticket = NewTicket {
+            lines: vec![
+                TicketLine { product_id: 1, quantity: 2, discount: None },
+                TicketLine { product_id: 2, quantity: 1, discount: Some(10.0) },
+            ],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: Some("Tienda".to_string()),
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        let (ticket_id, ids) = record_ticket(&conn, &ticket).unwrap();
+        let load = |id: i64| {
+            conn.query_row(&format!("SELECT {} FROM sales WHERE id=?1", SALE_COLUMNS), [id], sale_from_row)
+                .unwrap()
+        };
+        // El combo (ahorro 15) gana a la promoción por categoría y se reparte 12 / 3
+        let whey = load(ids[0]);
+        assert_eq!(whey.ticket_id, Some(ticket_id as i32));
+        assert_eq!(whey.promotion_id, Some(2));
+        assert_eq!(whey.promotion_amount, Money::from_cents(1200));
+        assert_eq!(whey.sale_price, Money::from_cents(18800));
+        // El descuento manual se aplica sobre lo que queda tras la promoción
+        let shaker = load(ids[1]);
+        assert_eq!(shaker.promotion_amount, Money::from_cents(300));
+        assert_eq!(shaker.discount_amount, Money::from_cents(300 + 220));
+        assert_eq!(shaker.sale_price, Money::from_cents(1980));
+
+        // Sin combo, fuera del canal permitido no hay descuento
+        let mut online = test_sale(1, 1, None);
+        online.created_by = Some(user);
+        online.channel = Some("Online".to_string());
+        online.sale_date = "2024-03-10T12:00:00Z".to_string();
+        let sale = load(record_sale(&conn, &online).unwrap());
+        assert_eq!(sale.promotion_id, None);
+        assert_eq!(sale.sale_price, Money::from_cents(10000));
+
+        let mut store = online;
+        store.channel = Some("Tienda".to_string());
+        let sale = load(record_sale(&conn, &store).unwrap());
+        assert_eq!(sale.promotion_id, Some(1));
+        assert_eq!(sale.sale_price, Money::from_cents(9000));
+
+        // El día 31 sigue vigente, el 1 de abril ya no
+        store.sale_date = "2024-04-01T09:00:00Z".to_string();
+        let sale = load(record_sale(&conn, &store).unwrap());
+        assert_eq!(sale.promotion_id, None);
+
+        let over = NewTicket {
+            lines: vec![
+                TicketLine { product_id: 2, quantity: 5, discount: None },
+                TicketLine { product_id: 2, quantity: 5, discount: None },
+            ],
+            ..ticket
+        };
+        assert!(matches!(
+            record_ticket(&conn, &over),
+            Err(AppError::InsufficientStock { available: 9, requested: 10 })
+        ));
+    }
+
+    #[test]
+    fn customer_history_groups_tickets() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        seed_product(&conn, 1, "Whey", 10000);
+        seed_product(&conn, 2, "Creatina", 5000);
+        seed_stock(&conn, 1, 10);
+        seed_stock(&conn, 2, 10);
+        let customer = Customer {
+            id: None,
+            name: "  Ana Pérez ".to_string(),
+            document_id: Some("45678912".to_string()),
+            phone: Some("".to_string()),
+            email: None,
+            notes: None,
+        };
+        let customer_id = insert_customer(&conn, &customer).unwrap() as i32;
+        assert!(matches!(insert_customer(&conn, &customer), Err(AppError::Conflict { .. })));
+        // Sin documento no hay conflicto
+        let walk_in = Customer { document_id: Some(" ".to_string()), ..customer };
+        insert_customer(&conn, &walk_in).unwrap();
+        insert_customer(&conn, &walk_in).unwrap();
+
+        let ticket = NewTicket {
+            lines: vec![
+                TicketLine { product_id: 1, quantity: 1, discount: None },
+                TicketLine { product_id: 2, quantity: 2, discount: None },
+            ],
+            customer_id: Some(customer_id),
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: Some("Tienda".to_string()),
+            sale_date: "2024-05-01T10:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        record_ticket(&conn, &ticket).unwrap();
+        let mut sale = test_sale(2, 1, None);
+        sale.created_by = Some(user);
+        sale.customer_id = Some(customer_id);
+        sale.sale_date = "2024-05-20T10:00:00Z".to_string();
+        record_sale(&conn, &sale).unwrap();
+        sale.product_id = 1;
+        sale.customer_id = None;
+        record_sale(&conn, &sale).unwrap();
+
+        let history = customer_history(&conn, customer_id).unwrap();
+        assert_eq!(history.customer.name, "Ana Pérez");
+        assert_eq!(history.customer.phone, None);
+        assert_eq!(history.ticket_count, 2);
+        assert_eq!(history.tickets[0].total, Money::from_cents(5000));
+        assert_eq!(history.tickets[1].lines.len(), 2);
+        assert_eq!(history.tickets[1].units, 3);
+        assert_eq!(history.lifetime_value, Money::from_cents(25000));
+        assert_eq!(history.last_visit.as_deref(), Some("2024-05-20T10:00:00Z"));
+
+        sale.customer_id = Some(999);
+        assert!(matches!(record_sale(&conn, &sale), Err(AppError::NotFound { resource: "customer" })));
+    }
+
+    #[test]
+    fn loyalty_points_accrue_redeem_and_expire() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, category) VALUES
+                 (1, 'Whey', 10000, 'Proteínas'), (2, 'Shaker', 2550, 'Accesorios');",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 10);
+        seed_stock(&conn, 2, 10);
+        seed_customer(&conn, 1, "Ana");
+        conn.execute_batch(
+            "INSERT INTO loyalty_rules (category, points_per_unit) VALUES ('proteínas', 2);
+             INSERT INTO settings (key, value) VALUES ('loyalty_point_value', '10'), ('loyalty_expiry_days', '30');",
+        )
+        .unwrap();
+
+        let mut ticket = NewTicket {
+            lines: vec![
+                TicketLine { product_id: 1, quantity: 1, discount: None },
+                TicketLine { product_id: 2, quantity: 1, discount: None },
+            ],
+            customer_id: Some(1),
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-06-01T10:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        // 100 x 2 (categoría) + floor(25.50 x 1) (general) = 225
+        record_ticket(&conn, &ticket).unwrap();
+        assert_eq!(loyalty_balance(&conn, 1, "2024-06-02").unwrap(), 225);
+
+        // Canjear más puntos de los que hay falla sin dejar rastro
+        ticket.redeem_points = Some(500);
+        let failed = in_transaction(&conn, || record_ticket(&conn, &ticket));
+        assert!(matches!(failed, Err(AppError::Validation { .. })));
+        assert_eq!(loyalty_balance(&conn, 1, "2024-06-02").unwrap(), 225);
+        let tickets: i64 = conn.query_row("SELECT COUNT(*) FROM tickets", [], |r| r.get(0)).unwrap();
+        assert_eq!(tickets, 1);
+
+        // 200 puntos x 0.10 = 20.00 de descuento repartido entre las líneas
+        ticket.redeem_points = Some(200);
+        ticket.sale_date = "2024-06-10T10:00:00Z".to_string();
+        let (_, ids) = in_transaction(&conn, || record_ticket(&conn, &ticket)).unwrap();
+        let points_total: Money = ids
+            .iter()
+            .map(|id| conn.query_row("SELECT points_amount FROM sales WHERE id=?1", [id], |r| r.get::<_, Money>(0)).unwrap())
+            .sum();
+        assert_eq!(points_total, Money::from_cents(2000));
+        // Nuevos puntos sobre el neto pagado: 84.06 x 2 + floor(21.44) = 189
+        assert_eq!(loyalty_balance(&conn, 1, "2024-06-11").unwrap(), 225 - 200 + 189);
+
+        // Los 25 que quedan del primer ticket vencen el 1 de julio
+        assert_eq!(loyalty_balance(&conn, 1, "2024-07-02").unwrap(), 189);
+        let expired: i64 = conn
+            .query_row("SELECT SUM(points) FROM loyalty_ledger WHERE entry_type='vencimiento'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(expired, -25);
+    }
+
+    #[test]
+    fn credit_sales_payments_and_aging() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        seed_product(&conn, 1, "Whey", 10000);
+        seed_stock(&conn, 1, 10);
+        seed_customer(&conn, 1, "Coach Luis");
+        let mut sale = test_sale(1, 1, None);
+        sale.created_by = Some(user);
+        sale.customer_id = Some(1);
+        sale.on_account = true;
+        for date in ["2024-03-01T10:00:00Z", "2024-04-20T10:00:00Z", "2024-05-25T10:00:00Z"] {
+            sale.sale_date = date.to_string();
+            record_sale(&conn, &sale).unwrap();
+        }
+        sale.customer_id = None;
+        assert!(matches!(record_sale(&conn, &sale), Err(AppError::Validation { .. })));
+
+        // Las ventas al crédito no entran en caja hasta que se cobran
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        assert_eq!(summary.sales_on_account, Money::from_cents(30000));
+        assert_eq!(summary.total_income, Money::ZERO);
+
+        let aging = receivables_aging(&conn, "2024-05-31").unwrap();
+        assert_eq!(aging.len(), 1);
+        assert_eq!(aging[0].days_0_30, Money::from_cents(10000));
+        assert_eq!(aging[0].days_31_60, Money::from_cents(10000));
+        assert_eq!(aging[0].days_over_60, Money::from_cents(10000));
+
+        // El cobro se aplica del ticket más antiguo al más reciente
+        let payment = CustomerPayment {
+            id: None,
+            customer_id: 1,
+            amount: Money::from_cents(15000),
+            payment_date: "2024-05-31".to_string(),
+            method: None,
+            note: None,
+            ticket_id: None,
+            created_by: Some(user),
+        };
+        record_customer_payment(&conn, &payment).unwrap();
+        let aging = receivables_aging(&conn, "2024-05-31").unwrap();
+        assert_eq!(aging[0].days_over_60, Money::ZERO);
+        assert_eq!(aging[0].days_31_60, Money::from_cents(5000));
+        assert_eq!(aging[0].total, Money::from_cents(15000));
+
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        assert_eq!(summary.total_income, Money::from_cents(15000));
+
+        let too_much = CustomerPayment { amount: Money::from_cents(15001), ..payment };
+        assert!(matches!(record_customer_payment(&conn, &too_much), Err(AppError::Validation { .. })));
+    }
+
+    #[test]
+    fn split_tender_with_change_and_partial_credit() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        seed_product(&conn, 1, "Whey", 10000);
+        seed_stock(&conn, 1, 10);
+        seed_customer(&conn, 1, "Coach Luis");
+        let tender = |method: &str, cents: i64| Tender {
+            method: method.to_string(),
+            amount: Money::from_cents(cents),
+            reference: None,
+        };
+
+        // 100.00 = 30.00 Yape + 80.00 en billete (vuelto 10.00)
+        let mut sale = test_sale(1, 1, None);
+        sale.created_by = Some(user);
+        sale.payments = vec![tender("billetera", 3000), tender("efectivo", 8000)];
+        record_sale(&conn, &sale).unwrap();
+        let (change, cash): (Money, Money) = conn
+            .query_row(
+                "SELECT change_given, amount FROM ticket_payments WHERE method='efectivo'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(change, Money::from_cents(1000));
+        assert_eq!(cash, Money::from_cents(7000));
+
+        // Mitad con tarjeta y mitad al crédito
+        sale.customer_id = Some(1);
+        sale.payments = vec![tender("tarjeta", 5000), tender("credito", 5000)];
+        record_sale(&conn, &sale).unwrap();
+        assert_eq!(open_receivables(&conn, Some(1), "now").unwrap()[0].balance, Money::from_cents(5000));
+
+        // Falta dinero, o la tarjeta se pasa del total
+        sale.payments = vec![tender("tarjeta", 9000)];
+        assert!(matches!(record_sale(&conn, &sale), Err(AppError::Validation { .. })));
+        sale.payments = vec![tender("tarjeta", 11000)];
+        assert!(matches!(record_sale(&conn, &sale), Err(AppError::Validation { .. })));
+
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        let by = |method: &str| summary.by_method.iter().find(|m| m.method == method).unwrap().net;
+        assert_eq!(by("efectivo"), Money::from_cents(7000));
+        assert_eq!(by("billetera"), Money::from_cents(3000));
+        assert_eq!(by("tarjeta"), Money::from_cents(5000));
+        assert_eq!(summary.cash_in_drawer, Money::from_cents(7000));
+        assert_eq!(summary.sales_on_account, Money::from_cents(5000));
+        assert_eq!(summary.total_income, Money::from_cents(15000));
+    }
+
+    #[test]
+    fn cash_session_close_computes_variance() {
+        let conn = test_db();
+        seed_product(&conn, 1, "Whey", 10000);
+        seed_stock(&conn, 1, 10);
+        let admin: i32 = conn.query_row("SELECT id FROM users LIMIT 1", [], |r| r.get(0)).unwrap();
+
+        let session_id = open_session(&conn, "Caja 1", Money::from_cents(5000), Some(admin)).unwrap();
+        assert!(matches!(
+            open_session(&conn, "Caja 1", Money::ZERO, None),
+            Err(AppError::Conflict { .. })
+        ));
+
+        // Una venta en efectivo, otra con tarjeta y un egreso de caja del turno
+        let mut sale = test_sale(1, 1, None);
+        sale.created_by = Some(admin);
+        record_sale(&conn, &sale).unwrap();
+        sale.payments = vec![Tender { method: "tarjeta".to_string(), amount: Money::from_cents(10000), reference: None }];
+        record_sale(&conn, &sale).unwrap();
+        conn.execute(
+            "INSERT INTO cash_movements (movement_type, amount, movement_date, session_id) VALUES ('egreso', 1500, '2024-03-01', ?1)",
+            [session_id],
+        )
+        .unwrap();
+
+        // Esperado: 50 + 100 - 15 = 135; contado: 100 + 20 + 10 + 4 x 1 = 134
+        let counts = vec![
+            DenominationCount { denomination: Money::from_cents(10000), quantity: 1 },
+            DenominationCount { denomination: Money::from_cents(2000), quantity: 1 },
+            DenominationCount { denomination: Money::from_cents(1000), quantity: 1 },
+            DenominationCount { denomination: Money::from_cents(100), quantity: 4 },
+        ];
+        let closed = close_session(&conn, session_id, &counts, Some(admin), None).unwrap();
+        assert_eq!(closed.expected_cash, Some(Money::from_cents(13500)));
+        assert_eq!(closed.counted_cash, Some(Money::from_cents(13400)));
+        assert_eq!(closed.variance, Some(Money::from_cents(-100)));
+        assert!(close_session(&conn, session_id, &counts, None, None).is_err());
+
+        let report = z_report(&conn, session_id).unwrap();
+        assert_eq!(report.tickets, 2);
+        assert_eq!(report.sales_net, Money::from_cents(20000));
+        let card = report.by_method.iter().find(|m| m.method == "tarjeta").unwrap();
+        assert_eq!(card.sales, Money::from_cents(10000));
+        assert_eq!(report.counts.len(), 4);
+
+        // Tras el cierre el usuario no tiene turno: no se vende sin caja abierta
+        assert!(matches!(record_sale(&conn, &sale), Err(AppError::Validation { .. })));
+        let tickets: i64 = conn.query_row("SELECT COUNT(*) FROM tickets", [], |r| r.get(0)).unwrap();
+        assert_eq!(tickets, 2);
+    }
+
+    #[test]
+    fn sales_without_open_session_are_rejected() {
+        let conn = test_db();
+        seed_product(&conn, 1, "Whey", 10000);
+        seed_stock(&conn, 1, 10);
+        let admin: i32 = conn.query_row("SELECT id FROM users LIMIT 1", [], |r| r.get(0)).unwrap();
+        let ticket = NewTicket {
+            lines: vec![TicketLine { product_id: 1, quantity: 1, discount: None }],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(admin),
+        };
+        assert!(matches!(in_transaction(&conn, || record_ticket(&conn, &ticket)), Err(AppError::Validation { .. })));
+        let anonymous = NewTicket { created_by: None, ..ticket };
+        assert!(matches!(in_transaction(&conn, || record_ticket(&conn, &anonymous)), Err(AppError::Validation { .. })));
+        let tickets: i64 = conn.query_row("SELECT COUNT(*) FROM tickets", [], |r| r.get(0)).unwrap();
+        assert_eq!(tickets, 0);
+        assert_eq!(product_stock(&conn, 1).unwrap(), 10);
+    }
+
+    #[test]
+    fn cash_summary_is_exact_over_many_sales() {
+        let conn = test_db();
+
+        seed_product(&conn, 1, "Whey", 1999);
+        let unit = Money::from_cents(1999);
+        let line_total = unit.times(3) - unit.times(3).percent(15.0);
+        for _ in 0..5_000 {
+            conn.execute(
+                "INSERT INTO sales (product_id, quantity, sale_price, discount, sale_date) VALUES (1, 3, ?1, 15, '2024-01-01')",
+                [line_total],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO cash_movements (movement_type, amount, movement_date) VALUES ('egreso', ?1, '2024-01-01')",
+            [Money::from_cents(7)],
+        )
+        .unwrap();
+
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        // 3 x 19.99 = 59.97; 15 % = 8.9955 -> 9.00; 50.97 x 5000 = 254850.00
+        assert_eq!(line_total, Money::from_cents(5097));
+        assert_eq!(summary.total_income.to_string(), "254850.00");
+        assert_eq!(summary.balance.to_string(), "254849.93");
+    }
+
+    #[test]
+    fn cash_summary_filters_buckets_and_compares_periods() {
+        let conn = test_db();
+        seed_product(&conn, 1, "Whey", 1000);
+        for (price, date) in [(1000, "2024-02-10T10:00:00Z"), (1000, "2024-03-04T10:00:00Z"), (2000, "2024-03-06T18:00:00Z")] {
+            conn.execute(
+                "INSERT INTO sales (product_id, quantity, sale_price, sale_date) VALUES (1, 1, ?1, ?2)",
+                rusqlite::params![price, date],
+            )
+            .unwrap();
+        }
+        for (kind, amount, category, date) in [
+            ("egreso", 300, "Alquiler", "2024-02-01"),
+            ("egreso", 500, "Alquiler", "2024-03-01"),
+            ("egreso", 200, "Servicios", "2024-03-11"),
+            ("ingreso", 100, "Otros", "2024-03-11"),
+        ] {
+            conn.execute(
+                "INSERT INTO cash_movements (movement_type, amount, category, movement_date) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![kind, amount, category, date],
+            )
+            .unwrap();
+        }
+
+        let march = CashFilter {
+            start_date: Some("2024-03-01".to_string()),
+            end_date: Some("2024-03-31".to_string()),
+            ..CashFilter::default()
+        };
+        let summary = cash_summary(&conn, &march, Some("semana")).unwrap();
+        assert_eq!(summary.total_income, Money::from_cents(3100));
+        assert_eq!(summary.total_expense, Money::from_cents(700));
+        let weeks: Vec<(&str, i64)> = summary
+            .buckets
+            .iter()
+            .map(|b| (b.period_start.as_str(), b.balance.cents()))
+            .collect();
+        // 2024-03-01 es viernes: su semana empieza el lunes 26 de febrero
+        assert_eq!(weeks, vec![("2024-02-26", -500), ("2024-03-04", 3000), ("2024-03-11", -100)]);
+
+        // Mes completo: se compara contra febrero entero
+        let previous = summary.previous.unwrap();
+        assert_eq!((previous.start_date.as_str(), previous.end_date.as_str()), ("2024-02-01", "2024-02-29"));
+        assert_eq!(previous.balance, Money::from_cents(700));
+        assert_eq!(previous.balance_change, Money::from_cents(1700));
+        assert_eq!(previous.balance_change_pct, Some(1700.0 * 100.0 / 700.0));
+
+        // Rango de días: el mismo número de días justo antes
+        let (start, end) = previous_period(&conn, "2024-03-04", "2024-03-10").unwrap();
+        assert_eq!((start.as_str(), end.as_str()), ("2024-02-26", "2024-03-03"));
+
+        // Filtro de categorías: sin "Ventas" solo cuentan los movimientos
+        let rent = CashFilter {
+            categories: Some(vec!["Alquiler".to_string()]),
+            ..march.clone()
+        };
+        let summary = cash_summary(&conn, &rent, Some("mes")).unwrap();
+        assert_eq!(summary.total_income, Money::ZERO);
+        assert_eq!(summary.total_expense, Money::from_cents(500));
+        assert_eq!(summary.buckets.len(), 1);
+        assert_eq!(summary.buckets[0].period_start, "2024-03-01");
+
+        assert!(cash_summary(&conn, &march, Some("anio")).is_err());
+    }
+
+    #[test]
+    fn recurring_expenses_generate_pending_entries_and_roll_up_by_category() {
+        let conn = test_db();
+        let services: i32 = conn
+            .query_row("SELECT id FROM expense_categories WHERE name='Servicios'", [], |row| row.get(0))
+            .unwrap();
+        let power = insert_expense_category(&conn, "Luz del local", Some(services)).unwrap() as i32;
+
+        let rent = RecurringExpense {
+            id: None,
+            name: "Alquiler del local".to_string(),
+            amount: Money::from_cents(150000),
+            category_id: None,
+            description: None,
+            frequency: "mensual".to_string(),
+            every: None,
+            start_date: "2024-01-31".to_string(),
+            end_date: None,
+            next_date: None,
+            payment_method: None,
+            active: true,
+            created_by: None,
+        };
+        insert_recurring_expense(&conn, &rent).unwrap();
+        let light = RecurringExpense {
+            name: "Luz".to_string(),
+            amount: Money::from_cents(8000),
+            category_id: Some(power),
+            frequency: "semanal".to_string(),
+            every: Some(2),
+            start_date: "2024-02-05".to_string(),
+            end_date: Some("2024-03-01".to_string()),
+            ..rent
+        };
+        insert_recurring_expense(&conn, &light).unwrap();
+
+        // La fecha se toma tal como viene, sin pasarla a hora local: a última
+        // hora del 30 todavía no vence el alquiler del 31
+        assert_eq!(generate_recurring_expenses(&conn, "2024-03-30 23:30:00").unwrap(), 2 + 2);
+        assert_eq!(generate_recurring_expenses(&conn, "2024-03-31T23:59:59Z").unwrap(), 1);
+        // Ya generados: una segunda pasada no duplica
+        assert_eq!(generate_recurring_expenses(&conn, "2024-04-15").unwrap(), 0);
+        let dates: Vec<String> = conn
+            .prepare("SELECT movement_date FROM cash_movements WHERE status='pendiente' ORDER BY movement_date")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // El día 31 se ajusta al último día de cada mes
+        assert_eq!(dates, vec!["2024-01-31", "2024-02-05", "2024-02-19", "2024-02-29", "2024-03-31"]);
+
+        // Los pendientes no cuentan en caja hasta confirmarse
+        assert_eq!(cash_summary(&conn, &CashFilter::default(), None).unwrap().total_expense, Money::ZERO);
+        conn.execute("UPDATE cash_movements SET status='confirmado' WHERE category_id=?1", [power]).unwrap();
+        assert_eq!(cash_summary(&conn, &CashFilter::default(), None).unwrap().total_expense, Money::from_cents(16000));
+
+        let report = expenses_by_category(&conn, Some("2024-02-01"), Some("2024-02-29")).unwrap();
+        let row = |name: &str| report.iter().find(|r| r.name == name).unwrap();
+        assert_eq!(row("Servicios > Luz del local").own_total, Money::from_cents(16000));
+        assert_eq!(row("Servicios").own_total, Money::ZERO);
+        assert_eq!(row("Servicios").total, Money::from_cents(16000));
+        // La plantilla sin categoría queda agrupada por su texto
+        let uncategorized = report.iter().find(|r| r.category_id.is_none()).unwrap();
+        assert_eq!((uncategorized.name.as_str(), uncategorized.pending), ("Sin categoría", Money::from_cents(150000)));
+    }
+
+    #[test]
+    fn amounts_that_overflow_are_rejected() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        let huge = i64::MAX / 2;
+        seed_product(&conn, 1, "Whey", huge);
+        seed_stock(&conn, 1, 10);
+        let purchase = Purchase {
+            id: None,
+            product_id: 1,
+            supplier: None,
+            purchase_price: Some(Money::from_cents(huge)),
+            purchase_date: None,
+            discount: None,
+            expected_replenish_days: None,
+            quantity: Some(3),
+            created_by: None,
+            location_id: None,
+        };
+        assert!(matches!(record_purchase(&conn, &purchase), Err(AppError::Validation { .. })));
+
+        let mut ticket = NewTicket {
+            lines: vec![TicketLine { product_id: 1, quantity: 3, discount: None }],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        assert!(matches!(record_ticket(&conn, &ticket), Err(AppError::Validation { .. })));
+        // Cada línea entra, pero el total del ticket no
+        ticket.lines = (0..3).map(|_| TicketLine { product_id: 1, quantity: 1, discount: None }).collect();
+        assert!(matches!(record_ticket(&conn, &ticket), Err(AppError::Validation { .. })));
+        set_setting(&conn, "loyalty_point_value", "10").unwrap();
+        assert!(matches!(loyalty_points_value(&conn, i64::MAX), Err(AppError::Validation { .. })));
+    }
+
+    #[test]
+    fn purchases_update_cost_and_sales_capture_cogs() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        conn.execute("INSERT INTO products (id, name, sale_price, category, brand) VALUES (1, 'Whey', 1500, 'Proteínas', 'Optimum')", [])
+            .unwrap();
+        let receive = |price: i64, quantity: i32, discount: i64| {
+            let purchase = Purchase {
+                id: None,
+                product_id: 1,
+                supplier: Some("Distribuidora".to_string()),
+                purchase_price: Some(Money::from_cents(price)),
+                purchase_date: Some("2024-03-01".to_string()),
+                discount: Some(Money::from_cents(discount)),
+                expected_replenish_days: None,
+                quantity: Some(quantity),
+                created_by: None,
+                location_id: None,
+            };
+            record_purchase(&conn, &purchase).unwrap();
+        };
+        let sell = |quantity: i32| {
+            let ticket = NewTicket {
+                lines: vec![TicketLine { product_id: 1, quantity, discount: None }],
+                customer_id: None,
+                redeem_points: None,
+                on_account: false,
+                payments: Vec::new(),
+                session_id: None,
+                location_id: None,
+                channel: None,
+                sale_date: "2024-03-10T12:00:00Z".to_string(),
+                created_by: Some(user),
+            };
+            let (_, ids) = record_ticket(&conn, &ticket).unwrap();
+            conn.query_row("SELECT cost_amount FROM sales WHERE id=?1", [ids[0]], |row| row.get::<_, Money>(0))
+                .unwrap()
+        };
+        let average = || {
+            conn.query_row("SELECT average_cost FROM products WHERE id=1", [], |row| row.get::<_, Money>(0))
+                .unwrap()
+        };
+
+        receive(1000, 10, 0);
+        // 10 x 13.50 - 5.00 de descuento = 130.00
+        receive(1350, 10, 500);
+        assert_eq!(average(), Money::from_cents(1150));
+
+        // Promedio ponderado
+        assert_eq!(sell(12), Money::from_cents(12 * 1150));
+        assert_eq!(average(), Money::from_cents(1150));
+
+        // FIFO: la primera capa ya se consumió, quedan 8 a 13.00
+        set_setting(&conn, "costing_method", "fifo").unwrap();
+        assert_eq!(sell(5), Money::from_cents(5 * 1300));
+        let layer_left: (i64, Money) = conn
+            .query_row("SELECT SUM(remaining), SUM(remaining_cost) FROM cost_layers", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(layer_left, (3, Money::from_cents(3900)));
+
+        // Venta anterior al costeo: cuenta en ingresos pero no en el margen
+        conn.execute(
+            "INSERT INTO sales (product_id, quantity, sale_price, sale_date) VALUES (1, 1, 1500, '2024-03-11')",
+            [],
+        )
+        .unwrap();
+        let report = margin_report(&conn, "marca", None, None).unwrap();
+        assert_eq!(report.len(), 1);
+        let row = &report[0];
+        assert_eq!((row.label.as_str(), row.units, row.uncosted_units), ("Optimum", 18, 1));
+        assert_eq!(row.revenue, Money::from_cents(18 * 1500));
+        assert_eq!(row.cogs, Money::from_cents(13800 + 6500));
+        assert_eq!(row.gross_margin, Money::from_cents(17 * 1500 - 20300));
+        assert_eq!(row.margin_pct, Some(5200.0 * 100.0 / 25500.0));
+
+        let by_month = margin_report(&conn, "mes", None, None).unwrap();
+        assert_eq!(by_month[0].key, "2024-03-01");
+        assert!(margin_report(&conn, "proveedor", None, None).is_err());
+    }
+
+    #[test]
+    fn inventory_valuation_now_and_as_of_a_past_date() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, category, location, average_cost) VALUES
+                 (1, 'Whey', 2000, 'Proteínas', 'A1', 1000),
+                 (2, 'Creatina', 1500, 'Suplementos', 'A1', 800),
+                 (3, 'Shaker', 500, NULL, NULL, NULL);
+             INSERT INTO locations (id, name, kind, branch) VALUES (10, 'A1', 'almacen', 'Principal');
+             INSERT INTO stock_movements (product_id, type, quantity, cost_amount, created_at, location_id) VALUES
+                 (1, 'ingreso', 10, 10000, '2024-01-05 10:00:00', 10),
+                 (1, 'egreso', 4, 4000, '2024-02-10 18:30:00', 10),
+                 (2, 'ingreso', 5, NULL, '2024-01-20 09:00:00', 10),
+                 (3, 'ingreso', 2, 0, '2024-03-01 09:00:00', NULL);
+             INSERT INTO price_history (product_id, old_price, new_price, source, changed_at) VALUES
+                 (1, 1800, 2000, 'edicion', '2024-02-01 12:00:00');",
+        )
+        .unwrap();
+
+        let now = inventory_valuation(&conn, "producto", None).unwrap();
+        let whey = now.rows.iter().find(|r| r.key == "1").unwrap();
+        assert_eq!((whey.units, whey.cost_value, whey.retail_value), (6, Money::from_cents(6000), Money::from_cents(12000)));
+        // Movimiento sin costo: al costo promedio
+        let creatine = now.rows.iter().find(|r| r.key == "2").unwrap();
+        assert_eq!(creatine.cost_value, Money::from_cents(4000));
+        assert_eq!(now.total_units, 13);
+        assert_eq!(now.total_retail, Money::from_cents(12000 + 7500 + 1000));
+
+        // Al 31 de enero: antes de la venta y del cambio de precio
+        let january = inventory_valuation(&conn, "ubicacion", Some("2024-01-31")).unwrap();
+        assert_eq!(january.rows.len(), 1);
+        let a1 = &january.rows[0];
+        assert_eq!((a1.label.as_str(), a1.units), ("A1", 15));
+        assert_eq!(a1.cost_value, Money::from_cents(10000 + 4000));
+        assert_eq!(a1.retail_value, Money::from_cents(10 * 1800 + 5 * 1500));
+
+        // Una hora exacta deja fuera la venta de esa tarde
+        let by_category = inventory_valuation(&conn, "categoria", Some("2024-02-10 12:00:00")).unwrap();
+        let protein = by_category.rows.iter().find(|r| r.label == "Proteínas").unwrap();
+        assert_eq!((protein.units, protein.retail_value), (10, Money::from_cents(20000)));
+
+        assert!(inventory_valuation(&conn, "marca", None).is_err());
+        assert!(inventory_valuation(&conn, "producto", Some("ayer")).is_err());
+    }
+
+    #[test]
+    fn stock_as_of_and_stock_card_with_running_balance() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, average_cost) VALUES (1, 'Whey', 2000, 1000), (2, 'Creatina', 1500, 500);
+             INSERT INTO stock_movements (product_id, type, quantity, cost_amount, note, created_at) VALUES
+                 (1, 'ingreso', 10, 10000, 'Compra', '2024-02-20 10:00:00'),
+                 (1, 'egreso', 3, 3000, NULL, '2024-03-02 11:00:00'),
+                 (1, 'ingreso', 5, 6000, 'Compra', '2024-03-15 09:00:00'),
+                 (1, 'egreso', 4, NULL, 'Merma', '2024-03-31 20:00:00'),
+                 (1, 'egreso', 1, 1100, NULL, '2024-04-01 08:00:00'),
+                 (2, 'ingreso', 7, 3500, NULL, '2024-03-10 10:00:00');",
+        )
+        .unwrap();
+
+        let at = |as_of: Option<&str>| -> Vec<(i32, i64)> {
+            stock_balances(&conn, as_of).unwrap().iter().map(|b| (b.product_id, b.current_stock)).collect()
+        };
+        assert_eq!(at(None), vec![(1, 7), (2, 7)]);
+        assert_eq!(at(Some("2024-03-01")), vec![(1, 10)]);
+        assert_eq!(at(Some("2024-03-15 08:59:59")), vec![(1, 7), (2, 7)]);
+        assert!(stock_balances(&conn, Some("31/03/2024")).is_err());
+
+        // Marzo: saldo inicial de febrero y cierre antes del 1 de abril
+        let card = stock_card(&conn, 1, Some("2024-03-01"), Some("2024-03-31")).unwrap();
+        assert_eq!((card.opening_balance, card.opening_cost), (10, Money::from_cents(10000)));
+        let balances: Vec<(i64, i64, i64)> = card.entries.iter().map(|e| (e.quantity_in, e.quantity_out, e.balance)).collect();
+        assert_eq!(balances, vec![(0, 3, 7), (5, 0, 12), (0, 4, 8)]);
+        // La merma sin costo registrado sale al costo promedio
+        assert_eq!(card.entries[2].cost_out, Money::from_cents(4000));
+        assert_eq!((card.closing_balance, card.closing_cost), (8, Money::from_cents(10000 - 3000 + 6000 - 4000)));
+
+        assert!(stock_card(&conn, 99, None, None).is_err());
+    }
+
+    #[test]
+    fn sales_without_location_only_use_the_default_location_stock() {
+        let conn = test_db();
+        let user = seed_session(&conn);
+        let shop = resolve_location(&conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, average_cost) VALUES (1, 'Whey', 2000, 1000), (2, 'Creatina', 1500, 800);
+             INSERT INTO locations (id, name, kind, branch) VALUES (20, 'Depósito', 'almacen', 'Principal'), (30, 'Sucursal Norte', 'tienda', 'Norte');
+             INSERT INTO stock_movements (product_id, type, quantity, location_id) VALUES (1, 'ingreso', 5, 30), (2, 'ingreso', 5, 20);",
+        )
+        .unwrap();
+        // La creatina queda en tránsito hacia la otra sucursal
+        let transfer = NewTransfer { product_id: 2, quantity: 5, from_location_id: 20, to_location_id: 30, note: None, created_by: None };
+        record_transfer(&conn, &transfer).unwrap();
+
+        let mut ticket = NewTicket {
+            lines: vec![TicketLine { product_id: 1, quantity: 1, discount: None }],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: Vec::new(),
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-03-10T12:00:00Z".to_string(),
+            created_by: Some(user),
+        };
+        for product_id in [1, 2] {
+            ticket.lines[0].product_id = product_id;
+            assert!(matches!(
+                record_ticket(&conn, &ticket),
+                Err(AppError::InsufficientStock { available: 0, requested: 1 })
+            ));
+        }
+        assert_eq!(location_stock(&conn, 1, shop).unwrap(), 0);
+
+        conn.execute("INSERT INTO stock_movements (product_id, type, quantity, location_id) VALUES (1, 'ingreso', 1, ?1)", [shop])
+            .unwrap();
+        ticket.lines[0].product_id = 1;
+        record_ticket(&conn, &ticket).unwrap();
+        assert_eq!(location_stock(&conn, 1, shop).unwrap(), 0);
+        assert_eq!(location_stock(&conn, 1, 30).unwrap(), 5);
+    }
+
+    #[test]
+    fn deleting_a_product_is_all_or_nothing() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO parent_products (id, name) VALUES (7, 'Whey');
+             INSERT INTO products (id, name, parent_id, image_path) VALUES (1, 'Whey', 7, 'media/whey.png');",
+        )
+        .unwrap();
+        insert_barcode(&conn, 1, "4006381333931", false).unwrap();
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+        };
+
+        // Si falla un paso no queda el producto a medio borrar
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER keep_parents BEFORE DELETE ON parent_products BEGIN SELECT RAISE(ABORT, 'no'); END;",
+        )
+        .unwrap();
+        assert!(in_transaction(&conn, || delete_product_row(&conn, 1)).is_err());
+        assert_eq!((count("products"), count("product_barcodes"), count("parent_products")), (1, 1, 1));
+
+        conn.execute_batch("DROP TRIGGER keep_parents;").unwrap();
+        let image = in_transaction(&conn, || delete_product_row(&conn, 1)).unwrap();
+        assert_eq!(image.as_deref(), Some("media/whey.png"));
+        assert_eq!((count("products"), count("product_barcodes"), count("parent_products")), (0, 0, 0));
+        assert!(matches!(delete_product_row(&conn, 1), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn labels_use_product_barcodes() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, sku, name, flavor, weight, sale_price) VALUES
+                 (1, 'WHEY-1', 'Whey', 'Chocolate', '2 lb', 4500),
+                 (2, 'SHAKER', 'Shaker', NULL, NULL, 800);",
+        )
+        .unwrap();
+        insert_barcode(&conn, 1, "ABC-1", false).unwrap();
+        insert_barcode(&conn, 1, "4006381333931", false).unwrap();
+
+        let items = product_labels(&conn, &[2, 1], 2).unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].barcode.as_deref(), Some("SHAKER"));
+        assert_eq!(items[0].detail, None);
+        assert_eq!(items[0].price, "$8.00");
+        assert_eq!(items[2].barcode.as_deref(), Some("4006381333931"));
+        assert_eq!(items[2].detail.as_deref(), Some("Chocolate · 2 lb"));
+        assert!(matches!(product_labels(&conn, &[9], 1), Err(AppError::NotFound { .. })));
+
+        let template = labels::template("a4_3x8").unwrap();
+        assert_eq!(labels::layout(template, &items, labels::STYLE_SHELF).len(), 1);
+        let roll = labels::template("rollo_50x25").unwrap();
+        assert_eq!(labels::layout(roll, &items, labels::STYLE_PRICE).len(), 4);
+    }
+
+    #[test]
+    fn receipt_from_recorded_ticket() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO users (id, username, password_hash, role, fullname) VALUES (5, 'ana', 'x', 'Vendedor', 'Ana Torres');
+             INSERT INTO products (id, name, flavor, sale_price) VALUES (1, 'Whey', 'Vainilla', 10000), (2, 'Shaker', '', 2500);",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 10);
+        seed_stock(&conn, 2, 10);
+        open_session(&conn, "Caja 1", Money::ZERO, Some(5)).unwrap();
+        set_setting(&conn, "store_name", "VitaSport Miraflores").unwrap();
+        set_setting(&conn, "store_tax_id", "20123456789").unwrap();
+        set_setting(&conn, "receipt_paper_width", "58").unwrap();
+
+        let ticket = NewTicket {
+            lines: vec![
+                TicketLine { product_id: 1, quantity: 2, discount: Some(10.0) },
+                TicketLine { product_id: 2, quantity: 1, discount: None },
+            ],
+            customer_id: None,
+            redeem_points: None,
+            on_account: false,
+            payments: vec![
+                Tender { method: "tarjeta".to_string(), amount: Money::from_cents(15000), reference: Some("4242".to_string()) },
+                Tender { method: "efectivo".to_string(), amount: Money::from_cents(10000), reference: None },
+            ],
+            session_id: None,
+            location_id: None,
+            channel: None,
+            sale_date: "2024-03-10T12:30:00Z".to_string(),
+            created_by: Some(5),
+        };
+        let (ticket_id, _) = record_ticket(&conn, &ticket).unwrap();
+
+        let receipt = receipt_for_ticket(&conn, ticket_id as i32).unwrap();
+        assert_eq!(receipt.date, "2024-03-10 12:30");
+        assert_eq!(receipt.cashier.as_deref(), Some("Ana Torres"));
+        assert_eq!(receipt.lines[0].name, "Whey Vainilla");
+        assert_eq!(receipt.lines[1].name, "Shaker");
+
+        let paper = receipt_paper(&conn, None).unwrap();
+        assert_eq!(paper.columns, 32);
+        let text = receipt::to_text(&receipt, paper);
+        let lines: Vec<&str> = text.lines().collect();
+        // 2 x 100.00 con 10% = 180.00, más 25.00; vuelto de 250.00 - 205.00
+        for expected in [
+            "  VitaSport Miraflores",
+            "  2 x 100.00              200.00",
+            "  Descuento               -20.00",
+            "TOTAL                     205.00",
+            "Tarjeta (4242)            150.00",
+            "Efectivo                  100.00",
+            "Vuelto                     45.00",
+        ] {
+            assert!(lines.iter().any(|l| l.trim_start() == expected.trim_start()), "{}", expected);
+        }
+        assert!(matches!(receipt_paper(&conn, Some(76)), Err(AppError::Validation { .. })));
+        assert!(matches!(receipt_for_ticket(&conn, 999), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn receipts_never_overwrite_regular_files() {
+        let file = std::env::temp_dir().join(format!("vitasport_ticket_{}.prn", std::process::id()));
+        fs::write(&file, "no tocar").unwrap();
+        let file_path = file.to_string_lossy().to_string();
+        let some = |s: &str| Some(s.to_string());
+
+        // Un archivo común pedido desde la vista o configurado va a Descargas
+        assert_eq!(printer_target(Some(file_path.clone()), None), None);
+        assert_eq!(printer_target(None, Some(file_path.clone())), None);
+        assert_eq!(printer_target(Some(file_path.clone()), Some(file_path.clone())), None);
+        // Una ruta cualquiera pedida desde la vista tampoco se crea
+        assert_eq!(printer_target(some("/tmp/vitasport_no_existe.prn"), None), None);
+        // La impresora configurada se usa aunque no se pueda consultar
+        assert_eq!(printer_target(None, some(r"\\caja\termica")), Some(PathBuf::from(r"\\caja\termica")));
+        assert_eq!(printer_target(some("  "), some("COM3")), Some(PathBuf::from("COM3")));
+        if cfg!(unix) {
+            assert_eq!(printer_target(some("/dev/null"), None), Some(PathBuf::from("/dev/null")));
+        }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "no tocar");
+        let _ = fs::remove_file(&file);
+    }
+
+    #[test]
+    fn reports_export_typed_sheets_with_totals() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, sku, name, sale_price, expiry_date, average_cost) VALUES
+                 (1, 'W1', 'Whey', 2000, '2025-06-30', 1000),
+                 (2, 'C1', 'Creatina', 1500, NULL, 800);
+             INSERT INTO stock_movements (product_id, type, quantity, cost_amount, created_at) VALUES
+                 (1, 'ingreso', 10, 10000, '2024-01-05 10:00:00'),
+                 (2, 'ingreso', 4, 3200, '2024-01-05 10:00:00');
+             INSERT INTO sales (product_id, quantity, unit_price, gross_amount, discount, discount_amount, sale_price, channel, sale_date) VALUES
+                 (1, 2, 2000, 4000, 10, 400, 3600, 'tienda', '2024-02-01 10:00:00'),
+                 (2, 1, 1500, 1500, NULL, 0, 1500, NULL, '2024-02-02 11:30:00');",
+        )
+        .unwrap();
+
+        let sales = sales_sheet(&conn, None, None).unwrap();
+        let total = sales.total_row().unwrap();
+        assert_eq!(total[2], report::Cell::Int(3));
+        assert_eq!(total[7], report::Cell::Money(Money::from_cents(5100)));
+        assert_eq!(sales.rows[1][5], report::Cell::Number(10.0));
+        assert_eq!(sales.rows[1][9], report::Cell::Date("2024-02-01 10:00:00".into()));
+
+        let inventory = inventory_sheet(&conn).unwrap();
+        assert_eq!(inventory.rows[0][9], report::Cell::Date("2025-06-30".into()));
+        assert_eq!(inventory.rows[1][9], report::Cell::Empty);
+        assert_eq!(inventory.rows[0][15], report::Cell::Int(10));
+        // El inventario se reimporta tal cual: sin fila de totales
+        assert!(inventory.total_row().is_none());
+
+        // CSV con la fila de totales al final
+        let valuation = valuation_sheet(&inventory_valuation(&conn, "producto", None).unwrap());
+        assert_eq!(
+            report_csv(&valuation, csv::WriteOptions::default()),
+            "key,label,units,cost_value,retail_value,potential_margin\r\n\
+             2,Creatina,4,32.00,60.00,28.00\r\n\
+             1,Whey,10,100.00,200.00,100.00\r\n\
+             total,,14,132.00,260.00,128.00\r\n"
+        );
+
+        let card = stock_card_sheet(&stock_card(&conn, 1, Some("2024-01-01"), None).unwrap());
+        assert_eq!(card.rows[0][2], report::Cell::from("saldo_inicial"));
+        assert_eq!(card.total_row().unwrap()[4], report::Cell::Int(10));
+
+        assert!(report::to_xlsx(&[inventory, valuation, sales, card]).unwrap().starts_with(b"PK"));
+        assert_eq!(report_format(None).unwrap(), "csv");
+        assert!(report_format(Some("pdf")).is_err());
+    }
+
+    #[test]
+    fn csv_reports_quote_fields_and_reimport() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, sku, name, sale_price, category, flavor) VALUES
+                 (1, 'W5', 'Whey, 5lb', 8990, 'Proteínas', 'Chocolate \"Suizo\"'),
+                 (2, 'C1', 'Creatina; 300 g', 1550, 'Creatinas', NULL);",
+        )
+        .unwrap();
+        let inventory = inventory_sheet(&conn).unwrap();
+
+        let options = csv_options(&conn).unwrap();
+        assert_eq!(options, csv::WriteOptions::default());
+        let text = report_csv(&inventory, options);
+        assert!(text.contains("1,W5,\"Whey, 5lb\",89.90,,Proteínas,,\"Chocolate \"\"Suizo\"\"\","));
+
+        // Excel en español: `;`, decimales con coma y BOM
+        set_setting(&conn, "csv_delimiter", ";").unwrap();
+        set_setting(&conn, "csv_bom", "1").unwrap();
+        let options = csv_options(&conn).unwrap();
+        assert_eq!(options, csv::WriteOptions { delimiter: ';', bom: true });
+        let text = report_csv(&inventory, options);
+        assert!(text.starts_with("\u{feff}id;sku;name;sale_price;"));
+        assert!(text.contains("2;C1;\"Creatina; 300 g\";15,50;"));
+
+        // Lo exportado se vuelve a importar sin cambios
+        let report = import_products(&conn, &text, false, false).unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!((report.creates, report.updates), (0, 2));
+        let names: Vec<&str> = report.rows.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Whey, 5lb", "Creatina; 300 g"]);
+        let plan = plan_product_import(&conn, &text, false).unwrap();
+        assert_eq!(plan.rows[1].1.sale_price, Some(Money::from_cents(1550)));
+        assert_eq!(plan.rows[0].1.flavor.as_deref(), Some("Chocolate \"Suizo\""));
+
+        set_setting(&conn, "csv_delimiter", "|").unwrap();
+        assert_eq!(csv_options(&conn).unwrap().delimiter, ',');
+    }
+
+    #[test]
+    fn loyalty_points_pay_as_tender() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO products (id, name, sale_price, category) VALUES (1, 'Whey', 10000, 'Proteínas');",
+        )
+        .unwrap();
+        seed_stock(&conn, 1, 10);
+        seed_customer(&conn, 1, "Ana");
+        conn.execute_batch(
+            "INSERT INTO loyalty_rules (category, points_per_unit) VALUES (NULL, 1);
+             INSERT INTO loyalty_ledger (customer_id, entry_type, points, remaining, created_at)
+                 VALUES (1, 'acumulacion', 500, 500, '2024-06-01 10:00:00');
+             INSERT INTO settings (key, value) VALUES ('loyalty_point_value', '10');",
+        )
+        .unwrap();
+        let session_id = open_session(&conn, "Caja 1", Money::ZERO, None).unwrap();
+        let tender = |method: &str, cents: i64| Tender { method: method.to_string(), amount: Money::from_cents(cents), reference: None };
+        let mut ticket = NewTicket {
+            lines: vec![TicketLine { product_id: 1, quantity: 1, discount: None }],
+            customer_id: Some(1),
+            redeem_points: None,
+            on_account: false,
+            payments: vec![tender("puntos", 3000), tender("efectivo", 10000)],
+            session_id: Some(session_id as i32),
+            location_id: None,
+            channel: None,
+            sale_date: "2024-06-10T10:00:00Z".to_string(),
+            created_by: None,
+        };
+
+        // 30.00 con puntos (300 x 0.10) y el resto en efectivo, con vuelto
+        let (ticket_id, _) = in_transaction(&conn, || record_ticket(&conn, &ticket)).unwrap();
+        let payments: Vec<(String, Money, Money)> = conn
+            .prepare("SELECT method, amount, change_given FROM ticket_payments WHERE ticket_id=?1 ORDER BY id")
+            .unwrap()
+            .query_map([ticket_id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            payments,
+            vec![
+                ("puntos".to_string(), Money::from_cents(3000), Money::ZERO),
+                ("efectivo".to_string(), Money::from_cents(7000), Money::from_cents(3000)),
+            ]
+        );
+        let redeemed: i64 = conn
+            .query_row("SELECT points FROM loyalty_ledger WHERE entry_type='canje' AND ticket_id=?1", [ticket_id], |r| r.get(0))
+            .unwrap();
+        assert_eq!(redeemed, -300);
+        // La venta no pierde importe; los puntos nuevos son solo por lo pagado en efectivo
+        let net: Money = conn.query_row("SELECT sale_price FROM sales WHERE ticket_id=?1", [ticket_id], |r| r.get(0)).unwrap();
+        assert_eq!(net, Money::from_cents(10000));
+        assert_eq!(loyalty_balance(&conn, 1, "2024-06-11").unwrap(), 500 - 300 + 70);
+
+        // Sin puntos suficientes, o con un importe que no son puntos enteros,
+        // no se registra nada
+        ticket.payments = vec![tender("puntos", 5000), tender("efectivo", 5000)];
+        assert!(in_transaction(&conn, || record_ticket(&conn, &ticket)).is_err());
+        ticket.payments = vec![tender("puntos", 1005), tender("efectivo", 8995)];
+        assert!(in_transaction(&conn, || record_ticket(&conn, &ticket)).is_err());
+        let tickets: i64 = conn.query_row("SELECT COUNT(*) FROM tickets", [], |r| r.get(0)).unwrap();
+        assert_eq!(tickets, 1);
+
+        // Los puntos no son dinero de caja: en caja solo entran los 70.00 en efectivo
+        let summary = cash_summary(&conn, &CashFilter::default(), None).unwrap();
+        assert_eq!(summary.sales_net, Money::from_cents(10000));
+        assert_eq!(summary.sales_in_points, Money::from_cents(3000));
+        assert_eq!(summary.total_income, Money::from_cents(7000));
+        assert_eq!(summary.cash_in_drawer, Money::from_cents(7000));
+        assert!(summary.by_method.iter().all(|m| m.method != "puntos"));
+        close_session(&conn, session_id, &[], None, None).unwrap();
+        let report = z_report(&conn, session_id).unwrap();
+        assert_eq!(report.sales_net, Money::from_cents(10000));
+        assert_eq!(report.sales_in_points, Money::from_cents(3000));
+        assert!(report.by_method.iter().all(|m| m.method != "puntos"));
+        let cash = report.by_method.iter().find(|m| m.method == "efectivo").unwrap();
+        assert_eq!(cash.sales, Money::from_cents(7000));
+
+        let payment = CustomerPayment {
+            id: None,
+            customer_id: 1,
+            amount: Money::from_cents(100),
+            method: Some("puntos".to_string()),
+            payment_date: "2024-06-12".to_string(),
+            note: None,
+            ticket_id: None,
+            created_by: None,
+        };
+        assert!(record_customer_payment(&conn, &payment).is_err());
+    }
+}
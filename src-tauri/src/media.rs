@@ -0,0 +1,144 @@
+// Imágenes de productos guardadas en la carpeta de datos de la aplicación.
+//
+// Cada imagen se copia a `<media>/products/<sha256>.<ext>`: el nombre es el
+// hash del contenido, así la misma foto elegida para varios productos se
+// guarda una sola vez. Junto a cada una queda una miniatura PNG
+// `<sha256>_thumb.png` de 256 px de lado como máximo. En
+// `products.image_path` se guarda la ruta relativa a la carpeta de medios.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PRODUCTS_DIR: &str = "products";
+pub const THUMBNAIL_SIZE: u32 = 256;
+// Tamaño máximo de una imagen
+pub const MAX_BYTES: usize = 10 * 1024 * 1024;
+
+// Formato según los primeros bytes del archivo, no según la extensión
+pub fn image_format(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Ruta guardada en la base si la imagen es de la carpeta de medios
+pub fn is_managed(path: &str) -> bool {
+    path.starts_with(&format!("{}/", PRODUCTS_DIR))
+}
+
+// Miniatura de una imagen guardada (relativa a la carpeta de medios)
+pub fn thumbnail_path(path: &str) -> String {
+    if !is_managed(path) {
+        return path.to_string();
+    }
+    let stem = path.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(path);
+    format!("{}_thumb.png", stem)
+}
+
+// Reduce la imagen para que entre en `max` x `max`, sin agrandarla, y la
+// devuelve como PNG. None si no se puede decodificar.
+pub fn thumbnail(bytes: &[u8], max: u32) -> Option<Vec<u8>> {
+    let image = image::load_from_memory(bytes).ok()?;
+    let image = if image.width() > max || image.height() > max {
+        image.thumbnail(max, max)
+    } else {
+        image
+    };
+    let mut out = io::Cursor::new(Vec::new());
+    image.write_to(&mut out, image::ImageFormat::Png).ok()?;
+    Some(out.into_inner())
+}
+
+// Copia la imagen a la carpeta de medios si no estaba y devuelve su ruta
+// relativa. None si el formato no es una imagen admitida.
+pub fn store(media_dir: &Path, bytes: &[u8]) -> io::Result<Option<String>> {
+    let format = match image_format(bytes) {
+        Some(format) => format,
+        None => return Ok(None),
+    };
+    let path = format!("{}/{}.{}", PRODUCTS_DIR, content_hash(bytes), format);
+    let target = media_dir.join(&path);
+    if !target.exists() {
+        fs::create_dir_all(media_dir.join(PRODUCTS_DIR))?;
+        // Se escribe aparte y se renombra para no dejar archivos a medias
+        let partial = target.with_extension("tmp");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &target)?;
+    }
+    let thumb_target = media_dir.join(thumbnail_path(&path));
+    if !thumb_target.exists() {
+        // Si no se puede decodificar queda sin miniatura
+        if let Some(thumb) = thumbnail(bytes, THUMBNAIL_SIZE) {
+            fs::write(thumb_target, thumb)?;
+        }
+    }
+    Ok(Some(path))
+}
+
+// Borra la imagen y su miniatura; no falla si ya no estaban
+pub fn remove(media_dir: &Path, path: &str) -> io::Result<()> {
+    if !is_managed(path) {
+        return Ok(());
+    }
+    for file in [path.to_string(), thumbnail_path(path)] {
+        match fs::remove_file(media_dir.join(file)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let image = image::RgbImage::from_fn(width, height, |x, _| image::Rgb([(x % 256) as u8, 0, 255]));
+        let mut out = io::Cursor::new(Vec::new());
+        image.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    fn dimensions(bytes: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Png).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn detects_formats_and_hashes_content() {
+        assert_eq!(image_format(&encode(2, 2, image::ImageFormat::Png)), Some("png"));
+        assert_eq!(image_format(&encode(2, 2, image::ImageFormat::Jpeg)), Some("jpg"));
+        assert_eq!(image_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_format(b"hola"), None);
+        assert_eq!(content_hash(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(thumbnail_path("products/ab.png"), "products/ab_thumb.png");
+        assert_eq!(thumbnail_path("products/ab.jpg"), "products/ab_thumb.png");
+        assert_eq!(thumbnail_path("C:/fotos/ab.png"), "C:/fotos/ab.png");
+    }
+
+    #[test]
+    fn thumbnails_keep_aspect_ratio() {
+        let thumb = thumbnail(&encode(1024, 512, image::ImageFormat::Png), THUMBNAIL_SIZE).unwrap();
+        assert_eq!(dimensions(&thumb), (256, 128));
+        let thumb = thumbnail(&encode(300, 600, image::ImageFormat::Jpeg), THUMBNAIL_SIZE).unwrap();
+        assert_eq!(dimensions(&thumb), (128, 256));
+        // Las imágenes chicas no se agrandan
+        assert_eq!(dimensions(&thumbnail(&encode(40, 30, image::ImageFormat::Png), THUMBNAIL_SIZE).unwrap()), (40, 30));
+        assert_eq!(thumbnail(b"\x89PNG\r\n\x1a\nroto", THUMBNAIL_SIZE), None);
+    }
+}
@@ -0,0 +1,306 @@
+// Importes monetarios en unidades mínimas (céntimos).
+//
+// En SQLite se guardan como INTEGER y en Rust como `i64`, de modo que las
+// sumas son exactas. Hacia el frontend se siguen serializando como número
+// decimal (p. ej. 19.99) para no cambiar el contrato JSON existente.
+//
+// Reglas de redondeo:
+// - Al convertir un decimal a céntimos se redondea al céntimo más cercano,
+//   con las mitades alejándose de cero (0.005 -> 0.01).
+// - Los porcentajes se aplican con la misma regla sobre el importe en
+//   céntimos, usando centésimas de punto porcentual para evitar flotantes.
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+pub const CENTS_PER_UNIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    /// Convierte un importe decimal (p. ej. 19.99) a céntimos, redondeando
+    /// al céntimo más cercano.
+    ///
+    /// Se trabaja sobre la representación decimal más corta del `f64` (la
+    /// misma que escribió el frontend en el JSON), así 1.005 se redondea a
+    /// 1.01 aunque `1.005 * 100.0` dé 100.4999...
+    ///
+    /// None si el importe no es finito o no entra en un `i64` de céntimos.
+    pub fn from_major(amount: f64) -> Option<Self> {
+        let text = amount.abs().to_string();
+        let (int_part, frac_part) = text.split_once('.').unwrap_or((text.as_str(), ""));
+        let units: i64 = int_part.parse().ok()?;
+        let mut digits = frac_part.bytes().map(|b| (b - b'0') as i64);
+        let tenths = digits.next().unwrap_or(0);
+        let hundredths = digits.next().unwrap_or(0);
+        let round_up = digits.next().unwrap_or(0) >= 5;
+        let cents = units
+            .checked_mul(CENTS_PER_UNIT)?
+            .checked_add(tenths * 10 + hundredths + round_up as i64)?;
+        Some(Money(if amount < 0.0 { -cents } else { cents }))
+    }
+
+    pub fn to_major(self) -> f64 {
+        self.0 as f64 / CENTS_PER_UNIT as f64
+    }
+
+    pub fn times(self, quantity: i64) -> Self {
+        Money(self.0 * quantity)
+    }
+
+    /// Como `times`, pero None si el resultado no entra en un `i64` de
+    /// céntimos. Para cantidades e importes que vienen del usuario.
+    pub fn checked_times(self, quantity: i64) -> Option<Self> {
+        self.0.checked_mul(quantity).map(Money)
+    }
+
+    pub fn checked_add(self, rhs: Money) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Money)
+    }
+
+    pub fn checked_sub(self, rhs: Money) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Money)
+    }
+
+    /// Suma sin desbordar; None si el total no entra en un `i64`
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Money>) -> Option<Self> {
+        amounts.into_iter().try_fold(Money::ZERO, Money::checked_add)
+    }
+
+    /// Importe por unidad al repartir entre `quantity` unidades, redondeado
+    /// al céntimo más cercano. Con cantidad cero devuelve cero.
+    pub fn per_unit(self, quantity: i64) -> Self {
+        if quantity == 0 {
+            return Money::ZERO;
+        }
+        Money(div_round(self.0 as i128, quantity as i128) as i64)
+    }
+
+    /// Porcentaje del importe (p. ej. `percent(12.5)` = 12,5 %), redondeado
+    /// al céntimo más cercano con las mitades alejándose de cero.
+    pub fn percent(self, pct: f64) -> Self {
+        let basis_points = (pct * 100.0).round() as i128;
+        Money(div_round(self.0 as i128 * basis_points, 10_000) as i64)
+    }
+}
+
+impl Money {
+    /// Reparte el importe en proporción a `weights`. Cada parte se trunca y
+    /// la última se lleva el resto, así la suma siempre es exacta.
+    pub fn allocate(self, weights: &[Money]) -> Vec<Money> {
+        let total_weight: i128 = weights.iter().map(|w| w.0 as i128).sum();
+        let mut remaining = self;
+        let mut parts = Vec::with_capacity(weights.len());
+        for (n, weight) in weights.iter().enumerate() {
+            let part = if n + 1 == weights.len() {
+                remaining
+            } else if total_weight == 0 {
+                Money::ZERO
+            } else {
+                Money((self.0 as i128 * weight.0 as i128 / total_weight) as i64)
+            };
+            remaining -= part;
+            parts.push(part);
+        }
+        parts
+    }
+}
+
+/// División entera redondeando al más cercano (mitades lejos de cero)
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    let half = denominator / 2;
+    if (numerator < 0) != (denominator < 0) {
+        (numerator - half) / denominator
+    } else {
+        (numerator + half) / denominator
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(
+            f,
+            "{}{}.{:02}",
+            sign,
+            abs / CENTS_PER_UNIT as u64,
+            abs % CENTS_PER_UNIT as u64
+        )
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, |acc, m| acc + m)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_major())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let amount = f64::deserialize(deserializer)?;
+        Money::from_major(amount).ok_or_else(|| serde::de::Error::custom("importe no válido"))
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(cents) => Ok(Money(cents)),
+            // Bases de datos sin migrar todavía guardan el importe como REAL
+            ValueRef::Real(amount) => Money::from_major(amount).ok_or(FromSqlError::InvalidType),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn from_major_rounds_to_nearest_cent() {
+        assert_eq!(Money::from_major(19.99), Some(Money(1999)));
+        assert_eq!(Money::from_major(0.1 + 0.2), Some(Money(30)));
+        assert_eq!(Money::from_major(1.005), Some(Money(101)));
+        assert_eq!(Money::from_major(-2.5), Some(Money(-250)));
+    }
+
+    #[test]
+    fn from_major_rejects_amounts_out_of_range() {
+        assert_eq!(Money::from_major(1e17), None);
+        assert_eq!(Money::from_major(-1e30), None);
+        assert_eq!(Money::from_major(f64::NAN), None);
+        assert_eq!(Money::from_major(f64::INFINITY), None);
+        assert_eq!(Money::from_major(93_000_000_000_000_000.0), None);
+        assert_eq!(Money::from_major(9e15), Some(Money(900_000_000_000_000_000)));
+        assert!(serde_json::from_str::<Money>("1e20").is_err());
+    }
+
+    #[test]
+    fn checked_arithmetic_reports_overflow() {
+        let big = Money(i64::MAX / 2 + 1);
+        assert_eq!(Money(1999).checked_times(3), Some(Money(5997)));
+        assert_eq!(big.checked_times(2), None);
+        assert_eq!(big.checked_add(big), None);
+        assert_eq!(Money(-big.0).checked_sub(big.checked_add(Money(1)).unwrap()), None);
+        assert_eq!(Money::checked_sum([Money(1), Money(2)]), Some(Money(3)));
+        assert_eq!(Money::checked_sum([big, big]), None);
+    }
+
+    #[test]
+    fn percent_rounds_half_away_from_zero() {
+        // 15 % de 19.99 = 2.9985 -> 3.00
+        assert_eq!(Money::from_cents(1999).percent(15.0).cents(), 300);
+        // 12.5 % de 0.04 = 0.005 -> 0.01
+        assert_eq!(Money::from_cents(4).percent(12.5).cents(), 1);
+        // 10 % de 0.04 = 0.004 -> 0.00
+        assert_eq!(Money::from_cents(4).percent(10.0).cents(), 0);
+        assert_eq!(Money::from_cents(-4).percent(12.5).cents(), -1);
+    }
+
+    #[test]
+    fn allocate_keeps_the_total() {
+        let parts = Money::from_cents(1000).allocate(&[Money::from_cents(1), Money::from_cents(1), Money::from_cents(1)]);
+        assert_eq!(parts, vec![Money::from_cents(333), Money::from_cents(333), Money::from_cents(334)]);
+        let parts = Money::from_cents(1500).allocate(&[Money::from_cents(20000), Money::from_cents(2500)]);
+        assert_eq!(parts, vec![Money::from_cents(1333), Money::from_cents(167)]);
+    }
+
+    #[test]
+    fn per_unit_rounds_to_nearest_cent() {
+        assert_eq!(Money::from_cents(1000).per_unit(3), Money::from_cents(333));
+        assert_eq!(Money::from_cents(1001).per_unit(2), Money::from_cents(501));
+        assert_eq!(Money::from_cents(500).per_unit(0), Money::ZERO);
+    }
+
+    #[test]
+    fn display_and_json_use_major_units() {
+        assert_eq!(Money::from_cents(1999).to_string(), "19.99");
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(serde_json::to_string(&Money::from_cents(1050)).unwrap(), "10.5");
+        let parsed: Money = serde_json::from_str("10.1").unwrap();
+        assert_eq!(parsed.cents(), 1010);
+    }
+
+    #[test]
+    fn sum_of_many_sales_matches_to_the_cent() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE t (amount INTEGER NOT NULL)", []).unwrap();
+
+        let price = Money::from_major(0.1).unwrap();
+        let mut float_total = 0.0_f64;
+        for _ in 0..10_000 {
+            conn.execute("INSERT INTO t (amount) VALUES (?1)", [price]).unwrap();
+            float_total += 0.1;
+        }
+        let total: Money = conn
+            .query_row("SELECT COALESCE(SUM(amount),0) FROM t", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(total, Money::from_cents(100_000));
+        assert_eq!(total.to_string(), "1000.00");
+        // La suma con flotantes que hacíamos antes no da exactamente 1000
+        assert_ne!(float_total, 1000.0);
+    }
+}
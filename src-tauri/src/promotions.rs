@@ -0,0 +1,312 @@
+// Motor de promociones.
+//
+// Tipos de regla (`kind`):
+// - "porcentaje": `percent` % de descuento sobre las líneas que cumplen el filtro
+// - "nxm": lleva `buy_qty`, paga `pay_qty` (2x1 = 2 y 1) en las líneas que cumplen el filtro
+// - "combo": precio fijo `bundle_price` por cada juego completo de `bundle_items`
+//
+// El filtro de "porcentaje" y "nxm" combina product_id, category, brand y
+// flavor (los que no son nulos deben coincidir todos). Cada línea recibe como
+// mucho una promoción: primero se asignan los combos y después, a cada línea
+// libre, la promoción que más ahorra.
+
+use crate::money::Money;
+use serde::{Deserialize, Serialize};
+
+pub const KIND_PERCENT: &str = "porcentaje";
+pub const KIND_BUY_X_PAY_Y: &str = "nxm";
+pub const KIND_BUNDLE: &str = "combo";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleItem {
+    pub product_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Promotion {
+    pub id: Option<i32>,
+    pub name: String,
+    pub kind: String,
+    pub percent: Option<f64>,
+    pub buy_qty: Option<i32>,
+    pub pay_qty: Option<i32>,
+    pub bundle_price: Option<Money>,
+    pub product_id: Option<i32>,
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    pub flavor: Option<String>,
+    /// Canales separados por coma ("Tienda,Online"); vacío = todos
+    pub channels: Option<String>,
+    pub starts_at: Option<String>,
+    pub ends_at: Option<String>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+    #[serde(default)]
+    pub bundle_items: Vec<BundleItem>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Datos de una línea de ticket necesarios para evaluar promociones
+#[derive(Debug, Clone)]
+pub struct LineInfo {
+    pub product_id: i32,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    pub flavor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppliedPromotion {
+    pub promotion_id: i32,
+    pub amount: Money,
+}
+
+impl Promotion {
+    /// Devuelve el campo inválido, si lo hay
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.name.trim().is_empty() {
+            return Err("name");
+        }
+        match self.kind.as_str() {
+            KIND_PERCENT => match self.percent {
+                Some(p) if p > 0.0 && p <= 100.0 => {}
+                _ => return Err("percent"),
+            },
+            KIND_BUY_X_PAY_Y => match (self.buy_qty, self.pay_qty) {
+                (Some(buy), Some(pay)) if buy > pay && pay >= 0 => {}
+                _ => return Err("buy_qty"),
+            },
+            KIND_BUNDLE => {
+                if self.bundle_items.is_empty() || self.bundle_items.iter().any(|i| i.quantity <= 0) {
+                    return Err("bundle_items");
+                }
+                match self.bundle_price {
+                    Some(p) if p >= Money::ZERO => {}
+                    _ => return Err("bundle_price"),
+                }
+            }
+            _ => return Err("kind"),
+        }
+        Ok(())
+    }
+
+    pub fn allows_channel(&self, channel: Option<&str>) -> bool {
+        let allowed: Vec<&str> = self
+            .channels
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .collect();
+        if allowed.is_empty() {
+            return true;
+        }
+        match channel {
+            Some(ch) => allowed.iter().any(|a| a.eq_ignore_ascii_case(ch.trim())),
+            None => false,
+        }
+    }
+
+    fn matches(&self, line: &LineInfo) -> bool {
+        fn same(filter: &Option<String>, value: &Option<String>) -> bool {
+            match (filter, value) {
+                (None, _) => true,
+                (Some(f), Some(v)) => f.trim().eq_ignore_ascii_case(v.trim()),
+                (Some(_), None) => false,
+            }
+        }
+        self.product_id.is_none_or(|id| id == line.product_id)
+            && same(&self.category, &line.category)
+            && same(&self.brand, &line.brand)
+            && same(&self.flavor, &line.flavor)
+    }
+
+    /// Ahorro de una promoción de línea ("porcentaje" o "nxm")
+    fn line_savings(&self, line: &LineInfo) -> Money {
+        let gross = line.unit_price.times(line.quantity as i64);
+        match self.kind.as_str() {
+            KIND_PERCENT => gross.percent(self.percent.unwrap_or(0.0)),
+            KIND_BUY_X_PAY_Y => {
+                let (buy, pay) = (self.buy_qty.unwrap_or(0), self.pay_qty.unwrap_or(0));
+                if buy <= 0 || pay >= buy {
+                    return Money::ZERO;
+                }
+                let free_units = (line.quantity / buy) * (buy - pay);
+                line.unit_price.times(free_units as i64)
+            }
+            _ => Money::ZERO,
+        }
+    }
+}
+
+/// Asigna a cada línea la promoción aplicada y su importe. `promotions` ya
+/// debe venir filtrada por fecha, canal y `active`.
+pub fn evaluate(promotions: &[Promotion], lines: &[LineInfo]) -> Vec<Option<AppliedPromotion>> {
+    let mut applied: Vec<Option<AppliedPromotion>> = vec![None; lines.len()];
+
+    // Combos: del que más ahorra por juego al que menos
+    let mut bundles: Vec<&Promotion> = promotions.iter().filter(|p| p.kind == KIND_BUNDLE).collect();
+    bundles.sort_by_key(|p| std::cmp::Reverse(bundle_unit_savings(p, lines)));
+    for promo in bundles {
+        let Some(promo_id) = promo.id else { continue };
+        let bundle_price = promo.bundle_price.unwrap_or(Money::ZERO);
+        // Línea libre para cada componente del combo
+        let mut slots: Vec<(usize, i32)> = Vec::new();
+        for item in &promo.bundle_items {
+            let found = lines
+                .iter()
+                .enumerate()
+                .find(|(i, l)| applied[*i].is_none() && l.product_id == item.product_id && !slots.iter().any(|(s, _)| s == i));
+            match found {
+                Some((i, _)) => slots.push((i, item.quantity)),
+                None => break,
+            }
+        }
+        if slots.len() != promo.bundle_items.len() {
+            continue;
+        }
+        let sets = slots.iter().map(|(i, qty)| lines[*i].quantity / qty).min().unwrap_or(0);
+        if sets <= 0 {
+            continue;
+        }
+        let parts: Vec<Money> = slots
+            .iter()
+            .map(|(i, qty)| lines[*i].unit_price.times((*qty * sets) as i64))
+            .collect();
+        let list_value: Money = parts.iter().copied().sum();
+        let savings = list_value - bundle_price.times(sets as i64);
+        if savings <= Money::ZERO {
+            continue;
+        }
+        // El ahorro se reparte proporcionalmente al valor de cada componente
+        for ((i, _), share) in slots.iter().zip(savings.allocate(&parts)) {
+            applied[*i] = Some(AppliedPromotion { promotion_id: promo_id, amount: share });
+        }
+    }
+
+    // Promociones de línea: la de mayor ahorro
+    for (i, line) in lines.iter().enumerate() {
+        if applied[i].is_some() {
+            continue;
+        }
+        let best = promotions
+            .iter()
+            .filter(|p| p.kind != KIND_BUNDLE && p.id.is_some() && p.matches(line))
+            .map(|p| (p, p.line_savings(line)))
+            .filter(|(_, savings)| *savings > Money::ZERO)
+            .max_by_key(|(_, savings)| *savings);
+        if let Some((promo, amount)) = best {
+            applied[i] = Some(AppliedPromotion { promotion_id: promo.id.unwrap_or_default(), amount });
+        }
+    }
+    applied
+}
+
+fn bundle_unit_savings(promo: &Promotion, lines: &[LineInfo]) -> Money {
+    let list_value: Money = promo
+        .bundle_items
+        .iter()
+        .map(|item| {
+            lines
+                .iter()
+                .find(|l| l.product_id == item.product_id)
+                .map(|l| l.unit_price.times(item.quantity as i64))
+                .unwrap_or(Money::ZERO)
+        })
+        .sum();
+    list_value - promo.bundle_price.unwrap_or(Money::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(product_id: i32, quantity: i32, cents: i64, category: &str, flavor: &str) -> LineInfo {
+        LineInfo {
+            product_id,
+            quantity,
+            unit_price: Money::from_cents(cents),
+            category: Some(category.to_string()),
+            brand: None,
+            flavor: Some(flavor.to_string()),
+        }
+    }
+
+    fn promo(id: i32, kind: &str) -> Promotion {
+        Promotion {
+            id: Some(id),
+            name: format!("Promo {}", id),
+            kind: kind.to_string(),
+            percent: None,
+            buy_qty: None,
+            pay_qty: None,
+            bundle_price: None,
+            product_id: None,
+            category: None,
+            brand: None,
+            flavor: None,
+            channels: None,
+            starts_at: None,
+            ends_at: None,
+            active: true,
+            bundle_items: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn best_line_promotion_wins() {
+        let mut pct = promo(1, KIND_PERCENT);
+        pct.percent = Some(10.0);
+        pct.category = Some("Proteínas".to_string());
+        let mut two_for_one = promo(2, KIND_BUY_X_PAY_Y);
+        two_for_one.buy_qty = Some(2);
+        two_for_one.pay_qty = Some(1);
+        two_for_one.flavor = Some("Chocolate".to_string());
+
+        let lines = vec![
+            line(1, 3, 10000, "proteínas", "Chocolate"),
+            line(2, 2, 5000, "Proteínas", "Vainilla"),
+            line(3, 2, 3000, "Accesorios", "Chocolate"),
+        ];
+        let applied = evaluate(&[pct, two_for_one], &lines);
+        // 3 x 100: 2x1 regala 1 unidad (100) > 10 % (30)
+        assert_eq!(applied[0], Some(AppliedPromotion { promotion_id: 2, amount: Money::from_cents(10000) }));
+        assert_eq!(applied[1], Some(AppliedPromotion { promotion_id: 1, amount: Money::from_cents(1000) }));
+        assert_eq!(applied[2], Some(AppliedPromotion { promotion_id: 2, amount: Money::from_cents(3000) }));
+    }
+
+    #[test]
+    fn bundle_savings_are_split_across_lines() {
+        let mut combo = promo(7, KIND_BUNDLE);
+        combo.bundle_price = Some(Money::from_cents(11000));
+        combo.bundle_items = vec![
+            BundleItem { product_id: 1, quantity: 1 },
+            BundleItem { product_id: 2, quantity: 1 },
+        ];
+        let lines = vec![line(1, 2, 10000, "Proteínas", "Chocolate"), line(2, 1, 2500, "Accesorios", "-")];
+        let applied = evaluate(&[combo], &lines);
+        let total: Money = applied.iter().flatten().map(|a| a.amount).sum();
+        // Un solo combo: 100 + 25 - 110 = 15
+        assert_eq!(total, Money::from_cents(1500));
+        assert_eq!(applied[0].unwrap().amount, Money::from_cents(1200));
+        assert_eq!(applied[1].unwrap().amount, Money::from_cents(300));
+    }
+
+    #[test]
+    fn channel_restrictions() {
+        let mut p = promo(1, KIND_PERCENT);
+        p.channels = Some("Tienda, Redes".to_string());
+        assert!(p.allows_channel(Some("tienda")));
+        assert!(!p.allows_channel(Some("Online")));
+        assert!(!p.allows_channel(None));
+        p.channels = None;
+        assert!(p.allows_channel(None));
+    }
+}
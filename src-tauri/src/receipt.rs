@@ -0,0 +1,413 @@
+// Tickets de venta para impresoras térmicas de 58 y 80 mm.
+//
+// El ticket se arma como renglones de ancho fijo (32 columnas en 58 mm y 48
+// en 80 mm, con la fuente A de ESC/POS) y de ahí salen el texto plano, el
+// flujo ESC/POS para la impresora y un PDF en Courier del ancho del rollo.
+// Así el contenido es el mismo en los tres formatos y se puede comparar en
+// los tests sin impresora.
+
+use crate::money::Money;
+use printpdf::{BuiltinFont, Mm, PdfDocument};
+
+// 1 punto tipográfico en milímetros
+const PT: f32 = 0.3528;
+// Margen lateral y superior del PDF
+const PDF_MARGIN: f32 = 3.0;
+// Ancho de un carácter de Courier en relación al tamaño de la fuente
+const COURIER_WIDTH: f32 = 0.6;
+
+const DEFAULT_FOOTER: &str = "¡Gracias por su compra!";
+
+#[derive(Debug)]
+pub struct Paper {
+    pub width_mm: u32,
+    pub columns: usize,
+}
+
+pub const PAPERS: [Paper; 2] = [Paper { width_mm: 58, columns: 32 }, Paper { width_mm: 80, columns: 48 }];
+
+pub fn paper(width_mm: u32) -> Option<&'static Paper> {
+    PAPERS.iter().find(|p| p.width_mm == width_mm)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Store {
+    pub name: String,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub tax_id: Option<String>,
+    /// Texto al pie; None = agradecimiento por defecto
+    pub footer: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiptLine {
+    pub name: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub gross: Money,
+    /// Promoción, descuento manual y puntos
+    pub discount: Money,
+    pub net: Money,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiptPayment {
+    /// Nombre a mostrar de la forma de pago
+    pub method: String,
+    pub tendered: Money,
+    pub change: Money,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub store: Store,
+    pub ticket_id: i64,
+    pub date: String,
+    pub cashier: Option<String>,
+    pub customer: Option<String>,
+    pub lines: Vec<ReceiptLine>,
+    pub payments: Vec<ReceiptPayment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Normal,
+    Bold,
+    /// Nombre del comercio: negrita y doble alto
+    Title,
+}
+
+struct Row {
+    text: String,
+    style: Style,
+}
+
+fn center(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len >= width {
+        return text.chars().take(width).collect();
+    }
+    format!("{}{}", " ".repeat((width - len) / 2), text)
+}
+
+// Texto a la izquierda e importe a la derecha; el texto se recorta si no entra
+fn two_columns(left: &str, right: &str, width: usize) -> String {
+    let right_len = right.chars().count();
+    let room = width.saturating_sub(right_len + 1);
+    let left: String = left.chars().take(room).collect();
+    let gap = width - left.chars().count() - right_len;
+    format!("{}{}{}", left, " ".repeat(gap), right)
+}
+
+// Corta en palabras; las palabras más largas que el renglón se parten
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > width {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            lines.push(word.drain(..width).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if current.is_empty() {
+            current = word;
+        } else if current.chars().count() + 1 + word.chars().count() <= width {
+            current.push(' ');
+            current.push_str(&word);
+        } else {
+            lines.push(std::mem::replace(&mut current, word));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn rows(receipt: &Receipt, width: usize) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut push = |text: String, style: Style| rows.push(Row { text, style });
+    let separator = "-".repeat(width);
+
+    let store = &receipt.store;
+    for line in wrap(&store.name, width) {
+        push(center(&line, width), Style::Title);
+    }
+    let extras = [
+        store.address.clone(),
+        store.phone.as_ref().map(|p| format!("Tel: {}", p)),
+        store.tax_id.as_ref().map(|t| format!("RUC: {}", t)),
+    ];
+    for extra in extras.into_iter().flatten() {
+        for line in wrap(&extra, width) {
+            push(center(&line, width), Style::Normal);
+        }
+    }
+    push(separator.clone(), Style::Normal);
+    push(format!("Ticket Nº {:06}", receipt.ticket_id), Style::Bold);
+    push(format!("Fecha: {}", receipt.date), Style::Normal);
+    if let Some(cashier) = &receipt.cashier {
+        push(format!("Cajero: {}", cashier), Style::Normal);
+    }
+    if let Some(customer) = &receipt.customer {
+        push(format!("Cliente: {}", customer), Style::Normal);
+    }
+    push(separator.clone(), Style::Normal);
+
+    for line in &receipt.lines {
+        for name in wrap(&line.name, width) {
+            push(name, Style::Normal);
+        }
+        let detail = format!("  {} x {}", line.quantity, line.unit_price);
+        push(two_columns(&detail, &line.gross.to_string(), width), Style::Normal);
+        if line.discount > Money::ZERO {
+            push(two_columns("  Descuento", &(-line.discount).to_string(), width), Style::Normal);
+        }
+    }
+    push(separator.clone(), Style::Normal);
+
+    let gross: Money = receipt.lines.iter().map(|l| l.gross).sum();
+    let discount: Money = receipt.lines.iter().map(|l| l.discount).sum();
+    let total: Money = receipt.lines.iter().map(|l| l.net).sum();
+    push(two_columns("Subtotal", &gross.to_string(), width), Style::Normal);
+    if discount > Money::ZERO {
+        push(two_columns("Descuentos", &(-discount).to_string(), width), Style::Normal);
+    }
+    push(two_columns("TOTAL", &total.to_string(), width), Style::Bold);
+    push(String::new(), Style::Normal);
+
+    for payment in &receipt.payments {
+        let method = match &payment.reference {
+            Some(reference) => format!("{} ({})", payment.method, reference),
+            None => payment.method.clone(),
+        };
+        push(two_columns(&method, &payment.tendered.to_string(), width), Style::Normal);
+    }
+    let change: Money = receipt.payments.iter().map(|p| p.change).sum();
+    if change > Money::ZERO {
+        push(two_columns("Vuelto", &change.to_string(), width), Style::Normal);
+    }
+    push(separator, Style::Normal);
+    for line in wrap(store.footer.as_deref().unwrap_or(DEFAULT_FOOTER), width) {
+        push(center(&line, width), Style::Normal);
+    }
+    rows
+}
+
+// Texto plano, un renglón por línea
+pub fn to_text(receipt: &Receipt, paper: &Paper) -> String {
+    rows(receipt, paper.columns).iter().map(|r| format!("{}\n", r.text)).collect()
+}
+
+// Página de códigos PC850 (Latin-1 de las impresoras térmicas). Lo que no
+// tiene equivalente se imprime como "?".
+fn encode_pc850(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            c if c.is_ascii() => c as u8,
+            'á' => 0xA0,
+            'é' => 0x82,
+            'í' => 0xA1,
+            'ó' => 0xA2,
+            'ú' => 0xA3,
+            'ñ' => 0xA4,
+            'Ñ' => 0xA5,
+            'ü' => 0x81,
+            'Ü' => 0x9A,
+            'Á' => 0xB5,
+            'É' => 0x90,
+            'Í' => 0xD6,
+            'Ó' => 0xE0,
+            'Ú' => 0xE9,
+            '¿' => 0xA8,
+            '¡' => 0xAD,
+            'º' => 0xA7,
+            'ª' => 0xA6,
+            '°' => 0xF8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+
+// Flujo ESC/POS: inicializa, elige PC850, imprime, avanza y corta
+pub fn to_escpos(receipt: &Receipt, paper: &Paper) -> Vec<u8> {
+    let mut bytes = vec![ESC, b'@', ESC, b't', 2];
+    for row in rows(receipt, paper.columns) {
+        match row.style {
+            Style::Normal => {}
+            Style::Bold => bytes.extend([ESC, b'E', 1]),
+            Style::Title => bytes.extend([ESC, b'E', 1, GS, b'!', 0x01]),
+        }
+        bytes.extend(encode_pc850(&row.text));
+        bytes.push(b'\n');
+        match row.style {
+            Style::Normal => {}
+            Style::Bold => bytes.extend([ESC, b'E', 0]),
+            Style::Title => bytes.extend([ESC, b'E', 0, GS, b'!', 0]),
+        }
+    }
+    // Avance de 4 renglones y corte parcial
+    bytes.extend([ESC, b'd', 4, GS, b'V', 1]);
+    bytes
+}
+
+// Una sola página del ancho del rollo y el largo del ticket
+pub fn to_pdf(receipt: &Receipt, paper: &Paper) -> Result<Vec<u8>, printpdf::Error> {
+    let width = paper.width_mm as f32;
+    let size = (width - 2.0 * PDF_MARGIN) / paper.columns as f32 / COURIER_WIDTH / PT;
+    let line_height = size * PT * 1.25;
+    let rows = rows(receipt, paper.columns);
+    let height = 2.0 * PDF_MARGIN
+        + rows.iter().map(|r| if r.style == Style::Title { 2.0 * line_height } else { line_height }).sum::<f32>();
+
+    let title = format!("Ticket {:06}", receipt.ticket_id);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(width), Mm(height), "Ticket");
+    let regular = doc.add_builtin_font(BuiltinFont::Courier)?;
+    let bold = doc.add_builtin_font(BuiltinFont::CourierBold)?;
+    let layer = doc.get_page(page).get_layer(layer);
+    // PDF mide desde abajo; `y` es la línea base del renglón
+    let mut y = height - PDF_MARGIN;
+    for row in &rows {
+        let (font, font_size, step) = match row.style {
+            Style::Normal => (&regular, size, line_height),
+            Style::Bold => (&bold, size, line_height),
+            Style::Title => (&bold, size * 2.0, 2.0 * line_height),
+        };
+        y -= step;
+        if row.style == Style::Title {
+            // Al doble de tamaño el renglón ocupa el doble: se centra de nuevo
+            let text = row.text.trim();
+            let text_width = text.chars().count() as f32 * font_size * PT * COURIER_WIDTH;
+            let x = ((width - text_width) / 2.0).max(PDF_MARGIN);
+            layer.use_text(text, font_size, Mm(x), Mm(y + step * 0.2), font);
+        } else {
+            layer.use_text(row.text.as_str(), font_size, Mm(PDF_MARGIN), Mm(y + step * 0.2), font);
+        }
+    }
+    doc.save_to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt() -> Receipt {
+        Receipt {
+            store: Store {
+                name: "VitaSport".to_string(),
+                address: Some("Av. Larco 123, Miraflores".to_string()),
+                phone: Some("01 555 1234".to_string()),
+                tax_id: Some("20123456789".to_string()),
+                footer: None,
+            },
+            ticket_id: 42,
+            date: "2026-10-19 10:30".to_string(),
+            cashier: Some("María Pérez".to_string()),
+            customer: None,
+            lines: vec![
+                ReceiptLine {
+                    name: "Proteína Whey Gold Standard 5lb Chocolate".to_string(),
+                    quantity: 1,
+                    unit_price: Money::from_cents(19990),
+                    gross: Money::from_cents(19990),
+                    discount: Money::from_cents(1999),
+                    net: Money::from_cents(17991),
+                },
+                ReceiptLine {
+                    name: "Shaker".to_string(),
+                    quantity: 2,
+                    unit_price: Money::from_cents(1500),
+                    gross: Money::from_cents(3000),
+                    discount: Money::ZERO,
+                    net: Money::from_cents(3000),
+                },
+            ],
+            payments: vec![
+                ReceiptPayment {
+                    method: "Tarjeta".to_string(),
+                    tendered: Money::from_cents(10000),
+                    change: Money::ZERO,
+                    reference: Some("4242".to_string()),
+                },
+                ReceiptPayment {
+                    method: "Efectivo".to_string(),
+                    tendered: Money::from_cents(12000),
+                    change: Money::from_cents(1009),
+                    reference: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn renders_58mm_text() {
+        let expected = "           VitaSport
+   Av. Larco 123, Miraflores
+        Tel: 01 555 1234
+        RUC: 20123456789
+--------------------------------
+Ticket Nº 000042
+Fecha: 2026-10-19 10:30
+Cajero: María Pérez
+--------------------------------
+Proteína Whey Gold Standard 5lb
+Chocolate
+  1 x 199.90              199.90
+  Descuento               -19.99
+Shaker
+  2 x 15.00                30.00
+--------------------------------
+Subtotal                  229.90
+Descuentos                -19.99
+TOTAL                     209.91
+
+Tarjeta (4242)            100.00
+Efectivo                  120.00
+Vuelto                     10.09
+--------------------------------
+    ¡Gracias por su compra!
+";
+        assert_eq!(to_text(&receipt(), paper(58).unwrap()), expected);
+    }
+
+    #[test]
+    fn renders_80mm_text_with_customer() {
+        let mut r = receipt();
+        r.customer = Some("Juan Quispe".to_string());
+        r.store.footer = Some("Cambios dentro de los 7 días con este ticket".to_string());
+        let text = to_text(&r, paper(80).unwrap());
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.iter().all(|l| l.chars().count() <= 48));
+        assert!(lines.contains(&"Cliente: Juan Quispe"));
+        assert!(lines.contains(&"Proteína Whey Gold Standard 5lb Chocolate"));
+        assert!(lines.contains(&"TOTAL                                     209.91"));
+        assert_eq!(lines.last(), Some(&"  Cambios dentro de los 7 días con este ticket"));
+        assert!(paper(70).is_none());
+    }
+
+    #[test]
+    fn encodes_escpos_and_pdf() {
+        let bytes = to_escpos(&receipt(), paper(58).unwrap());
+        assert!(bytes.starts_with(&[ESC, b'@', ESC, b't', 2]));
+        assert!(bytes.ends_with(&[ESC, b'd', 4, GS, b'V', 1]));
+        // Título en negrita y doble alto
+        let title = [&[ESC, b'E', 1, GS, b'!', 1][..], b"           VitaSport\n"].concat();
+        assert!(bytes.windows(title.len()).any(|w| w == title.as_slice()));
+        // "Ticket Nº" en PC850
+        assert!(bytes.windows(9).any(|w| w == b"Ticket N\xA7"));
+        assert!(bytes.windows(4).any(|w| w == b"Prot"));
+        assert!(bytes.windows(3).any(|w| w == b"\xA1na"));
+        assert_eq!(encode_pc850("€"), b"?");
+
+        let pdf = to_pdf(&receipt(), paper(80).unwrap()).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,246 @@
+// Reportes exportables como hojas de cálculo.
+//
+// Cada reporte es una hoja con encabezado, filas de celdas tipadas y, si
+// corresponde, una fila de totales. En Excel (XLSX) los importes y las
+// cantidades se escriben como números y las fechas como fechas, así se pueden
+// sumar y filtrar sin convertir; los totales son fórmulas `SUM` con el valor
+// ya calculado para los visores que no recalculan.
+
+use crate::money::Money;
+use rust_xlsxwriter::{column_number_to_name, ExcelDateTime, Format, FormatBorder, Formula, Workbook, XlsxError};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Empty,
+    Text(String),
+    Int(i64),
+    Number(f64),
+    Money(Money),
+    /// "YYYY-MM-DD", con hora opcional
+    Date(String),
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_string())
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(value: i64) -> Self {
+        Cell::Int(value)
+    }
+}
+
+impl From<i32> for Cell {
+    fn from(value: i32) -> Self {
+        Cell::Int(value as i64)
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(value: f64) -> Self {
+        Cell::Number(value)
+    }
+}
+
+impl From<Money> for Cell {
+    fn from(value: Money) -> Self {
+        Cell::Money(value)
+    }
+}
+
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Cell::Empty)
+    }
+}
+
+// Valor como texto, igual que en los CSV
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Empty => Ok(()),
+            Cell::Text(s) | Cell::Date(s) => f.write_str(s),
+            Cell::Int(n) => write!(f, "{}", n),
+            Cell::Number(n) => write!(f, "{}", n),
+            Cell::Money(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sheet {
+    pub name: String,
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+    /// Columnas que se suman en la fila de totales; vacío = sin totales
+    pub totals: Vec<usize>,
+}
+
+impl Sheet {
+    pub fn new(name: &str, columns: &[&'static str]) -> Self {
+        Sheet { name: name.to_string(), columns: columns.to_vec(), rows: Vec::new(), totals: Vec::new() }
+    }
+
+    /// Agrega la fila de totales sumando las columnas indicadas por nombre
+    pub fn with_totals(mut self, columns: &[&str]) -> Self {
+        self.totals = columns
+            .iter()
+            .filter_map(|name| self.columns.iter().position(|c| c == name))
+            .collect();
+        self
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    /// "total" en la primera columna y la suma de cada columna de totales.
+    /// Una columna con algún importe se suma como importe.
+    pub fn total_row(&self) -> Option<Vec<Cell>> {
+        if self.totals.is_empty() {
+            return None;
+        }
+        let mut row = vec![Cell::Empty; self.columns.len()];
+        row[0] = Cell::from("total");
+        for &col in &self.totals {
+            let cells = self.rows.iter().filter_map(|r| r.get(col));
+            let has_money = cells.clone().any(|c| matches!(c, Cell::Money(_)));
+            row[col] = if has_money {
+                Cell::Money(cells.filter_map(|c| if let Cell::Money(m) = c { Some(*m) } else { None }).sum())
+            } else {
+                Cell::Int(cells.filter_map(|c| if let Cell::Int(n) = c { Some(*n) } else { None }).sum())
+            };
+        }
+        Some(row)
+    }
+}
+
+// Libro con una hoja por reporte, en el orden recibido
+pub fn to_xlsx(sheets: &[Sheet]) -> Result<Vec<u8>, XlsxError> {
+    let header = Format::new()
+        .set_bold()
+        .set_background_color(0xD9E1F2)
+        .set_border_bottom(FormatBorder::Thin);
+    let money = Format::new().set_num_format("#,##0.00");
+    let date = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    let total = Format::new().set_bold().set_border_top(FormatBorder::Thin);
+    let total_money = total.clone().set_num_format("#,##0.00");
+
+    let mut workbook = Workbook::new();
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(&sheet.name)?;
+        for (col, name) in sheet.columns.iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, *name, &header)?;
+        }
+        for (i, cells) in sheet.rows.iter().enumerate() {
+            let row = i as u32 + 1;
+            for (col, cell) in cells.iter().enumerate() {
+                let col = col as u16;
+                match cell {
+                    Cell::Empty => {}
+                    Cell::Text(s) => {
+                        worksheet.write_string(row, col, s)?;
+                    }
+                    Cell::Int(n) => {
+                        worksheet.write_number(row, col, *n as f64)?;
+                    }
+                    Cell::Number(n) => {
+                        worksheet.write_number(row, col, *n)?;
+                    }
+                    Cell::Money(m) => {
+                        worksheet.write_number_with_format(row, col, m.to_major(), &money)?;
+                    }
+                    // Una fecha que no se puede leer queda como texto
+                    Cell::Date(s) => match ExcelDateTime::parse_from_str(s) {
+                        Ok(value) => {
+                            let format = if s.trim().len() > 10 { &datetime } else { &date };
+                            worksheet.write_datetime_with_format(row, col, &value, format)?;
+                        }
+                        Err(_) => {
+                            worksheet.write_string(row, col, s)?;
+                        }
+                    },
+                }
+            }
+        }
+        let last = sheet.rows.len() as u32;
+        if let Some(cells) = sheet.total_row() {
+            let row = last + 1;
+            for (col, cell) in cells.iter().enumerate() {
+                let col = col as u16;
+                let letter = column_number_to_name(col);
+                let sum = |value: String| Formula::new(format!("=SUM({0}2:{0}{1})", letter, last + 1)).set_result(value);
+                match cell {
+                    Cell::Int(n) => {
+                        worksheet.write_formula_with_format(row, col, sum(n.to_string()), &total)?;
+                    }
+                    Cell::Money(m) => {
+                        worksheet.write_formula_with_format(row, col, sum(m.to_string()), &total_money)?;
+                    }
+                    Cell::Empty => {
+                        worksheet.write_blank(row, col, &total)?;
+                    }
+                    other => {
+                        worksheet.write_string_with_format(row, col, other.to_string(), &total)?;
+                    }
+                }
+            }
+        }
+        worksheet.set_freeze_panes(1, 0)?;
+        if !sheet.columns.is_empty() {
+            worksheet.autofilter(0, 0, last, sheet.columns.len() as u16 - 1)?;
+        }
+        worksheet.autofit();
+    }
+    workbook.save_to_buffer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sales() -> Sheet {
+        let mut sheet = Sheet::new("Ventas", &["id", "quantity", "sale_price", "sale_date"]).with_totals(&["quantity", "sale_price"]);
+        sheet.push(vec![Cell::from(1), Cell::from(2i64), Cell::from(Money::from_cents(1999)), Cell::Date("2024-03-01 10:15:00".into())]);
+        sheet.push(vec![Cell::from(2), Cell::from(1i64), Cell::from(None::<Money>), Cell::Date("2024-03-02".into())]);
+        sheet.push(vec![Cell::from(3), Cell::from(3i64), Cell::from(Money::from_cents(501)), Cell::Date("sin fecha".into())]);
+        sheet
+    }
+
+    #[test]
+    fn totals_sum_marked_columns() {
+        let sheet = sales();
+        assert_eq!(
+            sheet.total_row().unwrap(),
+            vec![Cell::from("total"), Cell::Int(6), Cell::Money(Money::from_cents(2500)), Cell::Empty]
+        );
+        assert_eq!(Sheet::new("Inventario", &["id"]).total_row(), None);
+        let text: Vec<String> = sheet.rows[1].iter().map(Cell::to_string).collect();
+        assert_eq!(text, vec!["2", "1", "", "2024-03-02"]);
+    }
+
+    #[test]
+    fn writes_one_worksheet_per_sheet() {
+        let inventory = Sheet::new("Inventario", &["id", "name"]);
+        let bytes = to_xlsx(&[sales(), inventory]).unwrap();
+        // Un XLSX es un ZIP; los nombres de las partes van sin comprimir
+        assert!(bytes.starts_with(b"PK"));
+        let contains = |part: &str| bytes.windows(part.len()).any(|w| w == part.as_bytes());
+        assert!(contains("xl/worksheets/sheet1.xml"));
+        assert!(contains("xl/worksheets/sheet2.xml"));
+        assert!(!contains("xl/worksheets/sheet3.xml"));
+        // Excel no admite ciertos caracteres en el nombre de la hoja
+        assert!(to_xlsx(&[Sheet::new("Ventas/2024", &["id"])]).is_err());
+    }
+}
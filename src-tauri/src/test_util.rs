@@ -0,0 +1,46 @@
+// Ayudas compartidas por los tests: una base en memoria con el esquema
+// completo y altas mínimas para armar cada caso.
+
+use crate::money::Money;
+use crate::{init_schema, open_session};
+use rusqlite::Connection;
+
+/// Base en memoria con todas las tablas, el usuario inicial y las
+/// ubicaciones de sistema
+pub fn test_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    init_schema(&conn).unwrap();
+    conn
+}
+
+/// Producto con solo nombre y precio de lista (en céntimos)
+pub fn seed_product(conn: &Connection, id: i32, name: &str, sale_price: i64) {
+    conn.execute(
+        "INSERT INTO products (id, name, sale_price) VALUES (?1, ?2, ?3)",
+        rusqlite::params![id, name, sale_price],
+    )
+    .unwrap();
+}
+
+/// Ingreso de stock en la ubicación por defecto
+pub fn seed_stock(conn: &Connection, product_id: i32, quantity: i64) {
+    conn.execute(
+        "INSERT INTO stock_movements (product_id, type, quantity, location_id)
+         VALUES (?1, 'ingreso', ?2, (SELECT id FROM locations WHERE is_default=1))",
+        rusqlite::params![product_id, quantity],
+    )
+    .unwrap();
+}
+
+pub fn seed_customer(conn: &Connection, id: i32, name: &str) {
+    conn.execute("INSERT INTO customers (id, name) VALUES (?1, ?2)", rusqlite::params![id, name])
+        .unwrap();
+}
+
+/// Turno abierto en "Caja 1" para el usuario inicial. Devuelve el id del
+/// usuario, para registrar ventas y movimientos con `created_by`
+pub fn seed_session(conn: &Connection) -> i32 {
+    let user_id: i32 = conn.query_row("SELECT id FROM users LIMIT 1", [], |r| r.get(0)).unwrap();
+    open_session(conn, "Caja 1", Money::ZERO, Some(user_id)).unwrap();
+    user_id
+}
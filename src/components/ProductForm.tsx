@@ -16,6 +16,8 @@ interface ProductFormData {
   max_stock?: number;
   location?: string;
   status?: string;
+  default_bin_id?: number;
+  parent_id?: number;
 }
 
 interface ProductFormProps {
@@ -257,7 +259,7 @@ export default function ProductForm({ initialData, onSubmit, onCancel }: Product
         {/* Ubicación */}
         <div>
           <label className="block text-sm font-medium text-gray-700 mb-1">
-            Ubicación
+            Bin (zona-pasillo-estante)
           </label>
           <input
             type="text"
@@ -265,7 +267,7 @@ export default function ProductForm({ initialData, onSubmit, onCancel }: Product
             value={formData.location}
             onChange={handleChange}
             className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:ring-2 focus:ring-primary-500 focus:border-transparent"
-            placeholder="Ej: Estante A1"
+            placeholder="Ej: A-03-2"
           />
         </div>
 
@@ -22,6 +22,8 @@ interface Product {
   max_stock?: number;
   location?: string;
   status?: string;
+  default_bin_id?: number;
+  parent_id?: number;
   current_stock?: number;
 }
 
@@ -245,9 +247,11 @@ export default function Products() {
       }
     } catch (error) {
       console.error('❌ Error guardando producto:', error);
-      const msg = String(error || 'Error al guardar');
-      if (msg.includes('UNIQUE constraint failed: products.sku') || msg.toLowerCase().includes('sku ya existe')) {
-        alert('El SKU ya existe. Usa otro SKU o edita el producto existente.');
+      // El backend devuelve { code, message, ... } (ver src-tauri/src/error.rs)
+      const err = error as { code?: string; field?: string; message?: string } | string | null;
+      const msg = typeof err === 'string' ? err : err?.message || 'Error al guardar';
+      if (typeof err === 'object' && err?.code === 'CONFLICT' && err.field === 'sku') {
+        alert(msg);
       } else {
         alert(`❌ Error al guardar el producto:\n\n${msg}\n\nVerifica la consola para más detalles.`);
       }
@@ -13,8 +13,8 @@ export default function Reports() {
   const handleExportAll = async () => {
     try {
       if (typeof window !== 'undefined' && '__TAURI__' in window) {
-        const paths = await invoke<string[]>('export_all_reports');
-        alert(`✅ Reportes exportados en CSV:\n\n${paths.join('\n')}`);
+        const paths = await invoke<string[]>('export_all_reports', { format: 'xlsx' });
+        alert(`✅ Reportes exportados en Excel:\n\n${paths.join('\n')}`);
       } else {
         alert('⚠️ Ejecuta en modo Tauri para exportar reportes (npm run tauri:dev)');
       }
@@ -8,8 +8,28 @@ interface Sale {
   id?: number;
   product_id: number;
   quantity: number;
-  sale_price: number;
+  /** Precio de lista unitario (lo fija el backend) */
+  unit_price?: number;
+  /** Total antes del descuento */
+  gross_amount?: number;
+  /** Porcentaje de descuento (0-100) */
   discount?: number;
+  /** Importe descontado (promoción + descuento manual) */
+  discount_amount?: number;
+  /** Total neto cobrado (después del descuento) */
+  sale_price: number;
+  ticket_id?: number;
+  /** Promoción aplicada por el backend */
+  promotion_id?: number;
+  promotion_amount?: number;
+  /** Cliente registrado (vacío = mostrador) */
+  customer_id?: number;
+  /** Parte del descuento pagada con puntos */
+  points_amount?: number;
+  /** Venta al crédito (fiado); requiere cliente */
+  on_account?: boolean;
+  /** Formas de pago al registrar (efectivo, tarjeta, transferencia, billetera, credito, puntos) */
+  payments?: { method: string; amount: number; reference?: string }[];
   channel?: string;
   sale_date?: string;
   created_by?: number;
@@ -28,13 +48,34 @@ interface CashMovement {
   category?: string;
   description?: string;
   movement_date?: string;
+  /** Forma de pago (por defecto efectivo) */
+  payment_method?: string;
   created_by?: number;
+  /** Categoría de gasto; `category` lleva su nombre */
+  category_id?: number;
+  /** 'confirmado' | 'pendiente' (gastos recurrentes sin pagar) */
+  status?: string;
 }
 
 interface CashSummary {
   total_income: number;
   total_expense: number;
   balance: number;
+  /** Neto por forma de pago y efectivo esperado en caja */
+  by_method?: { method: string; sales: number; other_income: number; expense: number; net: number }[];
+  cash_in_drawer?: number;
+  /** Ingresos y egresos por día, semana o mes */
+  buckets?: { period_start: string; total_income: number; total_expense: number; balance: number }[];
+  /** Período anterior equivalente, si se filtró por fechas */
+  previous?: {
+    start_date: string;
+    end_date: string;
+    total_income: number;
+    total_expense: number;
+    balance: number;
+    balance_change: number;
+    balance_change_pct: number | null;
+  } | null;
 }
 
 /**
@@ -123,14 +164,10 @@ export default function Sales() {
     const discPct = Math.max(0, Math.min(100, Number(form.discount) || 0));
     try {
       if (typeof window !== 'undefined' && '__TAURI__' in window) {
-        const unit = Number(form.sale_price) || 0;
-        const qty = Number(form.quantity) || 0;
-        const subtotal = unit * qty;
-        const total = Math.max(0, Math.round(subtotal * (1 - discPct / 100)));
+        // Los importes (bruto, descuento y neto) los calcula el backend con el precio de lista
         const payload = {
           product_id: form.product_id,
           quantity: form.quantity,
-          sale_price: total,
           discount: discPct,
           channel: form.channel,
           sale_date: new Date().toISOString(),
@@ -322,7 +359,7 @@ export default function Sales() {
             </div>
             <div>
               <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-1">Precio x Unidad</label>
-              <input type="number" step="0.01" name="sale_price" value={form.sale_price} onChange={handleChange} className="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg" />
+              <input type="number" step="0.01" name="sale_price" value={form.sale_price} readOnly className="w-full px-3 py-2 border border-gray-300 dark:border-gray-600 rounded-lg bg-gray-50 dark:bg-gray-900/30" />
             </div>
             <div>
               <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-1">Descuento (%)</label>
@@ -353,7 +390,7 @@ export default function Sales() {
                 const qty = Number(form.quantity) || 0;
                 const subtotal = unit * qty;
                 const disc = Math.max(0, Math.min(100, Number(form.discount) || 0));
-                const total = Math.max(0, Math.round(subtotal * (1 - disc / 100)));
+                const total = Math.max(0, Math.round((subtotal - subtotal * disc / 100) * 100) / 100);
                 return `$${total.toLocaleString()}`;
               })()}</span>
             </div>